@@ -0,0 +1,138 @@
+use reqwest::header::{HeaderMap, HeaderName};
+
+/// A check on the response to an item's request, made before any of its body is written.
+///
+/// A response that fails one fails the item with a permanent error naming the violation,
+/// instead of storing, say, an HTML error page served with `200 OK` under the name of an
+/// archive. Assertions apply to the response at the end of any redirects.
+///
+/// ```rust
+/// use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+/// use robust_downloader::{DownloadItem, ResponseAssertion};
+///
+/// let item = DownloadItem::builder()
+///   .url("https://example.com/tool.tar.gz")
+///   .target("tool.tar.gz")
+///   .assertions(vec![
+///     ResponseAssertion::Present(CONTENT_LENGTH),
+///     ResponseAssertion::Excludes(TRANSFER_ENCODING, "chunked".to_string()),
+///     ResponseAssertion::OneOf(
+///       CONTENT_TYPE,
+///       vec!["application/gzip".to_string(), "application/x-*".to_string()],
+///     ),
+///   ])
+///   .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseAssertion {
+  /// The header is present.
+  Present(HeaderName),
+  /// The header is absent.
+  Absent(HeaderName),
+  /// None of the header's comma-separated values is this one, ignoring case, e.g.
+  /// `Transfer-Encoding` is not `chunked`. An absent header passes.
+  Excludes(HeaderName, String),
+  /// The header is present and its value, without parameters after a `;`, matches one of
+  /// these patterns, ignoring case. A pattern ending in `*` matches any value starting with
+  /// what comes before it, e.g. `image/*` for a `Content-Type`.
+  OneOf(HeaderName, Vec<String>),
+}
+
+impl ResponseAssertion {
+  /// Checks the response's `headers`, describing the violation if there is one.
+  pub(crate) fn check(&self, headers: &HeaderMap) -> Result<(), String> {
+    let values = |name: &HeaderName| {
+      headers
+        .get_all(name)
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).trim().to_string())
+        .collect::<Vec<_>>()
+    };
+
+    match self {
+      Self::Present(name) if !headers.contains_key(name) => Err(format!("no {name} header")),
+      Self::Absent(name) if headers.contains_key(name) => Err(format!(
+        "unexpected {name} header: {}",
+        values(name).join(", ")
+      )),
+      Self::Excludes(name, excluded) => {
+        let listed = values(name)
+          .iter()
+          .flat_map(|value| value.split(',').map(|token| token.trim().to_string()))
+          .any(|token| token.eq_ignore_ascii_case(excluded));
+        if listed {
+          return Err(format!("{name} lists {excluded}"));
+        }
+        Ok(())
+      }
+      Self::OneOf(name, patterns) => {
+        let Some(value) = headers.get(name) else {
+          return Err(format!("no {name} header"));
+        };
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let value = value.split(';').next().unwrap_or_default().trim();
+        let matched = patterns
+          .iter()
+          .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => value
+              .get(..prefix.len())
+              .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+            None => value.eq_ignore_ascii_case(pattern),
+          });
+        if !matched {
+          return Err(format!("{name} is {value}, expected one of {patterns:?}"));
+        }
+        Ok(())
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+
+  use super::*;
+
+  #[test]
+  fn test_check() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "Text/HTML; charset=utf-8".parse().unwrap());
+    headers.insert(TRANSFER_ENCODING, "gzip, Chunked".parse().unwrap());
+    let check = |assertion: ResponseAssertion| assertion.check(&headers);
+    let one_of = |patterns: &[&str]| {
+      ResponseAssertion::OneOf(
+        CONTENT_TYPE,
+        patterns.iter().map(ToString::to_string).collect(),
+      )
+    };
+
+    assert!(check(ResponseAssertion::Present(CONTENT_TYPE)).is_ok());
+    assert_eq!(
+      check(ResponseAssertion::Present(CONTENT_LENGTH)),
+      Err("no content-length header".to_string())
+    );
+    assert!(check(ResponseAssertion::Absent(CONTENT_LENGTH)).is_ok());
+    assert!(
+      check(ResponseAssertion::Excludes(
+        TRANSFER_ENCODING,
+        "chunked".to_string()
+      ))
+      .is_err()
+    );
+    assert!(
+      check(ResponseAssertion::Excludes(
+        TRANSFER_ENCODING,
+        "br".to_string()
+      ))
+      .is_ok()
+    );
+    assert!(check(one_of(&["text/html"])).is_ok());
+    assert!(check(one_of(&["application/gzip", "text/*"])).is_ok());
+    assert_eq!(
+      check(one_of(&["application/*"])),
+      Err("content-type is Text/HTML, expected one of [\"application/*\"]".to_string())
+    );
+  }
+}
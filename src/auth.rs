@@ -0,0 +1,76 @@
+use std::fmt;
+
+/// Credentials sent in the `Authorization` header.
+///
+/// They go with every request for an item at its original origin, including retries,
+/// resumed requests and same-origin redirect hops. Hops that leave the origin never get
+/// them, whatever the [`RedirectPolicy`](crate::RedirectPolicy) keeps of the item's own
+/// headers, so a token for an API does not travel to the CDN it redirects to.
+///
+/// ```rust
+/// use robust_downloader::{Auth, DownloadItem, RobustDownloader};
+///
+/// let downloader = RobustDownloader::builder()
+///   .auth(Auth::Bearer("ghp_example".to_string()))
+///   .build();
+/// // One item from another host with its own credentials.
+/// let item = DownloadItem::builder()
+///   .url("https://nexus.example.com/repository/raw/tool.tar.gz")
+///   .target("tool.tar.gz")
+///   .auth(Auth::Basic {
+///     user: "ci".to_string(),
+///     pass: "secret".to_string(),
+///   })
+///   .build();
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub enum Auth {
+  /// `Authorization: Bearer <token>`.
+  Bearer(String),
+  /// `Authorization: Basic <base64 of user:pass>`.
+  Basic { user: String, pass: String },
+}
+
+impl Auth {
+  pub(crate) fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match self {
+      Auth::Bearer(token) => request.bearer_auth(token),
+      Auth::Basic { user, pass } => request.basic_auth(user, Some(pass)),
+    }
+  }
+}
+
+impl fmt::Debug for Auth {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Auth::Bearer(_) => f.debug_tuple("Bearer").finish_non_exhaustive(),
+      Auth::Basic { user, .. } => f
+        .debug_struct("Basic")
+        .field("user", user)
+        .finish_non_exhaustive(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header::AUTHORIZATION;
+
+  use super::*;
+
+  #[test]
+  fn test_basic_header_and_redacted_debug() {
+    let auth = Auth::Basic {
+      user: "ci".to_string(),
+      pass: "secret".to_string(),
+    };
+    let request = auth
+      .apply(reqwest::Client::new().get("https://example.com/file"))
+      .build()
+      .unwrap();
+
+    assert_eq!(request.headers()[AUTHORIZATION], "Basic Y2k6c2VjcmV0");
+    assert!(request.headers()[AUTHORIZATION].is_sensitive());
+    assert!(!format!("{:?}", auth).contains("secret"));
+  }
+}
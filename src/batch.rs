@@ -0,0 +1,29 @@
+/// When a failed item makes [`download`](crate::RobustDownloader::download) give up on
+/// the rest of its batch.
+///
+/// An aborted batch returns the failure that aborted it; items still running are stopped
+/// at their next `.await` point and their partial files handled per the
+/// [`CleanupPolicy`](crate::CleanupPolicy), as if the download future had been dropped.
+/// [`download_all`](crate::RobustDownloader::download_all) always runs every item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+  /// Abort on the first failed item.
+  #[default]
+  FailFast,
+  /// Run every item, then return the first failure in input order, if any.
+  ContinueAll,
+  /// Abort once this many items have failed; earlier failures are reported after the
+  /// batch like with [`ContinueAll`](Self::ContinueAll). `FailAfter(1)` is `FailFast`.
+  FailAfter(usize),
+}
+
+impl ErrorPolicy {
+  /// Whether the batch is aborted once `failures` items have failed.
+  pub(crate) fn aborts(self, failures: usize) -> bool {
+    match self {
+      ErrorPolicy::FailFast => true,
+      ErrorPolicy::ContinueAll => false,
+      ErrorPolicy::FailAfter(limit) => failures >= limit,
+    }
+  }
+}
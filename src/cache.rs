@@ -0,0 +1,239 @@
+use std::{
+  collections::HashMap,
+  io,
+  path::{Path, PathBuf},
+  sync::{
+    Mutex,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::UNIX_EPOCH,
+};
+
+use hashery::Hashery;
+
+use crate::integrity::Integrity;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheEntry {
+  size: u64,
+  modified: u128,
+  digest: String,
+}
+
+/// A small on-disk cache of file digests keyed by `(path, algorithm)`.
+///
+/// An entry is only trusted while the file's size and modification time are unchanged,
+/// so repeated verification runs over thousands of files only re-hash what changed.
+///
+/// The cache file is a plain tab-separated text file; it is read by [`DigestCache::open`]
+/// and written back by [`DigestCache::save`]. Given to a downloader as its `digest_cache`,
+/// it spares re-hashing targets that are checked with `skip_valid_targets`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use robust_downloader::{DigestCache, Integrity};
+///
+/// # #[cfg(feature = "sha2")]
+/// # async fn example() -> std::io::Result<()> {
+/// let cache = DigestCache::open(".digests").await?;
+/// let digest = cache
+///   .digest("local/file.zip", &Integrity::SHA256(String::new()))
+///   .await?;
+/// cache.save().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct DigestCache {
+  path: Option<PathBuf>,
+  entries: Mutex<HashMap<(PathBuf, &'static str), CacheEntry>>,
+  dirty: AtomicBool,
+}
+
+impl DigestCache {
+  /// Creates a cache that lives only as long as this value.
+  pub fn in_memory() -> Self {
+    Self::default()
+  }
+
+  /// Loads the cache stored at `path`. A missing file yields an empty cache.
+  pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+    let path = path.as_ref().to_path_buf();
+
+    let content = match tokio::fs::read_to_string(&path).await {
+      Ok(content) => content,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+      Err(e) => return Err(e),
+    };
+
+    let entries = content.lines().filter_map(parse_line).collect();
+
+    Ok(Self {
+      path: Some(path),
+      entries: Mutex::new(entries),
+      dirty: AtomicBool::new(false),
+    })
+  }
+
+  /// Returns the digest of `file` for the algorithm of `integrity`, hashing the file only
+  /// if it changed since the digest was cached.
+  pub async fn digest(&self, file: impl AsRef<Path>, integrity: &Integrity) -> io::Result<String> {
+    let file = file.as_ref();
+    let (size, modified) = fingerprint(file).await?;
+    let key = (file.to_path_buf(), integrity.algorithm_name());
+
+    let cached = self.lock().get(&key).cloned();
+    if let Some(entry) = cached.filter(|e| e.size == size && e.modified == modified) {
+      return Ok(entry.digest);
+    }
+
+    let digest = Hashery::builder()
+      .algorithm(integrity.algorithm())
+      .build()
+      .digest(file)
+      .await?;
+
+    self.insert(key, size, modified, digest.clone());
+    Ok(digest)
+  }
+
+  /// Caches `digest` as the digest of `file` as it is now, for a file whose digest is
+  /// already known, e.g. a download verified while it was written.
+  pub(crate) async fn record(
+    &self,
+    file: &Path,
+    integrity: &Integrity,
+    digest: &str,
+  ) -> io::Result<()> {
+    let (size, modified) = fingerprint(file).await?;
+    let key = (file.to_path_buf(), integrity.algorithm_name());
+    self.insert(key, size, modified, digest.to_string());
+    Ok(())
+  }
+
+  fn insert(&self, key: (PathBuf, &'static str), size: u64, modified: u128, digest: String) {
+    self.lock().insert(
+      key,
+      CacheEntry {
+        size,
+        modified,
+        digest,
+      },
+    );
+    self.dirty.store(true, Ordering::Relaxed);
+  }
+
+  /// Writes the cache back to the file it was opened from, if anything changed.
+  ///
+  /// Paths that are not valid UTF-8 or contain tabs or newlines are only cached in memory.
+  pub async fn save(&self) -> io::Result<()> {
+    let Some(path) = &self.path else {
+      return Ok(());
+    };
+
+    if !self.dirty.swap(false, Ordering::Relaxed) {
+      return Ok(());
+    }
+
+    let content = self
+      .lock()
+      .iter()
+      .filter_map(|((file, algorithm), entry)| {
+        let file = file.to_str().filter(|f| !f.contains(['\t', '\n']))?;
+        Some(format!(
+          "{}\t{}\t{}\t{}\t{}\n",
+          algorithm, entry.size, entry.modified, entry.digest, file
+        ))
+      })
+      .collect::<String>();
+
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(&tmp, path).await
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(PathBuf, &'static str), CacheEntry>> {
+    self.entries.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+async fn fingerprint(file: &Path) -> io::Result<(u64, u128)> {
+  let metadata = tokio::fs::metadata(file).await?;
+  let modified = metadata
+    .modified()?
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  Ok((metadata.len(), modified))
+}
+
+fn parse_line(line: &str) -> Option<((PathBuf, &'static str), CacheEntry)> {
+  let mut fields = line.splitn(5, '\t');
+  let algorithm = known_algorithm(fields.next()?)?;
+  let size = fields.next()?.parse().ok()?;
+  let modified = fields.next()?.parse().ok()?;
+  let digest = fields.next()?.to_string();
+  let file = PathBuf::from(fields.next()?);
+  Some((
+    (file, algorithm),
+    CacheEntry {
+      size,
+      modified,
+      digest,
+    },
+  ))
+}
+
+/// Maps a stored algorithm name back to the `'static` name used as key.
+fn known_algorithm(name: &str) -> Option<&'static str> {
+  [
+    "md5", "sha1", "sha256", "sha512", "sha3-256", "blake2b", "blake2s", "blake3",
+  ]
+  .into_iter()
+  .find(|known| *known == name)
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_digest_is_cached_until_file_changes() {
+    let dir = std::env::temp_dir().join(format!("rd-digest-cache-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let file = dir.join("data.bin");
+    tokio::fs::write(&file, b"hello").await.unwrap();
+
+    let integrity = Integrity::SHA256(String::new());
+    let cache = DigestCache::open(dir.join("cache.tsv")).await.unwrap();
+    let first = cache.digest(&file, &integrity).await.unwrap();
+    assert_eq!(
+      first,
+      "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+    cache.save().await.unwrap();
+
+    let reopened = DigestCache::open(dir.join("cache.tsv")).await.unwrap();
+    assert_eq!(reopened.lock().len(), 1);
+
+    tokio::fs::write(&file, b"hello world").await.unwrap();
+    let second = reopened.digest(&file, &integrity).await.unwrap();
+    assert_ne!(first, second);
+
+    // A recorded digest is trusted as long as the file is unchanged.
+    reopened
+      .record(&file, &integrity, "recorded")
+      .await
+      .unwrap();
+    assert_eq!(
+      reopened.digest(&file, &integrity).await.unwrap(),
+      "recorded"
+    );
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
@@ -0,0 +1,113 @@
+use std::{
+  collections::HashMap,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+  },
+};
+
+/// Cancels a whole [`download_with_cancel`](crate::RobustDownloader::download_with_cancel)
+/// call from another task. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+  cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Requests cancellation; running items stop at their next chunk.
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+  }
+}
+
+/// The tags of a registered item and its cancellation flag.
+#[derive(Debug)]
+struct Entry {
+  tags: Vec<String>,
+  flag: Arc<AtomicBool>,
+}
+
+/// Tracks the items currently queued or running so they can be cancelled by tag.
+#[derive(Debug, Default)]
+pub(crate) struct CancelRegistry {
+  next_id: AtomicU64,
+  entries: Mutex<HashMap<u64, Entry>>,
+}
+
+impl CancelRegistry {
+  /// Registers an item until the returned registration is dropped.
+  pub fn register(&self, tags: &[String]) -> Registration<'_> {
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+    let flag = Arc::new(AtomicBool::new(false));
+    let entry = Entry {
+      tags: tags.to_vec(),
+      flag: flag.clone(),
+    };
+    self.lock().insert(id, entry);
+    Registration {
+      registry: self,
+      id,
+      flag,
+    }
+  }
+
+  /// Flags every registered item carrying `tag`, returning how many were flagged.
+  pub fn cancel_tagged(&self, tag: &str) -> usize {
+    self
+      .lock()
+      .values()
+      .filter(|entry| entry.tags.iter().any(|t| t == tag))
+      .map(|entry| entry.flag.store(true, Ordering::Relaxed))
+      .count()
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Entry>> {
+    self.entries.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+#[derive(Debug)]
+pub(crate) struct Registration<'a> {
+  registry: &'a CancelRegistry,
+  id: u64,
+  flag: Arc<AtomicBool>,
+}
+
+impl Registration<'_> {
+  pub fn flag(&self) -> Arc<AtomicBool> {
+    self.flag.clone()
+  }
+}
+
+impl Drop for Registration<'_> {
+  fn drop(&mut self) {
+    self.registry.lock().remove(&self.id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cancel_tagged_only_flags_matching_items() {
+    let registry = CancelRegistry::default();
+    let optional = registry.register(&["optional".to_string()]);
+    let required = registry.register(&["required".to_string()]);
+
+    assert_eq!(registry.cancel_tagged("optional"), 1);
+    assert!(optional.flag().load(Ordering::Relaxed));
+    assert!(!required.flag().load(Ordering::Relaxed));
+
+    drop(optional);
+    assert_eq!(registry.cancel_tagged("optional"), 0);
+  }
+}
@@ -0,0 +1,72 @@
+use std::{io, path::PathBuf};
+
+use log::warn;
+
+use crate::staging;
+
+/// What to do with an item's partial temp file when it does not complete, either
+/// because it failed for good or because the download future was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CleanupPolicy {
+  /// Keep the partial file so the next run resumes from it.
+  #[default]
+  KeepPartial,
+  /// Delete the partial file.
+  RemovePartial,
+}
+
+/// Applies the [`CleanupPolicy`] unless the item completed.
+///
+/// Runs from `Drop`, so it also covers the download future being dropped mid-flight.
+/// Bytes still sitting in the write buffer at that point are discarded; what reached the
+/// file is always a contiguous prefix of the remote content, so resuming from it is safe.
+#[derive(Debug)]
+pub(crate) struct PartialGuard {
+  temp_file: PathBuf,
+  policy: CleanupPolicy,
+  completed: bool,
+}
+
+impl PartialGuard {
+  pub fn new(temp_file: PathBuf, policy: CleanupPolicy) -> Self {
+    Self {
+      temp_file,
+      policy,
+      completed: false,
+    }
+  }
+
+  /// Keeps the partial file whatever the policy, e.g. so a cancelled item can resume.
+  pub fn keep_partial(mut self) {
+    self.policy = CleanupPolicy::KeepPartial;
+  }
+
+  /// Marks the item as completed; dropping the guard then does nothing.
+  pub fn complete(mut self) {
+    self.completed = true;
+  }
+}
+
+impl Drop for PartialGuard {
+  fn drop(&mut self) {
+    if self.completed {
+      return;
+    }
+
+    if self.policy == CleanupPolicy::RemovePartial {
+      // `Drop` cannot await; these are single unlinks. The validator may not exist, and
+      // neither may the partial when the item failed before writing any byte.
+      let _ = std::fs::remove_file(staging::validator_file(&self.temp_file));
+      let failed = std::fs::remove_file(&self.temp_file)
+        .err()
+        .filter(|e| e.kind() != io::ErrorKind::NotFound);
+      if let Some(e) = failed {
+        warn!(
+          "failed to remove partial file {}: {}",
+          self.temp_file.display(),
+          e
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,74 @@
+use std::{
+  sync::Arc,
+  time::{Duration, Instant, SystemTime},
+};
+
+/// Where a downloader reads the current time from: retry budgets and backoff schedules,
+/// bandwidth limits, host pacing, throughput windows, attempt timings, progress cadence,
+/// cached DNS lookups, and the wall-clock time requests are signed with and `Retry-After`
+/// dates are compared to all use it.
+///
+/// Waits, i.e. backoff sleeps, pacing and bandwidth delays, and chunk and request timeouts,
+/// always run on tokio's timer. With [`TokioClock`] the readings follow that timer too, so
+/// a test can pause time with `tokio::time::pause` and advance it by minutes of retries
+/// without waiting for them.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use robust_downloader::{RobustDownloader, TokioClock};
+///
+/// let downloader = RobustDownloader::builder().clock(Arc::new(TokioClock)).build();
+/// ```
+pub trait Clock: Send + Sync + std::fmt::Debug {
+  fn now(&self) -> Instant;
+
+  /// Time passed since `earlier`, or zero if it is in the future.
+  fn elapsed(&self, earlier: Instant) -> Duration {
+    self.now().saturating_duration_since(earlier)
+  }
+
+  /// The wall-clock time, off the system's by as much as `now` is off the system's
+  /// monotonic clock, so it stands still and jumps along with `now`.
+  fn system_time(&self) -> SystemTime {
+    let (now, system) = (self.now(), Instant::now());
+    match now.checked_duration_since(system) {
+      Some(ahead) => SystemTime::now() + ahead,
+      None => SystemTime::now() - system.duration_since(now),
+    }
+  }
+}
+
+/// The system's monotonic clock. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Instant {
+    Instant::now()
+  }
+
+  fn system_time(&self) -> SystemTime {
+    SystemTime::now()
+  }
+}
+
+/// Tokio's clock, which stands still while time is paused and jumps when it is advanced.
+/// Outside of paused time it reads the same as [`SystemClock`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+  fn now(&self) -> Instant {
+    tokio::time::Instant::now().into_std()
+  }
+}
+
+/// A [`Clock`] as the `backoff` crate wants it, for the elapsed time of retry schedules.
+#[derive(Debug, Clone)]
+pub(crate) struct BackoffClock(pub Arc<dyn Clock>);
+
+impl backoff::Clock for BackoffClock {
+  fn now(&self) -> Instant {
+    self.0.now()
+  }
+}
@@ -0,0 +1,325 @@
+//! Detecting metered connections and battery power, for desktop updaters that should not
+//! burn a user's data plan or battery on large downloads.
+//!
+//! [`SystemConditions::detect`] asks the platform; [`ConservePolicy`] is a ready-made
+//! [`StartPolicy`] deferring large items while the conditions are unfavorable. Custom
+//! policies can call [`SystemConditions::detect`] themselves.
+//!
+//! | Platform | Metered connection | On battery |
+//! |---|---|---|
+//! | Linux | NetworkManager, through `busctl` | `/sys/class/power_supply` |
+//! | macOS | not detected | `pmset -g batt` |
+//! | Windows | connection cost, through PowerShell | `Win32_Battery`, through PowerShell |
+
+use std::{
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use typed_builder::TypedBuilder;
+
+use crate::{
+  clock::{Clock, SystemClock},
+  start::{StartCandidate, StartDecision, StartPolicy},
+};
+
+/// A snapshot of the conditions a download runs under. `None` means unknown: the platform
+/// does not tell, or asking it failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemConditions {
+  /// Whether the active connection is metered (mobile data, a tethered phone, a connection
+  /// the user marked as metered).
+  pub metered: Option<bool>,
+  /// Whether the machine runs on battery. Machines without a battery never do.
+  pub on_battery: Option<bool>,
+}
+
+impl SystemConditions {
+  /// Asks the platform for the current conditions.
+  pub async fn detect() -> Self {
+    let (metered, on_battery) = tokio::join!(platform::metered(), platform::on_battery());
+    Self {
+      metered,
+      on_battery,
+    }
+  }
+}
+
+/// Defers large items while the connection is metered and, optionally, while the machine
+/// runs on battery.
+///
+/// An item counts as large when its [`size_hint`](crate::DownloadItem::size_hint) reaches
+/// `large_size`; items of unknown size always start. Conditions are detected at most once
+/// per `recheck` interval, which is also how long a deferred item waits before it is
+/// considered again.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use robust_downloader::{RobustDownloader, conditions::ConservePolicy};
+///
+/// let policy = ConservePolicy::builder()
+///   .large_size(50 * 1024 * 1024)
+///   .defer_on_battery(true)
+///   .build();
+/// let downloader = RobustDownloader::builder()
+///   .start_policy(Arc::new(policy))
+///   .build();
+/// ```
+#[derive(Debug, TypedBuilder)]
+pub struct ConservePolicy {
+  /// Defaults to 100 MiB.
+  #[builder(default = 100 * 1024 * 1024)]
+  large_size: u64,
+  /// Defaults to false: only metered connections defer large items.
+  #[builder(default = false)]
+  defer_on_battery: bool,
+  /// Defaults to 5 minutes.
+  #[builder(default = Duration::from_secs(300))]
+  recheck: Duration,
+  /// Where the time since the last detection is read from. Defaults to [`SystemClock`].
+  #[builder(default = Arc::new(SystemClock))]
+  clock: Arc<dyn Clock>,
+  #[builder(default, setter(skip))]
+  detected: Mutex<Option<(Instant, SystemConditions)>>,
+}
+
+impl ConservePolicy {
+  async fn conditions(&self) -> SystemConditions {
+    let cached = *self.detected.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, conditions)) = cached.filter(|(at, _)| self.clock.elapsed(*at) < self.recheck) {
+      return conditions;
+    }
+
+    let conditions = SystemConditions::detect().await;
+    let detected = Some((self.clock.now(), conditions));
+    *self.detected.lock().unwrap_or_else(|e| e.into_inner()) = detected;
+    conditions
+  }
+}
+
+impl StartPolicy for ConservePolicy {
+  fn should_start<'a>(&'a self, item: StartCandidate<'a>) -> BoxFuture<'a, StartDecision> {
+    Box::pin(async move {
+      if item.size_hint.is_none_or(|size| size < self.large_size) {
+        return StartDecision::Start;
+      }
+
+      let conditions = self.conditions().await;
+      let unfavorable = conditions.metered == Some(true)
+        || (self.defer_on_battery && conditions.on_battery == Some(true));
+      if unfavorable {
+        StartDecision::Defer(self.recheck)
+      } else {
+        StartDecision::Start
+      }
+    })
+  }
+}
+
+/// Runs `program` and returns its standard output if it succeeded.
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+async fn output(program: &str, args: &[&str]) -> Option<String> {
+  let output = tokio::process::Command::new(program)
+    .args(args)
+    .kill_on_drop(true)
+    .output()
+    .await
+    .ok()?;
+  output
+    .status
+    .success()
+    .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+  use super::output;
+
+  pub async fn metered() -> Option<bool> {
+    // NMMetered: 1 yes, 2 no, 3 guessed yes, 4 guessed no.
+    let reply = output(
+      "busctl",
+      &[
+        "get-property",
+        "org.freedesktop.NetworkManager",
+        "/org/freedesktop/NetworkManager",
+        "org.freedesktop.NetworkManager",
+        "Metered",
+      ],
+    )
+    .await?;
+    match reply.strip_prefix("u ")? {
+      "1" | "3" => Some(true),
+      "2" | "4" => Some(false),
+      _ => None,
+    }
+  }
+
+  pub async fn on_battery() -> Option<bool> {
+    let mut supplies = tokio::fs::read_dir("/sys/class/power_supply").await.ok()?;
+    let (mut has_battery, mut external_power) = (false, false);
+
+    while let Ok(Some(supply)) = supplies.next_entry().await {
+      let read = |name: &str| {
+        let path = supply.path().join(name);
+        async move {
+          tokio::fs::read_to_string(path)
+            .await
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+        }
+      };
+      // Batteries of wireless mice and the like report the `Device` scope.
+      if read("scope").await == "Device" {
+        continue;
+      }
+      if read("type").await == "Battery" {
+        has_battery = true;
+      } else if read("online").await == "1" {
+        external_power = true;
+      }
+    }
+
+    Some(has_battery && !external_power)
+  }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+  use super::output;
+
+  pub async fn metered() -> Option<bool> {
+    None
+  }
+
+  pub async fn on_battery() -> Option<bool> {
+    let report = output("pmset", &["-g", "batt"]).await?;
+    if report.contains("'Battery Power'") {
+      Some(true)
+    } else if report.contains("'AC Power'") {
+      Some(false)
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(windows)]
+mod platform {
+  use super::output;
+
+  async fn powershell(command: &str) -> Option<String> {
+    output(
+      "powershell",
+      &["-NoProfile", "-NonInteractive", "-Command", command],
+    )
+    .await
+  }
+
+  pub async fn metered() -> Option<bool> {
+    let cost = powershell(
+      "[Windows.Networking.Connectivity.NetworkInformation, Windows.Networking.Connectivity, \
+       ContentType = WindowsRuntime]::GetInternetConnectionProfile().GetConnectionCost().NetworkCostType",
+    )
+    .await?;
+    match cost.as_str() {
+      "Unrestricted" => Some(false),
+      "Fixed" | "Variable" => Some(true),
+      _ => None,
+    }
+  }
+
+  pub async fn on_battery() -> Option<bool> {
+    // BatteryStatus 1 means discharging; no output means there is no battery.
+    let status = powershell("(Get-CimInstance -ClassName Win32_Battery).BatteryStatus").await?;
+    Some(status.lines().any(|line| line.trim() == "1"))
+  }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+  pub async fn metered() -> Option<bool> {
+    None
+  }
+
+  pub async fn on_battery() -> Option<bool> {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  };
+
+  use super::*;
+  use crate::{
+    CancellationToken, DownloadItem, RobustDownloader,
+    err::ProgressDownloadError,
+    test_server::{self, Response},
+  };
+
+  #[tokio::test]
+  async fn test_large_items_wait_while_metered() {
+    let large_requests = Arc::new(AtomicUsize::new(0));
+    let counted = large_requests.clone();
+    let base = test_server::serve(move |request| {
+      if request.path == "/large.bin" {
+        counted.fetch_add(1, Ordering::Relaxed);
+      }
+      Response::ok("data")
+    })
+    .await;
+    let dir = std::env::temp_dir().join(format!("rd-conserve-{}", std::process::id()));
+    let item = |name: &str, size_hint: Option<u64>| {
+      let item = DownloadItem::builder()
+        .url(format!("{base}/{name}"))
+        .target(dir.join(name));
+      match size_hint {
+        Some(size) => item.size_hint(size).build(),
+        None => item.build(),
+      }
+    };
+
+    let policy = ConservePolicy::builder()
+      .large_size(1024)
+      .recheck(Duration::from_millis(300))
+      .build();
+    // Pretend the connection was just found to be metered.
+    let metered = SystemConditions {
+      metered: Some(true),
+      on_battery: None,
+    };
+    *policy.detected.lock().unwrap() = Some((Instant::now(), metered));
+
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_millis(150)).await;
+      cancel.cancel();
+    });
+
+    let result = RobustDownloader::builder()
+      .quiet(true)
+      .start_policy(Arc::new(policy))
+      .build()
+      .download_with_cancel(
+        vec![
+          item("large.bin", Some(4096)),
+          item("small.bin", Some(4)),
+          item("unknown.bin", None),
+        ],
+        token,
+      )
+      .await;
+
+    assert!(matches!(result, Err(ProgressDownloadError::Cancelled)));
+    assert_eq!(large_requests.load(Ordering::Relaxed), 0);
+    assert!(dir.join("small.bin").exists());
+    assert!(dir.join("unknown.bin").exists());
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
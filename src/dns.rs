@@ -0,0 +1,86 @@
+use std::{
+  collections::HashMap,
+  io,
+  net::SocketAddr,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use log::debug;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::clock::Clock;
+
+/// Addresses of a host, as resolved at `resolved_at`.
+#[derive(Debug, Clone)]
+struct CacheEntry {
+  resolved_at: Instant,
+  addrs: Vec<SocketAddr>,
+}
+
+/// A resolver that remembers lookups for `ttl`, shared by every request of a batch.
+///
+/// Downloading hundreds of files from the same few hosts otherwise pays the resolver's
+/// latency again for each new connection.
+#[derive(Debug, Clone)]
+pub(crate) struct CachingResolver {
+  ttl: Duration,
+  clock: Arc<dyn Clock>,
+  cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl CachingResolver {
+  pub fn new(ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+    Self {
+      ttl,
+      clock,
+      cache: Arc::default(),
+    }
+  }
+
+  /// Resolves all `hosts` concurrently so the first request to each finds a warm cache.
+  /// Failures are left for the actual request to report.
+  pub async fn prefetch(&self, hosts: impl IntoIterator<Item = String>) {
+    let lookups = hosts.into_iter().map(|host| async move {
+      if let Err(e) = self.lookup(&host).await {
+        debug!("dns prefetch failed for {}: {}", host, e);
+      }
+    });
+    futures::future::join_all(lookups).await;
+  }
+
+  pub async fn lookup(&self, host: &str) -> io::Result<Vec<SocketAddr>> {
+    let cached = self
+      .lock()
+      .get(host)
+      .filter(|entry| self.clock.elapsed(entry.resolved_at) < self.ttl)
+      .map(|entry| entry.addrs.clone());
+    if let Some(addrs) = cached {
+      return Ok(addrs);
+    }
+
+    // The port is replaced by the connector, only the addresses matter.
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+    let entry = CacheEntry {
+      resolved_at: self.clock.now(),
+      addrs: addrs.clone(),
+    };
+    self.lock().insert(host.to_string(), entry);
+
+    Ok(addrs)
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CacheEntry>> {
+    self.cache.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl Resolve for CachingResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let resolver = self.clone();
+    Box::pin(async move {
+      let addrs = resolver.lookup(name.as_str()).await?;
+      Ok(Box::new(addrs.into_iter()) as Addrs)
+    })
+  }
+}
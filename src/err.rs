@@ -1,7 +1,9 @@
 use log::debug;
-use std::path::PathBuf;
+use std::{any::Any, backtrace::Backtrace, path::PathBuf, time::Duration};
 use thiserror::Error;
 
+use crate::{observer::TaskState, report::VerificationSource};
+
 #[derive(Debug, Error)]
 pub enum ProgressDownloadError {
   #[error("IO error: {0}")]
@@ -19,6 +21,96 @@ pub enum ProgressDownloadError {
   #[error("Path error: {path}")]
   Path { path: String },
 
+  #[error("Too many redirects: {url}")]
+  TooManyRedirects { url: String },
+
+  #[error("Download cancelled")]
+  Cancelled,
+
+  #[error("Missing ETag header for content-addressed download: {url}")]
+  MissingETag { url: String },
+
+  #[error("Invalid release asset spec: {spec}")]
+  InvalidReleaseSpec { spec: String },
+
+  #[error("Release asset not found: {repo}@{tag}:{name}")]
+  ReleaseAssetNotFound {
+    repo: String,
+    tag: String,
+    name: String,
+  },
+
+  #[error("Invalid OCI image reference: {reference}")]
+  InvalidOciReference { reference: String },
+
+  #[error("Unexpected OCI manifest or token response for {reference}")]
+  InvalidOciManifest { reference: String },
+
+  #[error("No manifest for platform {platform} in {reference}")]
+  OciPlatformNotFound { reference: String, platform: String },
+
+  #[error("Invalid hub repository spec: {spec}")]
+  InvalidHubRepo { spec: String },
+
+  #[error("Unexpected hub API response for {repo}")]
+  InvalidHubResponse { repo: String },
+
+  /// The remote file is smaller than the partial download of it, so it changed since the
+  /// partial was written.
+  #[error(
+    "Remote file changed: {url} is smaller than the {local_size} bytes already downloaded (remote size: {remote_size:?})"
+  )]
+  RemoteFileChanged {
+    url: String,
+    local_size: u64,
+    remote_size: Option<u64>,
+  },
+
+  #[error("Verified download requested without an integrity: {url}")]
+  MissingIntegrity { url: String },
+
+  /// An item's [`IntegrityFile`](crate::IntegrityFile) has no usable entry for any of the
+  /// names it was looked up under.
+  #[error("No checksum for {names:?} in {list}")]
+  ChecksumNotListed { list: String, names: Vec<String> },
+
+  /// A URL's content no longer matches the SHA-256 pinned by its first download, see
+  /// [`PinStore`](crate::PinStore).
+  #[error("{url} changed since it was pinned - pinned: {pinned}, actual: {actual}")]
+  PinMismatch {
+    url: String,
+    pinned: String,
+    actual: String,
+  },
+
+  /// The target already exists and the item's [`OverwritePolicy`](crate::OverwritePolicy)
+  /// is `Error`.
+  #[error("Target already exists: {path}")]
+  TargetExists { path: String },
+
+  /// The file name a response gave a [`target_is_dir`](crate::DownloadItem::target_is_dir)
+  /// item is taken and its [`OverwritePolicy`](crate::OverwritePolicy) is `Skip`. Reported
+  /// as [`DownloadOutcome::Skipped`](crate::DownloadOutcome::Skipped), not as a failure.
+  #[error("Target already exists, skipped: {path}")]
+  TargetSkipped { path: String },
+
+  #[error("Invalid URL list, line {line}: {reason}")]
+  InvalidUrlList { line: usize, reason: String },
+
+  #[error("Request signing failed: {message}")]
+  Signing { message: String },
+
+  /// An item's [`PgpSignature`](crate::PgpSignature) could not be read or does not match.
+  #[error("OpenPGP signature check of {url} failed: {message}")]
+  PgpSignature { url: String, message: String },
+
+  #[error("Chunk transform failed: {message}")]
+  Transform { message: String },
+
+  /// A task panicked; the panic was caught so it only fails its own item.
+  #[error("Internal error: {message}")]
+  Internal { message: String },
+
   #[error(
     "Integrity hash mismatch - expected: {expect}, actual: {actual} , actual_file:{actual_file} , target_file: {target_file}"
   )]
@@ -28,9 +120,120 @@ pub enum ProgressDownloadError {
     actual_file: PathBuf,
     target_file: PathBuf,
   },
+
+  /// The body did not match a checksum header the server sent along with it. Unlike an
+  /// [`IntegrityHash`](Self::IntegrityHash) mismatch this points at a broken transfer, so
+  /// it is retried.
+  #[error("Checksum header mismatch for {url} ({header:?}) - expected: {expect}, actual: {actual}")]
+  ChecksumHeaderMismatch {
+    url: String,
+    header: VerificationSource,
+    expect: String,
+    actual: String,
+  },
+
+  /// A batch needs more space than is available on a filesystem it writes to; `items`
+  /// lists what each item needs there, largest first.
+  #[error(
+    "Insufficient space on the filesystem of {}: {needed} bytes needed, {available} available ({})",
+    .dir.display(),
+    breakdown(.items)
+  )]
+  InsufficientSpace {
+    dir: PathBuf,
+    needed: u64,
+    available: u64,
+    items: Vec<(String, u64)>,
+  },
+
+  /// The remote file is not the [`expected_size`](crate::DownloadItem::expected_size) of
+  /// its item, or more bytes arrived than the response announced.
+  #[error("{url} is {actual} bytes, expected {expected}")]
+  SizeMismatch {
+    url: String,
+    expected: u64,
+    actual: u64,
+  },
+
+  /// The body ended before the expected size without an error. What arrived is kept and
+  /// the download resumed, so it is retried.
+  #[error("{url} ended after {received} of {expected} bytes")]
+  Truncated {
+    url: String,
+    expected: u64,
+    received: u64,
+  },
+
+  /// The response failed one of the item's
+  /// [`ResponseAssertion`](crate::ResponseAssertion)s.
+  #[error("Unexpected response from {url}: {violation}")]
+  ResponseAssertion { url: String, violation: String },
+
+  /// The download grew beyond the maximum size allowed for it.
+  #[error("{url} exceeds the maximum size of {max_size} bytes")]
+  TooLarge { url: String, max_size: u64 },
+
+  /// The server sent the whole file where a range of it was asked for.
+  #[error("{url} does not serve ranges")]
+  RangesNotSupported { url: String },
+
+  #[error("Unpacking {archive} failed: {message}")]
+  Unpack { archive: String, message: String },
+
+  /// The server answered 429 or 503 and said when to come back; the next attempt waits
+  /// that long instead of following the retry policy.
+  #[error("{url} answered {status}, retry after {wait:?}")]
+  RetryAfter {
+    url: String,
+    status: u16,
+    wait: Duration,
+  },
+
+  /// A network or disk error that ended a download attempt, with where in the download it
+  /// happened. Retried or not like the error it wraps.
+  #[error("{url} failed while {state:?} at byte {offset}: {source}")]
+  Attempt {
+    url: String,
+    /// How far the attempt had come.
+    state: TaskState,
+    /// The offset in the remote file the attempt had reached.
+    offset: u64,
+    #[source]
+    source: Box<ProgressDownloadError>,
+    /// Where the attempt was given up, captured as `RUST_BACKTRACE` asks for. Boxed to
+    /// keep the error small; see [`trace`](Self::trace).
+    trace: Box<Backtrace>,
+  },
+}
+
+/// The largest items of an [`InsufficientSpace`](ProgressDownloadError::InsufficientSpace)
+/// error, for its message.
+fn breakdown(items: &[(String, u64)]) -> String {
+  const SHOWN: usize = 5;
+  let mut shown = items
+    .iter()
+    .take(SHOWN)
+    .map(|(url, bytes)| format!("{url}: {bytes}"))
+    .collect::<Vec<_>>();
+  if items.len() > SHOWN {
+    shown.push(format!("{} more", items.len() - SHOWN));
+  }
+  shown.join(", ")
 }
 
 impl ProgressDownloadError {
+  /// Builds an [`Internal`](Self::Internal) error from a caught panic payload.
+  pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+      message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+      message.clone()
+    } else {
+      "task panicked".to_string()
+    };
+    Self::Internal { message }
+  }
+
   fn is_retry_error(&self, e: &reqwest::Error) -> bool {
     // 1. 超时相关
     e.is_timeout() ||  // 请求超时
@@ -52,7 +255,63 @@ impl ProgressDownloadError {
     e.is_body() // 响应体错误
   }
 
+  /// Adds where in a download attempt a network or disk error happened, see
+  /// [`Attempt`](Self::Attempt). Other errors already tell what went wrong and are kept.
+  pub(crate) fn in_attempt(self, url: &str, state: TaskState, offset: u64) -> Self {
+    match self {
+      Self::Io(_) | Self::Reqwest(_) | Self::Timeout(_) => Self::Attempt {
+        url: url.to_string(),
+        state,
+        offset,
+        source: Box::new(self),
+        trace: Box::new(Backtrace::capture()),
+      },
+      error => error,
+    }
+  }
+
+  /// Where the download attempt of an [`Attempt`](Self::Attempt) was given up. Empty
+  /// unless `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set.
+  pub fn trace(&self) -> Option<&Backtrace> {
+    match self {
+      Self::Attempt { trace, .. } => Some(trace),
+      _ => None,
+    }
+  }
+
+  /// The error an [`Attempt`](Self::Attempt) wraps, or this one.
+  pub fn root(&self) -> &Self {
+    match self {
+      Self::Attempt { source, .. } => source.root(),
+      error => error,
+    }
+  }
+
   pub fn into_backoff_err(self) -> backoff::Error<Self> {
+    if let Self::Attempt {
+      url,
+      state,
+      offset,
+      source,
+      trace,
+    } = self
+    {
+      let rewrap = |source| Self::Attempt {
+        url,
+        state,
+        offset,
+        source: Box::new(source),
+        trace,
+      };
+      return match (*source).into_backoff_err() {
+        backoff::Error::Permanent(error) => backoff::Error::Permanent(rewrap(error)),
+        backoff::Error::Transient { err, retry_after } => backoff::Error::Transient {
+          err: rewrap(err),
+          retry_after,
+        },
+      };
+    }
+
     match &self {
       Self::Io(err) => match err.kind() {
         // 1. 资源暂时不可用
@@ -69,7 +328,7 @@ impl ProgressDownloadError {
         std::io::ErrorKind::Other            // 其他未知错误（保守重试）
               => {
                 debug!("transient error: {:?}", self);
-                return backoff::Error::transient(self);
+                backoff::Error::transient(self)
               },
         // 其他都是永久性错误
         _ => backoff::Error::permanent(self),
@@ -83,6 +342,15 @@ impl ProgressDownloadError {
           backoff::Error::permanent(self)
         }
       }
+      Self::ChecksumHeaderMismatch { .. } | Self::Truncated { .. } => {
+        debug!("transient error: {:?}", self);
+        backoff::Error::transient(self)
+      }
+      Self::RetryAfter { wait, .. } => {
+        debug!("transient error: {:?}", self);
+        let wait = *wait;
+        backoff::Error::retry_after(self, wait)
+      }
       Self::Timeout(_) => {
         debug!("transient error: {:?}", self);
         backoff::Error::transient(self)
@@ -99,6 +367,105 @@ impl ProgressDownloadError {
         debug!("permanent error: {:?}", self);
         backoff::Error::permanent(self)
       }
+      Self::TooManyRedirects { .. } => {
+        debug!("permanent error: {:?}", self);
+        backoff::Error::permanent(self)
+      }
+      Self::Cancelled => {
+        debug!("permanent error: {:?}", self);
+        backoff::Error::permanent(self)
+      }
+      Self::MissingETag { .. } => {
+        debug!("permanent error: {:?}", self);
+        backoff::Error::permanent(self)
+      }
+      Self::InvalidReleaseSpec { .. }
+      | Self::ReleaseAssetNotFound { .. }
+      | Self::InvalidOciReference { .. }
+      | Self::InvalidOciManifest { .. }
+      | Self::OciPlatformNotFound { .. }
+      | Self::InvalidHubRepo { .. }
+      | Self::InvalidHubResponse { .. }
+      | Self::InvalidUrlList { .. }
+      | Self::TargetExists { .. }
+      | Self::TargetSkipped { .. }
+      | Self::InsufficientSpace { .. }
+      | Self::Unpack { .. }
+      | Self::SizeMismatch { .. }
+      | Self::TooLarge { .. }
+      | Self::ResponseAssertion { .. }
+      | Self::PinMismatch { .. }
+      | Self::ChecksumNotListed { .. }
+      | Self::Signing { .. }
+      | Self::PgpSignature { .. }
+      | Self::Transform { .. }
+      | Self::MissingIntegrity { .. }
+      | Self::RangesNotSupported { .. }
+      | Self::RemoteFileChanged { .. } => {
+        debug!("permanent error: {:?}", self);
+        backoff::Error::permanent(self)
+      }
+      Self::Internal { .. } => {
+        debug!("permanent error: {:?}", self);
+        backoff::Error::permanent(self)
+      }
+      // Classified by the error it wraps, above.
+      Self::Attempt { .. } => unreachable!(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::panic::AssertUnwindSafe;
+
+  use futures::FutureExt;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_panic_becomes_internal_error() {
+    let result = AssertUnwindSafe(async { panic!("hook exploded") })
+      .catch_unwind()
+      .await
+      .unwrap_or_else(|payload| Err::<(), _>(ProgressDownloadError::from_panic(payload)));
+
+    assert!(matches!(
+      result,
+      Err(ProgressDownloadError::Internal { message }) if message == "hook exploded"
+    ));
+  }
+
+  #[test]
+  fn test_attempt_context() {
+    let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+    let error = ProgressDownloadError::from(reset).in_attempt(
+      "https://example.com/a.bin",
+      TaskState::Streaming,
+      1024,
+    );
+    assert_eq!(
+      error.to_string(),
+      "https://example.com/a.bin failed while Streaming at byte 1024: IO error: connection reset"
+    );
+    assert!(matches!(error.root(), ProgressDownloadError::Io(_)));
+    assert!(std::error::Error::source(&error).is_some());
+    assert!(error.trace().is_some());
+
+    // Still retried like the error it wraps, with its context kept.
+    match error.into_backoff_err() {
+      backoff::Error::Transient { err, .. } => {
+        assert!(matches!(
+          err,
+          ProgressDownloadError::Attempt { offset: 1024, .. }
+        ));
+      }
+      backoff::Error::Permanent(err) => panic!("permanent: {err}"),
     }
+
+    // Errors that tell what went wrong are not wrapped.
+    let cancelled =
+      ProgressDownloadError::Cancelled.in_attempt("https://example.com/a.bin", TaskState::Done, 0);
+    assert!(matches!(cancelled, ProgressDownloadError::Cancelled));
   }
 }
@@ -0,0 +1,261 @@
+use std::{
+  collections::{BTreeMap, VecDeque},
+  path::Path,
+  sync::{Arc, Mutex},
+};
+
+use reqwest::IntoUrl;
+use tokio::sync::oneshot;
+
+use crate::{
+  RobustDownloader, err::ProgressDownloadError, item::DownloadItem, report::DownloadReport,
+};
+
+/// A named, weighted queue sharing its downloader's concurrency slots with other queues.
+///
+/// Queues created from the same downloader (or its clones) draw from one pool of
+/// `max_concurrent` slots. Whenever a slot frees up, it goes to the waiting queue with the
+/// fewest running downloads relative to its weight, so under contention a queue of weight 3
+/// runs three times as many downloads as one of weight 1, while an idle queue leaves its
+/// share to the others. Plain [`RobustDownloader::download`] calls keep their own slots.
+///
+/// ```rust,no_run
+/// use robust_downloader::{DownloadItem, RobustDownloader};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let downloader = RobustDownloader::builder().max_concurrent(8).build();
+/// let paying = downloader.queue("paying", 3);
+/// let free = downloader.queue("free", 1);
+///
+/// let item = |n: u32| {
+///   DownloadItem::builder()
+///     .url(format!("https://example.com/{n}.bin"))
+///     .target(format!("out/{n}.bin"))
+///     .build()
+/// };
+/// let (paying, free) = tokio::join!(
+///   paying.download((0..20).map(item).collect()),
+///   free.download((20..40).map(item).collect()),
+/// );
+/// # paying?; free?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DownloadQueue {
+  downloader: RobustDownloader,
+  name: Arc<str>,
+}
+
+impl DownloadQueue {
+  pub(crate) fn new(downloader: RobustDownloader, name: Arc<str>) -> Self {
+    Self { downloader, name }
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Downloads `downloads` like [`RobustDownloader::download`], taking slots from the
+  /// shared pool according to this queue's weight.
+  pub async fn download<U, P>(
+    &self,
+    downloads: Vec<DownloadItem<U, P>>,
+  ) -> Result<Vec<DownloadReport>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    self
+      .downloader
+      .download_in(Some(&self.name), downloads, None)
+      .await
+  }
+}
+
+/// Hands out a fixed number of slots to weighted queues.
+#[derive(Debug)]
+pub(crate) struct FairScheduler {
+  slots: usize,
+  state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+  running: usize,
+  // Ordered so that ties are broken the same way every time.
+  queues: BTreeMap<Arc<str>, QueueState>,
+}
+
+#[derive(Debug)]
+struct QueueState {
+  weight: u32,
+  running: usize,
+  waiters: VecDeque<oneshot::Sender<()>>,
+}
+
+impl QueueState {
+  fn new(weight: u32) -> Self {
+    Self {
+      weight: weight.max(1),
+      running: 0,
+      waiters: VecDeque::new(),
+    }
+  }
+}
+
+impl FairScheduler {
+  pub fn new(slots: usize) -> Self {
+    Self {
+      slots: slots.max(1),
+      state: Mutex::default(),
+    }
+  }
+
+  /// Registers `queue`, or updates its weight. Weights below 1 count as 1.
+  pub fn set_weight(&self, queue: &Arc<str>, weight: u32) {
+    let mut state = self.lock();
+    state
+      .queues
+      .entry(queue.clone())
+      .and_modify(|entry| entry.weight = weight.max(1))
+      .or_insert_with(|| QueueState::new(weight));
+  }
+
+  /// Waits for a slot on behalf of `queue`.
+  pub async fn acquire(self: &Arc<Self>, queue: &Arc<str>) -> SlotPermit {
+    let mut pending = PendingSlot {
+      scheduler: self.clone(),
+      queue: queue.clone(),
+      rx: self.enqueue(queue),
+      granted: false,
+    };
+    // The sender lives in the scheduler, which `pending` keeps alive.
+    let _ = (&mut pending.rx).await;
+    pending.granted = true;
+
+    SlotPermit {
+      scheduler: self.clone(),
+      queue: queue.clone(),
+    }
+  }
+
+  fn enqueue(&self, queue: &Arc<str>) -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+    let mut state = self.lock();
+    state
+      .queues
+      .entry(queue.clone())
+      .or_insert_with(|| QueueState::new(1))
+      .waiters
+      .push_back(tx);
+    self.dispatch(&mut state);
+    rx
+  }
+
+  fn release(&self, queue: &str) {
+    let mut state = self.lock();
+    state.running -= 1;
+    if let Some(entry) = state.queues.get_mut(queue) {
+      entry.running -= 1;
+    }
+    self.dispatch(&mut state);
+  }
+
+  /// Grants free slots to the waiting queues with the lowest `running / weight`.
+  fn dispatch(&self, state: &mut State) {
+    while state.running < self.slots {
+      let Some(entry) = state
+        .queues
+        .values_mut()
+        .filter(|entry| !entry.waiters.is_empty())
+        .min_by(|a, b| {
+          (a.running as u64 * u64::from(b.weight)).cmp(&(b.running as u64 * u64::from(a.weight)))
+        })
+      else {
+        return;
+      };
+
+      let waiter = entry
+        .waiters
+        .pop_front()
+        .expect("filtered on non-empty waiters");
+      // A waiter that gave up is skipped without taking the slot.
+      if waiter.send(()).is_ok() {
+        entry.running += 1;
+        state.running += 1;
+      }
+    }
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// A slot that is being waited for; gives the slot back if the wait is abandoned after
+/// the slot was granted but before it was picked up.
+struct PendingSlot {
+  scheduler: Arc<FairScheduler>,
+  queue: Arc<str>,
+  rx: oneshot::Receiver<()>,
+  granted: bool,
+}
+
+impl Drop for PendingSlot {
+  fn drop(&mut self) {
+    if self.granted {
+      return;
+    }
+    self.rx.close();
+    if self.rx.try_recv().is_ok() {
+      self.scheduler.release(&self.queue);
+    }
+  }
+}
+
+/// A slot held by one download, released on drop.
+#[derive(Debug)]
+pub(crate) struct SlotPermit {
+  scheduler: Arc<FairScheduler>,
+  queue: Arc<str>,
+}
+
+impl Drop for SlotPermit {
+  fn drop(&mut self) {
+    self.scheduler.release(&self.queue);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_slots_follow_weights() {
+    let scheduler = Arc::new(FairScheduler::new(4));
+    let (heavy, light, other): (Arc<str>, Arc<str>, Arc<str>) =
+      ("heavy".into(), "light".into(), "other".into());
+    scheduler.set_weight(&heavy, 3);
+    scheduler.set_weight(&light, 1);
+
+    let mut blockers = Vec::new();
+    for _ in 0..4 {
+      blockers.push(scheduler.acquire(&other).await);
+    }
+
+    let mut heavy_waiters: Vec<_> = (0..4).map(|_| scheduler.enqueue(&heavy)).collect();
+    let mut light_waiters: Vec<_> = (0..4).map(|_| scheduler.enqueue(&light)).collect();
+    drop(blockers);
+
+    let granted = |waiters: &mut Vec<oneshot::Receiver<()>>| {
+      waiters
+        .iter_mut()
+        .map(|rx| rx.try_recv().is_ok())
+        .filter(|granted| *granted)
+        .count()
+    };
+    assert_eq!(granted(&mut heavy_waiters), 3);
+    assert_eq!(granted(&mut light_waiters), 1);
+  }
+}
@@ -0,0 +1,193 @@
+use std::borrow::Cow;
+
+use cow_utils::CowUtils;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use typed_builder::TypedBuilder;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+
+/// Controls how file names taken from URLs are turned into local file names.
+///
+/// URLs carry names percent-encoded (`my%20file.tar.gz`) and some servers send them in
+/// NFD form, which looks identical but compares differently on most filesystems.
+/// Whatever the policy, path separators are never allowed to survive decoding.
+///
+/// # Example
+///
+/// ```rust
+/// use robust_downloader::FilenamePolicy;
+///
+/// let policy = FilenamePolicy::default();
+/// let url = "https://example.com/dist/my%20file.tar.gz".parse().unwrap();
+/// assert_eq!(
+///   policy.file_name_from_url(&url).as_deref(),
+///   Some("my file.tar.gz")
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TypedBuilder)]
+pub struct FilenamePolicy {
+  /// Decode `%XX` escapes. Defaults to true.
+  #[builder(default = true)]
+  pub percent_decode: bool,
+
+  /// Normalize the name to Unicode NFC. Defaults to true.
+  #[builder(default = true)]
+  pub normalize_nfc: bool,
+}
+
+impl Default for FilenamePolicy {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl FilenamePolicy {
+  /// Applies the policy to a raw file name.
+  pub fn apply<'a>(&self, raw: &'a str) -> Cow<'a, str> {
+    let mut name = if self.percent_decode {
+      percent_decode_str(raw).decode_utf8_lossy()
+    } else {
+      Cow::Borrowed(raw)
+    };
+
+    if self.normalize_nfc && !is_nfc(&name) {
+      name = Cow::Owned(name.nfc().collect());
+    }
+
+    if name.contains(['/', '\\']) {
+      name = Cow::Owned(
+        name
+          .chars()
+          .map(|c| if matches!(c, '/' | '\\') { '_' } else { c })
+          .collect(),
+      );
+    }
+
+    name
+  }
+
+  /// Derives a file name from the last non-empty path segment of `url`.
+  ///
+  /// Returns `None` when the URL has no usable segment (e.g. `https://example.com/`).
+  pub fn file_name_from_url(&self, url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.rev().find(|s| !s.is_empty())?;
+    usable(self.apply(segment))
+  }
+
+  /// Derives a file name from a `Content-Disposition` header value, preferring the
+  /// RFC 6266 `filename*=UTF-8''...` form over a plain `filename=`.
+  ///
+  /// Only the extended form is percent-decoded, whatever the policy. Returns `None` when
+  /// the header names no usable file.
+  pub fn file_name_from_disposition(&self, disposition: &str) -> Option<String> {
+    let mut plain = None;
+    let mut extended = None;
+    for param in disposition.split(';').skip(1) {
+      let Some((key, value)) = param.split_once('=') else {
+        continue;
+      };
+      match key.trim().cow_to_ascii_lowercase().as_ref() {
+        "filename" => plain = Some(value.trim().trim_matches('"')),
+        // `<charset>'<language>'<percent-encoded name>`
+        "filename*" => {
+          extended = value
+            .trim()
+            .split_once('\'')
+            .filter(|(charset, _)| charset.eq_ignore_ascii_case("utf-8"))
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map(|(_language, encoded)| encoded);
+        }
+        _ => {}
+      }
+    }
+
+    match (extended, plain) {
+      (Some(encoded), _) => usable(
+        FilenamePolicy {
+          percent_decode: true,
+          ..*self
+        }
+        .apply(encoded),
+      ),
+      (None, Some(name)) => usable(
+        FilenamePolicy {
+          percent_decode: false,
+          ..*self
+        }
+        .apply(name),
+      ),
+      (None, None) => None,
+    }
+  }
+}
+
+/// Rejects names that would not name a file inside the directory they are joined to.
+fn usable(name: Cow<'_, str>) -> Option<String> {
+  match name.as_ref() {
+    "" | "." | ".." => None,
+    _ => Some(name.into_owned()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_percent_decode_and_nfc() {
+    let policy = FilenamePolicy::default();
+    assert_eq!(policy.apply("my%20file.tar.gz"), "my file.tar.gz");
+    // "é" as `e` + combining acute accent
+    assert_eq!(policy.apply("caf%65%CC%81.txt"), "caf\u{e9}.txt");
+  }
+
+  #[test]
+  fn test_separators_are_neutralized() {
+    let policy = FilenamePolicy::default();
+    assert_eq!(policy.apply("..%2Fetc%2Fpasswd"), ".._etc_passwd");
+  }
+
+  #[test]
+  fn test_disabled_policy_keeps_raw_name() {
+    let policy = FilenamePolicy::builder()
+      .percent_decode(false)
+      .normalize_nfc(false)
+      .build();
+    assert_eq!(policy.apply("my%20file"), "my%20file");
+  }
+
+  #[test]
+  fn test_file_name_from_url() {
+    let policy = FilenamePolicy::default();
+    let url = Url::parse("https://example.com/a/b/").unwrap();
+    assert_eq!(policy.file_name_from_url(&url).as_deref(), Some("b"));
+    let url = Url::parse("https://example.com/").unwrap();
+    assert_eq!(policy.file_name_from_url(&url), None);
+  }
+
+  #[test]
+  fn test_file_name_from_disposition() {
+    let policy = FilenamePolicy::default();
+    let name = |header: &str| policy.file_name_from_disposition(header);
+
+    assert_eq!(
+      name("attachment; filename=\"a b.zip\"").as_deref(),
+      Some("a b.zip")
+    );
+    assert_eq!(
+      name("attachment; filename=\"100%25.txt\"").as_deref(),
+      Some("100%25.txt")
+    );
+    assert_eq!(
+      name("attachment; filename=\"fallback.txt\"; filename*=UTF-8''r%C3%A9sum%C3%A9.txt")
+        .as_deref(),
+      Some("r\u{e9}sum\u{e9}.txt")
+    );
+    assert_eq!(
+      name("attachment; filename=\"../../.bashrc\"").as_deref(),
+      Some(".._.._.bashrc")
+    );
+    assert_eq!(name("attachment; filename=\"..\""), None);
+    assert_eq!(name("inline"), None);
+  }
+}
@@ -0,0 +1,242 @@
+use std::{
+  collections::HashMap,
+  io,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+
+use reqwest::Url;
+use typed_builder::TypedBuilder;
+
+use crate::{
+  err::ProgressDownloadError,
+  observer::{ObservedItem, Progress, ProgressObserver, Retry},
+  report::{DownloadOutcome, DownloadReport},
+};
+
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+use crate::integrity::Integrity;
+
+/// Several downloads making up one file, e.g. the chunks of a split archive (see
+/// [`split_archive`](Self::split_archive)), downloaded with
+/// [`RobustDownloader::download_group`](crate::RobustDownloader::download_group).
+///
+/// The parts are downloaded like items of a batch, concurrently and each with its own
+/// retries, into a `<target>.parts` directory. Once all of them are complete they are
+/// joined in order, the joined file is verified and placed at `target`, and the parts are
+/// removed. Observers see the group as a single item, and it gets a single report.
+///
+/// ```rust,no_run
+/// use robust_downloader::{DownloadGroup, RobustDownloader};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let group = DownloadGroup::builder()
+///   .parts(vec![
+///     "https://example.com/dataset.tar.part-a",
+///     "https://example.com/dataset.tar.part-b",
+///   ])
+///   .target("local/dataset.tar")
+///   .build();
+/// let report = RobustDownloader::builder().build().download_group(group).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct DownloadGroup<U, P> {
+  /// The parts' URLs, in the order their content is joined.
+  pub parts: Vec<U>,
+  /// Where the joined file goes.
+  pub target: P,
+
+  /// Expected hash of the joined file. The parts themselves are not verified on their own.
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  #[builder(default = None, setter(strip_option))]
+  pub integrity: Option<Integrity>,
+
+  /// Tags of the group, given to each part and to the group's report.
+  #[builder(default)]
+  pub tags: Vec<String>,
+}
+
+impl<P> DownloadGroup<String, P> {
+  /// The group of a file split into numbered parts, `<base>.001` up to `<base>.<parts>`,
+  /// the way 7-Zip, HJSplit or `split -d --suffix-length=3 --numeric-suffixes=1` name them.
+  /// A query string stays at the end of every part's URL.
+  ///
+  /// ```rust
+  /// use robust_downloader::DownloadGroup;
+  ///
+  /// let base = "https://example.com/backup.7z?token=t".parse().unwrap();
+  /// let group = DownloadGroup::split_archive(&base, 12, "local/backup.7z");
+  /// assert_eq!(group.parts[0], "https://example.com/backup.7z.001?token=t");
+  /// assert_eq!(group.parts[11], "https://example.com/backup.7z.012?token=t");
+  /// ```
+  pub fn split_archive(base: &Url, parts: usize, target: P) -> Self {
+    let parts = (1..=parts)
+      .map(|number| {
+        let mut part = base.clone();
+        part.set_path(&format!("{}.{number:03}", base.path()));
+        part.to_string()
+      })
+      .collect();
+    DownloadGroup::builder().parts(parts).target(target).build()
+  }
+}
+
+/// Where the parts of a group targeting `target` are downloaded to.
+pub(crate) fn parts_dir(target: &Path) -> PathBuf {
+  let mut name = target.as_os_str().to_os_string();
+  name.push(".parts");
+  PathBuf::from(name)
+}
+
+/// Writes the content of `parts`, in order, to `joined`.
+pub(crate) async fn join(parts: &[PathBuf], joined: &Path) -> io::Result<()> {
+  let mut output = tokio::fs::File::create(joined).await?;
+  for part in parts {
+    let mut input = tokio::fs::File::open(part).await?;
+    tokio::io::copy(&mut input, &mut output).await?;
+  }
+  output.sync_all().await
+}
+
+/// Shows the parts of a group to the downloader's observer as the group itself: their
+/// progress is summed up, and the group reports its own start and end.
+///
+/// A part only adds to the total once its download started, so the total grows while
+/// parts are still waiting for a concurrency slot.
+#[derive(Debug)]
+pub(crate) struct GroupObserver {
+  observer: Arc<dyn ProgressObserver>,
+  item: ObservedItem,
+  parts: Mutex<HashMap<u64, Progress>>,
+}
+
+impl GroupObserver {
+  pub fn new(observer: Arc<dyn ProgressObserver>, item: ObservedItem) -> Self {
+    Self {
+      observer,
+      item,
+      parts: Mutex::default(),
+    }
+  }
+
+  pub fn start(&self) {
+    self.observer.on_start(&self.item);
+  }
+
+  pub fn finish(&self, result: &Result<DownloadReport, ProgressDownloadError>) {
+    match result {
+      Ok(report) => self.observer.on_complete(&self.item, report.outcome),
+      Err(error) => self.observer.on_error(&self.item, error),
+    }
+  }
+}
+
+impl ProgressObserver for GroupObserver {
+  fn on_chunk(&self, part: &ObservedItem, progress: Progress) {
+    let combined = {
+      let mut parts = self.parts.lock().unwrap_or_else(|e| e.into_inner());
+      parts.insert(part.id, progress);
+      parts.values().fold(
+        Progress {
+          downloaded: 0,
+          total: 0,
+          attempt: 1,
+          max_attempts: None,
+        },
+        |sum, part| Progress {
+          downloaded: sum.downloaded + part.downloaded,
+          total: sum.total + part.total,
+          attempt: sum.attempt.max(part.attempt),
+          max_attempts: sum.max_attempts.max(part.max_attempts),
+        },
+      )
+    };
+    self.observer.on_chunk(&self.item, combined);
+  }
+
+  fn on_retry(&self, _part: &ObservedItem, retry: &Retry<'_>) {
+    self.observer.on_retry(&self.item, retry);
+  }
+
+  // Parts starting, completing or failing is not news to the observer: the group reports
+  // its own start and end.
+}
+
+/// The single report of a group, made of the reports of its parts.
+pub(crate) fn report(
+  url: String,
+  target: PathBuf,
+  tags: Vec<String>,
+  parts: Vec<DownloadReport>,
+  elapsed: std::time::Duration,
+) -> DownloadReport {
+  DownloadReport {
+    index: 0,
+    url,
+    target,
+    outcome: DownloadOutcome::Downloaded,
+    tags,
+    attempts: parts.into_iter().flat_map(|part| part.attempts).collect(),
+    elapsed,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, Default)]
+  struct Recorder(Mutex<Vec<(u64, u64)>>);
+
+  impl ProgressObserver for Recorder {
+    fn on_chunk(&self, _item: &ObservedItem, progress: Progress) {
+      self
+        .0
+        .lock()
+        .unwrap()
+        .push((progress.downloaded, progress.total));
+    }
+  }
+
+  #[test]
+  fn test_parts_progress_is_summed() {
+    let recorder = Arc::new(Recorder::default());
+    let group = GroupObserver::new(
+      recorder.clone(),
+      ObservedItem::new(String::new(), PathBuf::new(), Vec::new()),
+    );
+    let a = ObservedItem::new(String::new(), PathBuf::new(), Vec::new());
+    let b = ObservedItem::new(String::new(), PathBuf::new(), Vec::new());
+    let progress = |downloaded, total| Progress {
+      downloaded,
+      total,
+      attempt: 1,
+      max_attempts: None,
+    };
+
+    group.on_chunk(&a, progress(10, 100));
+    group.on_chunk(&b, progress(5, 50));
+    group.on_chunk(&a, progress(40, 100));
+
+    assert_eq!(
+      *recorder.0.lock().unwrap(),
+      [(10, 100), (15, 150), (45, 150)]
+    );
+  }
+}
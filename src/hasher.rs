@@ -0,0 +1,120 @@
+use crate::integrity::Integrity;
+
+/// Hashes a download's bytes as they are written, so verifying it does not read the whole
+/// file back. Digests come out as lowercase hex, like hashery's.
+pub(crate) enum StreamHasher {
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2"
+  ))]
+  Digest(Box<dyn digest::DynDigest + Send>),
+  #[cfg(feature = "blake3")]
+  Blake3(Box<blake3::Hasher>),
+}
+
+/// The hashers of one attempt's body.
+#[derive(Default)]
+pub(crate) struct Hashers {
+  /// For the integrity the body is verified against.
+  pub integrity: Option<StreamHasher>,
+  /// For the SHA-256 a [`PinStore`](crate::PinStore) checks or pins.
+  pub pin: Option<StreamHasher>,
+}
+
+impl Hashers {
+  pub fn update(&mut self, bytes: &[u8]) {
+    for hasher in self.integrity.iter_mut().chain(self.pin.iter_mut()) {
+      hasher.update(bytes);
+    }
+  }
+}
+
+impl StreamHasher {
+  #[cfg(feature = "sha2")]
+  pub fn sha256() -> Self {
+    Self::Digest(Box::new(sha2::Sha256::default()))
+  }
+
+  /// Makes hashers for `algorithm`, e.g. a fresh one for every attempt.
+  pub fn maker(algorithm: hashery::Algorithm) -> fn() -> Self {
+    match algorithm {
+      #[cfg(feature = "md5")]
+      hashery::Algorithm::MD5 => || Self::Digest(Box::new(md5::Md5::default())),
+      #[cfg(feature = "sha1")]
+      hashery::Algorithm::SHA1 => || Self::Digest(Box::new(sha1::Sha1::default())),
+      #[cfg(feature = "sha2")]
+      hashery::Algorithm::SHA256 => Self::sha256,
+      #[cfg(feature = "sha2")]
+      hashery::Algorithm::SHA512 => || Self::Digest(Box::new(sha2::Sha512::default())),
+      #[cfg(feature = "sha3")]
+      hashery::Algorithm::SHA3_256 => || Self::Digest(Box::new(sha3::Sha3_256::default())),
+      #[cfg(feature = "blake2")]
+      hashery::Algorithm::Blake2b => || Self::Digest(Box::new(blake2::Blake2b512::default())),
+      #[cfg(feature = "blake2")]
+      hashery::Algorithm::Blake2s => || Self::Digest(Box::new(blake2::Blake2s256::default())),
+      #[cfg(feature = "blake3")]
+      hashery::Algorithm::Blake3 => || Self::Blake3(Box::default()),
+    }
+  }
+
+  /// A hasher for the algorithm of `integrity`.
+  pub fn new(integrity: &Integrity) -> Self {
+    Self::maker(integrity.algorithm())()
+  }
+
+  pub fn update(&mut self, bytes: &[u8]) {
+    match self {
+      #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha2",
+        feature = "sha3",
+        feature = "blake2"
+      ))]
+      Self::Digest(digest) => digest.update(bytes),
+      #[cfg(feature = "blake3")]
+      Self::Blake3(hasher) => {
+        hasher.update(bytes);
+      }
+    }
+  }
+
+  pub fn finish(self) -> String {
+    match self {
+      #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha2",
+        feature = "sha3",
+        feature = "blake2"
+      ))]
+      Self::Digest(digest) => digest
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect(),
+      #[cfg(feature = "blake3")]
+      Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+    }
+  }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_matches_one_shot_digest() {
+    let mut hasher = StreamHasher::sha256();
+    for chunk in [&b"a"[..], b"", b"bc"] {
+      hasher.update(chunk);
+    }
+    assert_eq!(
+      hasher.finish(),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  }
+}
@@ -0,0 +1,306 @@
+//! Expanding a Hugging Face Hub repository into [`DownloadItem`]s.
+//!
+//! The hub's tree API lists every file of a revision with its size; files stored in LFS (the
+//! weights, in practice) also carry their SHA-256, which becomes the item's [`Integrity`].
+//! Small files tracked by plain git only have a git blob id and are downloaded unverified.
+//! Interrupted downloads resume like any other item.
+//!
+//! ```rust,no_run
+//! use robust_downloader::{RobustDownloader, hub::{HUGGING_FACE, HubRepo}};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let repo: HubRepo = "openai-community/gpt2@main".parse()?;
+//! let files = repo.resolve(HUGGING_FACE, None, "models/gpt2").await?;
+//! let total: u64 = files.iter().map(|file| file.size).sum();
+//! println!("fetching {} files, {total} bytes", files.len());
+//!
+//! let items = files
+//!   .into_iter()
+//!   .filter(|file| !file.path.ends_with(".h5"))
+//!   .map(|file| file.item)
+//!   .collect();
+//! RobustDownloader::builder().build().download(items).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue, LINK};
+use serde::Deserialize;
+
+use crate::{err::ProgressDownloadError, integrity::Integrity, item::DownloadItem};
+
+/// The public Hugging Face Hub.
+pub const HUGGING_FACE: &str = "https://huggingface.co";
+
+/// What kind of repository a [`HubRepo`] names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HubRepoKind {
+  Model,
+  Dataset,
+  Space,
+}
+
+impl HubRepoKind {
+  fn prefix(self) -> &'static str {
+    match self {
+      Self::Model => "",
+      Self::Dataset => "datasets/",
+      Self::Space => "spaces/",
+    }
+  }
+
+  fn api_segment(self) -> &'static str {
+    match self {
+      Self::Model => "models",
+      Self::Dataset => "datasets",
+      Self::Space => "spaces",
+    }
+  }
+}
+
+/// A hub repository at a revision, written `[datasets/|spaces/]owner/name[@revision]`.
+///
+/// Without a revision the repository's `main` branch is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HubRepo {
+  pub kind: HubRepoKind,
+  pub id: String,
+  pub revision: String,
+}
+
+/// One file of a resolved repository.
+#[derive(Debug, Clone)]
+pub struct HubFile {
+  /// The path inside the repository, e.g. `onnx/model.onnx`.
+  pub path: String,
+  /// The size in bytes as reported by the hub.
+  pub size: u64,
+  pub item: DownloadItem<String, PathBuf>,
+}
+
+impl FromStr for HubRepo {
+  type Err = ProgressDownloadError;
+
+  fn from_str(spec: &str) -> Result<Self, Self::Err> {
+    let invalid = || ProgressDownloadError::InvalidHubRepo {
+      spec: spec.to_string(),
+    };
+
+    let (kind, rest) = if let Some(rest) = spec.strip_prefix("datasets/") {
+      (HubRepoKind::Dataset, rest)
+    } else if let Some(rest) = spec.strip_prefix("spaces/") {
+      (HubRepoKind::Space, rest)
+    } else {
+      (HubRepoKind::Model, spec)
+    };
+    let (id, revision) = rest.split_once('@').unwrap_or((rest, "main"));
+
+    let mut parts = id.split('/');
+    let well_formed = matches!(
+      (parts.next(), parts.next(), parts.next()),
+      (Some(owner), Some(name), None) if !owner.is_empty() && !name.is_empty()
+    );
+    if !well_formed || revision.is_empty() {
+      return Err(invalid());
+    }
+
+    Ok(Self {
+      kind,
+      id: id.to_string(),
+      revision: revision.to_string(),
+    })
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct TreeEntry {
+  #[serde(rename = "type")]
+  kind: String,
+  path: String,
+  size: u64,
+  lfs: Option<LfsPointer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LfsPointer {
+  oid: String,
+  size: u64,
+}
+
+impl HubRepo {
+  /// Lists every file of the repository on `endpoint` (usually [`HUGGING_FACE`]) and returns
+  /// them as items saved under `target_dir`, keeping the repository layout.
+  ///
+  /// `token` is required for gated and private repositories.
+  pub async fn resolve(
+    &self,
+    endpoint: &str,
+    token: Option<&str>,
+    target_dir: impl AsRef<Path>,
+  ) -> Result<Vec<HubFile>, ProgressDownloadError> {
+    let endpoint = endpoint.trim_end_matches('/');
+    let target_dir = target_dir.as_ref();
+
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token {
+      let mut value =
+        HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| self.invalid_response())?;
+      value.set_sensitive(true);
+      headers.insert(AUTHORIZATION, value);
+    }
+
+    let client = reqwest::Client::new();
+    let mut files = Vec::new();
+    let mut next = Some(format!(
+      "{endpoint}/api/{}/{}/tree/{}?recursive=true",
+      self.kind.api_segment(),
+      self.id,
+      utf8_percent_encode(&self.revision, NON_ALPHANUMERIC)
+    ));
+
+    // Large repositories are listed a page at a time, chained through `Link: <...>; rel="next"`.
+    while let Some(url) = next.take() {
+      let response = client
+        .get(url)
+        .headers(headers.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+      next = response
+        .headers()
+        .get(LINK)
+        .and_then(|value| value.to_str().ok())
+        .and_then(next_page);
+
+      let body = response.bytes().await?;
+      let entries: Vec<TreeEntry> =
+        serde_json::from_slice(&body).map_err(|_| self.invalid_response())?;
+
+      for entry in entries.into_iter().filter(|entry| entry.kind == "file") {
+        files.push(self.file(endpoint, &headers, target_dir, entry)?);
+      }
+    }
+
+    Ok(files)
+  }
+
+  fn file(
+    &self,
+    endpoint: &str,
+    headers: &HeaderMap,
+    target_dir: &Path,
+    entry: TreeEntry,
+  ) -> Result<HubFile, ProgressDownloadError> {
+    // The listing comes from the server; never let it place files outside `target_dir`.
+    let segments: Vec<&str> = entry.path.split('/').collect();
+    if segments
+      .iter()
+      .any(|segment| segment.is_empty() || *segment == "." || *segment == "..")
+    {
+      return Err(self.invalid_response());
+    }
+
+    let url = format!(
+      "{endpoint}/{}{}/resolve/{}/{}",
+      self.kind.prefix(),
+      self.id,
+      utf8_percent_encode(&self.revision, NON_ALPHANUMERIC),
+      segments
+        .iter()
+        .map(|segment| utf8_percent_encode(segment, NON_ALPHANUMERIC).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+    );
+    let target = segments
+      .iter()
+      .fold(target_dir.to_path_buf(), |path, segment| path.join(segment));
+
+    let (size, item) = match entry.lfs {
+      Some(lfs) => (
+        lfs.size,
+        DownloadItem::builder()
+          .url(url)
+          .target(target)
+          .headers(headers.clone())
+          .integrity(Integrity::SHA256(lfs.oid))
+          .size_hint(lfs.size)
+          .build(),
+      ),
+      None => (
+        entry.size,
+        DownloadItem::builder()
+          .url(url)
+          .target(target)
+          .headers(headers.clone())
+          .size_hint(entry.size)
+          .build(),
+      ),
+    };
+
+    Ok(HubFile {
+      path: entry.path,
+      size,
+      item,
+    })
+  }
+
+  fn invalid_response(&self) -> ProgressDownloadError {
+    ProgressDownloadError::InvalidHubResponse {
+      repo: format!("{}{}@{}", self.kind.prefix(), self.id, self.revision),
+    }
+  }
+}
+
+/// Extracts the `rel="next"` target of a `Link` header.
+fn next_page(link: &str) -> Option<String> {
+  link.split(',').find_map(|part| {
+    let (target, params) = part.split_once(';')?;
+    params
+      .split(';')
+      .any(|param| param.trim() == r#"rel="next""#)
+      .then(|| {
+        target
+          .trim()
+          .trim_start_matches('<')
+          .trim_end_matches('>')
+          .to_string()
+      })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_hub_repo() {
+    let repo: HubRepo = "openai-community/gpt2".parse().unwrap();
+    assert_eq!(repo.kind, HubRepoKind::Model);
+    assert_eq!(repo.id, "openai-community/gpt2");
+    assert_eq!(repo.revision, "main");
+
+    let repo: HubRepo = "datasets/acme/corpus@refs/pr/3".parse().unwrap();
+    assert_eq!(repo.kind, HubRepoKind::Dataset);
+    assert_eq!(repo.id, "acme/corpus");
+    assert_eq!(repo.revision, "refs/pr/3");
+
+    assert!("gpt2".parse::<HubRepo>().is_err());
+    assert!("a/b/c".parse::<HubRepo>().is_err());
+    assert!("acme/model@".parse::<HubRepo>().is_err());
+  }
+
+  #[test]
+  fn test_next_page() {
+    assert_eq!(
+      next_page(r#"<https://huggingface.co/api/models/a/b/tree/main?cursor=xyz>; rel="next""#),
+      Some("https://huggingface.co/api/models/a/b/tree/main?cursor=xyz".to_string())
+    );
+    assert_eq!(next_page(r#"<https://example.com/1>; rel="prev""#), None);
+  }
+}
@@ -56,6 +56,28 @@ impl Integrity {
     }
   }
 
+  /// A stable, lowercase name for the algorithm, e.g. `sha256`.
+  pub fn algorithm_name(&self) -> &'static str {
+    match self {
+      #[cfg(feature = "md5")]
+      Integrity::MD5(_) => "md5",
+      #[cfg(feature = "sha1")]
+      Integrity::SHA1(_) => "sha1",
+      #[cfg(feature = "sha2")]
+      Integrity::SHA256(_) => "sha256",
+      #[cfg(feature = "sha2")]
+      Integrity::SHA512(_) => "sha512",
+      #[cfg(feature = "sha3")]
+      Integrity::SHA3_256(_) => "sha3-256",
+      #[cfg(feature = "blake2")]
+      Integrity::Blake2b(_) => "blake2b",
+      #[cfg(feature = "blake2")]
+      Integrity::Blake2s(_) => "blake2s",
+      #[cfg(feature = "blake3")]
+      Integrity::Blake3(_) => "blake3",
+    }
+  }
+
   pub fn algorithm(&self) -> hashery::Algorithm {
     match self {
       #[cfg(feature = "md5")]
@@ -77,3 +99,111 @@ impl Integrity {
     }
   }
 }
+
+/// Picks a checksum the server announced for the whole body from the response headers:
+/// `x-amz-checksum-sha256`, then the `Digest` header (`sha-512`, `sha-256`, `md5`), then
+/// `Content-MD5`. Values are base64 and come back as lowercase hex like hashery's digests.
+#[cfg(any(feature = "md5", feature = "sha2"))]
+pub(crate) fn from_server_headers(
+  headers: &reqwest::header::HeaderMap,
+) -> Option<(Integrity, crate::report::VerificationSource)> {
+  use crate::report::VerificationSource;
+
+  let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+  let digest = |algorithm: &str| {
+    header("digest")?.split(',').find_map(|part| {
+      let (name, value) = part.trim().split_once('=')?;
+      name.eq_ignore_ascii_case(algorithm).then_some(value)
+    })
+  };
+
+  #[cfg(feature = "sha2")]
+  if let Some(value) = header("x-amz-checksum-sha256").and_then(|value| base64_to_hex(value, 32)) {
+    return Some((Integrity::SHA256(value), VerificationSource::AmzChecksum));
+  }
+  #[cfg(feature = "sha2")]
+  if let Some(value) = digest("sha-512").and_then(|value| base64_to_hex(value, 64)) {
+    return Some((Integrity::SHA512(value), VerificationSource::Digest));
+  }
+  #[cfg(feature = "sha2")]
+  if let Some(value) = digest("sha-256").and_then(|value| base64_to_hex(value, 32)) {
+    return Some((Integrity::SHA256(value), VerificationSource::Digest));
+  }
+  #[cfg(feature = "md5")]
+  if let Some(value) = digest("md5").and_then(|value| base64_to_hex(value, 16)) {
+    return Some((Integrity::MD5(value), VerificationSource::Digest));
+  }
+  #[cfg(feature = "md5")]
+  if let Some(value) = header("content-md5").and_then(|value| base64_to_hex(value, 16)) {
+    return Some((Integrity::MD5(value), VerificationSource::ContentMd5));
+  }
+  None
+}
+
+/// Decodes standard or URL-safe base64 into lowercase hex, or `None` unless it decodes to
+/// exactly `len` bytes.
+#[cfg(any(feature = "md5", feature = "sha2"))]
+fn base64_to_hex(value: &str, len: usize) -> Option<String> {
+  let mut hex = String::with_capacity(len * 2);
+  let (mut bits, mut pending) = (0u32, 0u32);
+  for byte in value.trim().trim_end_matches('=').bytes() {
+    let sextet = match byte {
+      b'A'..=b'Z' => byte - b'A',
+      b'a'..=b'z' => byte - b'a' + 26,
+      b'0'..=b'9' => byte - b'0' + 52,
+      b'+' | b'-' => 62,
+      b'/' | b'_' => 63,
+      _ => return None,
+    };
+    bits = (bits << 6) | u32::from(sextet);
+    pending += 6;
+    if pending >= 8 {
+      pending -= 8;
+      hex.push_str(&format!("{:02x}", (bits >> pending) & 0xff));
+    }
+  }
+  (hex.len() == len * 2).then_some(hex)
+}
+
+#[cfg(all(test, any(feature = "md5", feature = "sha2")))]
+mod tests {
+  #[cfg(feature = "sha2")]
+  use reqwest::header::{HeaderMap, HeaderValue};
+
+  use super::*;
+  #[cfg(feature = "sha2")]
+  use crate::report::VerificationSource;
+
+  #[test]
+  fn test_base64_to_hex() {
+    assert_eq!(
+      base64_to_hex("1B2M2Y8AsgTpgAmY7PhCfg==", 16).as_deref(),
+      Some("d41d8cd98f00b204e9800998ecf8427e")
+    );
+    assert_eq!(base64_to_hex("1B2M2Y8AsgTpgAmY7PhCfg==", 32), None);
+    assert_eq!(base64_to_hex("not base64!", 16), None);
+  }
+
+  #[cfg(feature = "sha2")]
+  #[test]
+  fn test_from_server_headers_prefers_sha256() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+      "content-md5",
+      HeaderValue::from_static("1B2M2Y8AsgTpgAmY7PhCfg=="),
+    );
+    headers.insert(
+      "digest",
+      HeaderValue::from_static(
+        "md5=1B2M2Y8AsgTpgAmY7PhCfg==, SHA-256=47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+      ),
+    );
+
+    let (integrity, source) = from_server_headers(&headers).unwrap();
+    assert_eq!(source, VerificationSource::Digest);
+    assert_eq!(
+      integrity.value(),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+  }
+}
@@ -0,0 +1,244 @@
+//! Forwarding progress from worker processes to a parent that owns the terminal.
+//!
+//! A worker builds its downloader with
+//! [`progress_forwarder`](crate::RobustDownloaderBuilder), which replaces its own progress
+//! bars and streams the progress of its downloads over a Unix domain socket. The parent runs
+//! [`serve_progress`] on the same socket path and renders one bar per forwarded download
+//! inside its own [`MultiProgress`].
+//!
+//! The wire format is one tab-separated line per update:
+//! `<id>\t<position>\t<length>\t<status>\t<message>`, where the status is `0` while the
+//! download runs, `1` once it completed and `2` once it failed, with the error as message.
+
+use std::{
+  collections::HashMap,
+  io,
+  path::Path,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use log::debug;
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{UnixListener, UnixStream},
+  sync::mpsc,
+};
+
+use crate::{
+  clock::{Clock, SystemClock},
+  err::ProgressDownloadError,
+  observer::{ObservedItem, Progress, ProgressObserver, progress_message},
+  report::DownloadOutcome,
+};
+
+/// Minimum delay between two forwarded updates of the same download.
+const FORWARD_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The worker side: sends progress updates to a parent process.
+///
+/// Setting it as the downloader's [`progress_forwarder`](crate::RobustDownloaderBuilder)
+/// makes it the downloader's [`ProgressObserver`].
+#[derive(Debug, Clone)]
+pub struct ProgressForwarder {
+  sender: mpsc::UnboundedSender<String>,
+  last_sent: Arc<Mutex<HashMap<u64, Instant>>>,
+  clock: Arc<dyn Clock>,
+}
+
+impl ProgressForwarder {
+  /// Connects to a parent listening with [`serve_progress`] on `path`.
+  ///
+  /// Must be called from within a Tokio runtime; a background task writes the updates.
+  pub async fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+    let mut stream = UnixStream::connect(path).await?;
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+
+    tokio::spawn(async move {
+      while let Some(line) = receiver.recv().await {
+        if let Err(e) = stream.write_all(line.as_bytes()).await {
+          debug!("progress forwarder disconnected: {}", e);
+          break;
+        }
+      }
+    });
+
+    Ok(Self {
+      sender,
+      last_sent: Arc::default(),
+      clock: Arc::new(SystemClock),
+    })
+  }
+
+  /// Paces the updates by the downloader's clock.
+  pub(crate) fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+    Self { clock, ..self }
+  }
+
+  fn send(&self, id: u64, position: u64, length: u64, status: Status, message: &str) {
+    // The message is the last field, so only line breaks need to go.
+    let message: String = message
+      .chars()
+      .map(|c| if matches!(c, '\n' | '\r') { ' ' } else { c })
+      .collect();
+    let line = format!(
+      "{}\t{}\t{}\t{}\t{}\n",
+      id, position, length, status as u8, message
+    );
+    // A gone parent must not fail the download.
+    let _ = self.sender.send(line);
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Instant>> {
+    self.last_sent.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl ProgressObserver for ProgressForwarder {
+  fn on_chunk(&self, item: &ObservedItem, progress: Progress) {
+    {
+      let mut last_sent = self.lock();
+      if last_sent
+        .get(&item.id)
+        .is_some_and(|at| self.clock.elapsed(*at) < FORWARD_INTERVAL)
+      {
+        return;
+      }
+      last_sent.insert(item.id, self.clock.now());
+    }
+    self.send(
+      item.id,
+      progress.downloaded,
+      progress.total,
+      Status::Running,
+      &progress_message(item, progress),
+    );
+  }
+
+  fn on_complete(&self, item: &ObservedItem, _outcome: DownloadOutcome) {
+    self.lock().remove(&item.id);
+    self.send(item.id, 0, 0, Status::Finished, "");
+  }
+
+  fn on_error(&self, item: &ObservedItem, error: &ProgressDownloadError) {
+    self.lock().remove(&item.id);
+    let message = format!("{} failed: {}", item.url, error);
+    self.send(item.id, 0, 0, Status::Failed, &message);
+  }
+}
+
+/// The parent side: accepts worker connections on `path` and renders their downloads
+/// into `mp`, until the listener fails.
+///
+/// `style` is applied to every bar created for a forwarded download. A stale socket file
+/// at `path` is removed before binding.
+pub async fn serve_progress(
+  path: impl AsRef<Path>,
+  mp: MultiProgress,
+  style: indicatif::ProgressStyle,
+) -> io::Result<()> {
+  let path = path.as_ref();
+  if path.exists() {
+    tokio::fs::remove_file(path).await?;
+  }
+  let listener = UnixListener::bind(path)?;
+
+  loop {
+    let (stream, _) = listener.accept().await?;
+    let mp = mp.clone();
+    let style = style.clone();
+
+    tokio::spawn(async move {
+      let mut bars: HashMap<u64, ProgressBar> = HashMap::new();
+      let mut lines = BufReader::new(stream).lines();
+
+      while let Ok(Some(line)) = lines.next_line().await {
+        let Some(update) = parse_line(&line) else {
+          debug!("ignoring malformed progress line: {}", line);
+          continue;
+        };
+
+        match update.status {
+          Status::Running => {
+            let bar = bars.entry(update.id).or_insert_with(|| {
+              let bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden());
+              bar.set_style(style.clone());
+              mp.add(bar)
+            });
+            bar.set_length(update.length);
+            bar.set_position(update.position);
+            bar.set_message(update.message.to_string());
+          }
+          Status::Finished => {
+            if let Some(bar) = bars.remove(&update.id) {
+              bar.finish_and_clear();
+            }
+          }
+          // Left where it stopped, with the error next to it.
+          Status::Failed => {
+            if let Some(bar) = bars.remove(&update.id) {
+              bar.abandon_with_message(update.message.to_string());
+            }
+          }
+        }
+      }
+
+      // Worker went away: whatever is left will not progress anymore.
+      for bar in bars.into_values() {
+        bar.abandon();
+      }
+    });
+  }
+}
+
+/// Where a forwarded download is, as sent on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+  Running = 0,
+  Finished = 1,
+  Failed = 2,
+}
+
+struct Update<'a> {
+  id: u64,
+  position: u64,
+  length: u64,
+  status: Status,
+  message: &'a str,
+}
+
+fn parse_line(line: &str) -> Option<Update<'_>> {
+  let mut fields = line.splitn(5, '\t');
+  Some(Update {
+    id: fields.next()?.parse().ok()?,
+    position: fields.next()?.parse().ok()?,
+    length: fields.next()?.parse().ok()?,
+    status: match fields.next()? {
+      "0" => Status::Running,
+      "1" => Status::Finished,
+      "2" => Status::Failed,
+      _ => return None,
+    },
+    message: fields.next().unwrap_or_default(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_line() {
+    let update = parse_line("3\t10\t100\t0\t10% https://example.com/a").unwrap();
+    assert_eq!((update.id, update.position, update.length), (3, 10, 100));
+    assert_eq!(update.status, Status::Running);
+    assert_eq!(update.message, "10% https://example.com/a");
+
+    let update = parse_line("3\t0\t0\t2\thttps://example.com/a failed: timed out").unwrap();
+    assert_eq!(update.status, Status::Failed);
+    assert_eq!(update.message, "https://example.com/a failed: timed out");
+    assert!(parse_line("garbage").is_none());
+    assert!(parse_line("3\t0\t0\t9\t").is_none());
+  }
+}
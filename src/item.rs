@@ -1,5 +1,26 @@
+use std::{
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+#[cfg(feature = "sha2")]
+use cow_utils::CowUtils;
+use reqwest::{Url, header::HeaderMap};
 use typed_builder::TypedBuilder;
 
+use crate::{
+  assertion::ResponseAssertion, auth::Auth, err::ProgressDownloadError, filename::FilenamePolicy,
+  naming::ContentAddress, overwrite::OverwritePolicy, retry::RetryPolicy,
+  transform::ChunkTransform, verifier::IntegrityVerifier,
+};
+
+#[cfg(feature = "pgp")]
+use crate::signature::PgpSignature;
+#[cfg(feature = "sha2")]
+use crate::sums::IntegrityFile;
+#[cfg(feature = "unpack")]
+use crate::unpack::Unpack;
+
 #[cfg(any(
   feature = "md5",
   feature = "sha1",
@@ -15,6 +36,54 @@ pub struct DownloadItem<U, P> {
   pub url: U,
   pub target: P,
 
+  /// Treat `target` as a directory and name the file after the response's
+  /// `Content-Disposition`, or else the last segment of the URL, sanitized by the
+  /// [`FilenamePolicy`] so the name cannot leave the directory. The resolved path is the
+  /// report's [`target`](crate::DownloadReport::target). A name that is already taken is
+  /// handled by the overwrite policy once the response arrives. Defaults to false.
+  #[builder(default)]
+  pub target_is_dir: bool,
+
+  /// Fallback URLs serving the same file, tried in order once the previous URL failed
+  /// for good, i.e. after its retries ran out or with a permanent error. A partial
+  /// download carries over from one URL to the next.
+  #[builder(default)]
+  pub mirrors: Vec<U>,
+
+  /// Extra headers sent with every request for this item, including retries and
+  /// resumed requests, e.g. `Authorization`, `Accept` or an API version. A `Range` header
+  /// is ignored, since the downloader picks the range itself when resuming. Cross-origin
+  /// redirects strip some of them, see [`RedirectPolicy`](crate::RedirectPolicy).
+  ///
+  /// ```rust
+  /// use reqwest::header::{ACCEPT, HeaderMap, HeaderValue};
+  /// use robust_downloader::DownloadItem;
+  ///
+  /// let mut headers = HeaderMap::new();
+  /// headers.insert(ACCEPT, HeaderValue::from_static("application/octet-stream"));
+  /// let item = DownloadItem::builder()
+  ///   .url("https://api.github.com/repos/o/r/releases/assets/1")
+  ///   .target("asset.tar.gz")
+  ///   .headers(headers)
+  ///   .build();
+  /// ```
+  #[builder(default)]
+  pub headers: HeaderMap,
+
+  /// Credentials for this item, replacing the downloader's own `auth`. See [`Auth`].
+  #[builder(default = None, setter(strip_option))]
+  pub auth: Option<Auth>,
+
+  /// What to do if `target` already exists, replacing the downloader's own
+  /// `overwrite`. See [`OverwritePolicy`].
+  #[builder(default = None, setter(strip_option))]
+  pub overwrite: Option<OverwritePolicy>,
+
+  /// How the file name of a [`target_is_dir`](Self::target_is_dir) item is decoded,
+  /// replacing the downloader's own `filename_policy`. See [`FilenamePolicy`].
+  #[builder(default = None, setter(strip_option))]
+  pub filename_policy: Option<FilenamePolicy>,
+
   #[cfg(any(
     feature = "md5",
     feature = "sha1",
@@ -23,9 +92,287 @@ pub struct DownloadItem<U, P> {
     feature = "blake2",
     feature = "blake3"
   ))]
+  /// Expected hash of the file, checked against the bytes exactly as the server sent
+  /// them. A `Content-Encoding` is never undone (a warning is logged when a response
+  /// carries one), so the stored file and its hash are always the wire bytes. A target
+  /// that already has this hash is not downloaded again, see
+  /// [`DownloadOutcome::AlreadyValid`](crate::DownloadOutcome::AlreadyValid).
   #[builder(default = None, setter(strip_option))]
   pub integrity: Option<Integrity>,
 
+  /// A checksum list to look the item's integrity up in, e.g. a release's
+  /// `SHASUMS256.txt`; see [`IntegrityFile`](crate::IntegrityFile). Ignored when the item
+  /// has an [`integrity`](Self::integrity) of its own.
+  #[cfg(feature = "sha2")]
+  #[builder(default = None, setter(strip_option))]
+  pub integrity_file: Option<IntegrityFile>,
+
+  /// The URL of a file holding the item's checksum, such as `tool.tar.gz.sha256`, fetched
+  /// with the same retries as the download before it starts. A file with several entries is
+  /// looked up like an [`integrity_file`](Self::integrity_file); a bare checksum or a single
+  /// entry is taken whatever its name. Ignored when the item has an
+  /// [`integrity`](Self::integrity) or an `integrity_file`.
+  #[cfg(feature = "sha2")]
+  #[builder(default = None, setter(into, strip_option))]
+  pub integrity_url: Option<String>,
+
+  /// A checksum verified with a hashing implementation of the caller's own, checked in
+  /// addition to the item's integrity and usable without any hash feature; see
+  /// [`IntegrityVerifier`].
+  #[builder(default = None, setter(strip_option))]
+  pub verifier: Option<Arc<dyn IntegrityVerifier>>,
+
+  /// A detached OpenPGP signature the file is checked against after its hash; see
+  /// [`PgpSignature`](crate::PgpSignature).
+  #[cfg(feature = "pgp")]
+  #[builder(default = None, setter(strip_option))]
+  pub signature: Option<PgpSignature>,
+
+  /// Only download when the remote file differs from the existing target, like `wget -N`.
+  ///
+  /// A `HEAD` request is issued first; the download is skipped when the remote
+  /// `Content-Length` equals the target's size and its `Last-Modified` is not newer than
+  /// the target's modification time.
+  #[builder(default = false)]
+  pub only_if_newer: bool,
+
+  /// Name the file after its content; `target` then is the root of the store.
+  /// See [`ContentAddress`].
+  #[builder(default = None, setter(strip_option))]
+  pub content_address: Option<ContentAddress>,
+
+  /// The expected size in bytes, if known up front, e.g. from a listing. Only used to
+  /// decide when to start the item (see [`StartPolicy`](crate::StartPolicy)) and to check
+  /// that a batch fits on disk without asking the server; the actual size always comes from
+  /// the server.
+  #[builder(default = None, setter(strip_option))]
+  pub size_hint: Option<u64>,
+
+  /// The exact size in bytes, e.g. from a manifest. A response announcing another size
+  /// fails the item with a size mismatch, moving on to the mirrors, and a body ending short
+  /// of it is retried. Without it, the
+  /// body is checked against the response's `Content-Length`. Stands in for `size_hint`.
+  #[builder(default = None, setter(strip_option))]
+  pub expected_size: Option<u64>,
+
+  /// The most bytes this item may write, replacing the downloader's `max_size`: a response
+  /// announcing more is rejected, and a body growing beyond it is aborted and its partial
+  /// file removed. Either way the item fails without retries. Defaults to none.
+  #[builder(default = None, setter(strip_option))]
+  pub max_size: Option<u64>,
+
+  /// A partial download of this item left by another tool, e.g. a browser's `.crdownload`
+  /// or an old script's `.part`, to resume from. It is moved into place as the item's temp
+  /// file if it holds more than that, and if its tail matches the remote file, see
+  /// [`existing_partial_check`](Self::existing_partial_check); otherwise it is left alone.
+  /// Defaults to none.
+  #[builder(default, setter(into, strip_option))]
+  pub existing_partial: Option<PathBuf>,
+
+  /// How many bytes at the end of the [`existing_partial`](Self::existing_partial) are
+  /// compared with the remote file, fetched with a range request, before it is adopted; 0
+  /// adopts it unchecked. Defaults to 64KB.
+  #[builder(default = 64 * 1024)]
+  pub existing_partial_check: u64,
+
+  /// Checks on the response, made before any of the body is written; a violation fails
+  /// the item without retries. See [`ResponseAssertion`].
+  #[builder(default)]
+  pub assertions: Vec<ResponseAssertion>,
+
+  /// How this item's failed attempts are retried, replacing the downloader's
+  /// `retry_policy`. Defaults to none.
+  #[builder(default = None, setter(strip_option))]
+  pub retry_policy: Option<RetryPolicy>,
+
+  /// Upper bound in bytes per second for this item's network reads, e.g. to keep a
+  /// low-priority file from competing with the others. Applies on top of the downloader's
+  /// own `max_bandwidth`. Defaults to no limit.
   #[builder(default = None, setter(strip_option))]
-  pub integrity_file: Option<()>,
+  pub max_bandwidth: Option<u64>,
+
+  /// Free-form labels, used to cancel subsets of a batch
+  /// ([`RobustDownloader::cancel_tagged`](crate::RobustDownloader::cancel_tagged)),
+  /// to group progress bars and to summarize results ([`summarize_by_tag`](crate::summarize_by_tag)).
+  #[builder(default)]
+  pub tags: Vec<String>,
+
+  /// Keep the file once in this content-addressed store directory (laid out like
+  /// [`ContentAddress::Sha256`]) and make `target` a symlink to it.
+  ///
+  /// Items downloading identical content, e.g. from different projects, share one stored
+  /// copy. When the item has a SHA-256 [`integrity`](Self::integrity) that is already in the
+  /// store, nothing is fetched: the link is created, or repaired if it dangles or points
+  /// elsewhere, and the item is reported as [`NotModified`](crate::DownloadOutcome::NotModified).
+  #[cfg(feature = "sha2")]
+  #[builder(default = None, setter(strip_option, into))]
+  pub link_store: Option<PathBuf>,
+
+  /// More places to put the same file, e.g. the same binary in several toolchain
+  /// directories. The file is fetched and verified once, then placed at `target` and at
+  /// each of these, see [`FanOut`].
+  #[builder(default)]
+  pub extra_targets: Vec<PathBuf>,
+
+  /// How [`extra_targets`](Self::extra_targets) get their copy. Defaults to
+  /// [`FanOut::HardLink`].
+  #[builder(default)]
+  pub fan_out: FanOut,
+
+  /// Applied in order to the body between the network and the disk, see
+  /// [`ChunkTransform`]. Items with transforms always download from the start.
+  #[builder(default)]
+  pub transforms: Vec<Arc<dyn ChunkTransform>>,
+
+  /// Install the downloaded archive into a versioned directory, see [`Unpack`]. An item
+  /// whose archive cannot be installed fails. Defaults to none.
+  #[cfg(feature = "unpack")]
+  #[builder(default, setter(strip_option))]
+  pub unpack: Option<Unpack>,
+}
+
+impl DownloadItem<String, PathBuf> {
+  /// Reads a list of URLs, one item per line, downloading into `target_dir`.
+  ///
+  /// Every line is a URL optionally followed by the file's SHA-256 and its file name:
+  /// `url<TAB>sha256<TAB>filename`, or comma-separated in a `.csv` file. Either column may
+  /// be left empty; without a file name, the name is taken from the URL by `policy`. Listed
+  /// names are used as written, only normalized by it. Blank lines and lines starting with
+  /// `#` are skipped. In this list the columns are separated by tabs:
+  ///
+  /// ```text
+  /// # release 1.2.0
+  /// https://example.com/dist/tool-1.2.0.tar.gz
+  /// https://example.com/dist/tool-1.2.0.zip    <sha256>    tool.zip
+  /// ```
+  pub fn from_list_file(
+    path: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    policy: FilenamePolicy,
+  ) -> Result<Vec<Self>, ProgressDownloadError> {
+    let path = path.as_ref();
+    let separator = match path.extension() {
+      Some(extension) if extension.eq_ignore_ascii_case("csv") => ',',
+      _ => '\t',
+    };
+    let list = std::fs::read_to_string(path)?;
+    parse_list(&list, separator, target_dir.as_ref(), policy)
+  }
+}
+
+fn parse_list(
+  list: &str,
+  separator: char,
+  target_dir: &Path,
+  policy: FilenamePolicy,
+) -> Result<Vec<DownloadItem<String, PathBuf>>, ProgressDownloadError> {
+  let mut items = Vec::new();
+
+  for (index, line) in list.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let invalid = |reason: &str| ProgressDownloadError::InvalidUrlList {
+      line: index + 1,
+      reason: reason.to_string(),
+    };
+
+    let mut columns = line.split(separator).map(str::trim);
+    let url = columns.next().unwrap_or_default();
+    let sha256 = columns.next().filter(|sha256| !sha256.is_empty());
+    let file_name = columns.next().filter(|name| !name.is_empty());
+    if columns.next().is_some() {
+      return Err(invalid("expected at most 3 columns"));
+    }
+
+    let parsed = Url::parse(url).map_err(|_| invalid("not a URL"))?;
+    let file_name = match file_name {
+      // Separators never survive, so a listed name cannot escape the target directory.
+      Some(name) => {
+        let listed = FilenamePolicy {
+          percent_decode: false,
+          ..policy
+        };
+        let name = listed.apply(name);
+        (!matches!(name.as_ref(), "." | "..")).then(|| name.into_owned())
+      }
+      None => policy.file_name_from_url(&parsed),
+    }
+    .ok_or_else(|| invalid("no file name"))?;
+
+    let item = DownloadItem::builder()
+      .url(url.to_string())
+      .target(target_dir.join(file_name))
+      .build();
+
+    match sha256 {
+      None => items.push(item),
+      #[cfg(feature = "sha2")]
+      Some(sha256) => {
+        if sha256.len() != 64 || !sha256.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+          return Err(invalid("not a SHA-256 checksum"));
+        }
+        items.push(DownloadItem {
+          integrity: Some(Integrity::SHA256(
+            sha256.cow_to_ascii_lowercase().into_owned(),
+          )),
+          ..item
+        });
+      }
+      #[cfg(not(feature = "sha2"))]
+      Some(_) => return Err(invalid("SHA-256 checksums need the `sha2` feature")),
+    }
+  }
+
+  Ok(items)
+}
+
+/// How a file is duplicated for the extra targets of an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FanOut {
+  /// Hard-link the targets to one another where the filesystem allows it, falling back to
+  /// a copy (e.g. across devices).
+  #[default]
+  HardLink,
+  /// Give every target its own copy.
+  Copy,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_list() {
+    let list = "# mirror\n\
+                https://example.com/dist/a%20b.tar.gz\n\
+                \n\
+                https://example.com/get?id=1\t\tc.zip\n";
+    let parse = |list: &str, separator| {
+      parse_list(list, separator, Path::new("out"), FilenamePolicy::default())
+    };
+    let items = parse(list, '\t').unwrap();
+
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].target, Path::new("out/a b.tar.gz"));
+    assert_eq!(items[1].url, "https://example.com/get?id=1");
+    assert_eq!(items[1].target, Path::new("out/c.zip"));
+
+    let escaping = parse("https://example.com/a,,../../etc/passwd", ',');
+    assert_eq!(
+      escaping.unwrap()[0].target,
+      Path::new("out/.._.._etc_passwd")
+    );
+
+    assert!(matches!(
+      parse("https://example.com/a\tnot-a-hash", '\t'),
+      Err(ProgressDownloadError::InvalidUrlList { line: 1, .. })
+    ));
+
+    // Names from URLs follow the policy.
+    let raw = FilenamePolicy::builder().percent_decode(false).build();
+    let items = parse_list(list, '\t', Path::new("out"), raw).unwrap();
+    assert_eq!(items[0].target, Path::new("out/a%20b.tar.gz"));
+  }
 }
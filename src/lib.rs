@@ -1,19 +1,127 @@
-use std::{env, path::Path, sync::Arc, time::Duration};
+use std::{
+  collections::HashSet,
+  ffi::OsStr,
+  panic::AssertUnwindSafe,
+  path::{Path, PathBuf},
+  sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+  },
+  time::Duration,
+};
 
-use backoff::ExponentialBackoff;
+use cancel::CancelRegistry;
+use cleanup::PartialGuard;
+use dns::CachingResolver;
 use err::ProgressDownloadError;
-use indicatif::{ProgressBar, ProgressDrawTarget};
-use reqwest::IntoUrl;
+use fair::FairScheduler;
+use futures::FutureExt;
+use group::GroupObserver;
+use log::{debug, info, warn};
+use memory::MemoryBudget;
+use observer::ItemObserver;
+use pacing::HostPacer;
+use ramp::Ramp;
+use report::ItemFailure;
+use reqwest::{IntoUrl, Url};
+use space::{SpaceNeed, Volumes};
+use stats::StatsCollector;
 use task::DownloadTaskRunner;
+use throttle::BandwidthLimiter;
 use tokio::sync::Semaphore;
 use typed_builder::TypedBuilder;
 
+mod assertion;
+mod auth;
+mod batch;
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+mod cache;
+mod cancel;
+mod cleanup;
+mod clock;
+#[cfg(feature = "conditions")]
+pub mod conditions;
+mod dns;
 mod err;
+mod fair;
+mod filename;
+mod group;
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+mod hasher;
+#[cfg(feature = "hub")]
+pub mod hub;
 mod integrity;
+#[cfg(all(unix, feature = "ipc"))]
+pub mod ipc;
 mod item;
+mod memory;
+mod naming;
+mod observer;
+#[cfg(feature = "oci")]
+pub mod oci;
+mod overwrite;
+mod pacing;
+mod pins;
+mod preset;
+mod ramp;
+mod redirect;
+pub mod release;
+mod remote;
+mod report;
+mod retry;
+mod sign;
+#[cfg(feature = "pgp")]
+mod signature;
+mod space;
+mod spot;
+mod staging;
+mod start;
+mod stats;
+mod storage;
+#[cfg(feature = "sha2")]
+mod sums;
 mod task;
+#[cfg(test)]
+mod test_server;
+mod throttle;
 mod tracker;
+mod transform;
+#[cfg(feature = "unpack")]
+mod unpack;
+mod verifier;
 
+pub use assertion::ResponseAssertion;
+pub use auth::Auth;
+pub use batch::ErrorPolicy;
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+pub use cache::DigestCache;
+pub use cancel::CancellationToken;
+pub use cleanup::CleanupPolicy;
+pub use clock::{Clock, SystemClock, TokioClock};
+pub use fair::DownloadQueue;
+pub use filename::FilenamePolicy;
+pub use group::DownloadGroup;
 #[cfg(any(
   feature = "md5",
   feature = "sha1",
@@ -24,11 +132,45 @@ mod tracker;
 ))]
 pub use integrity::*;
 pub use item::*;
+pub use naming::ContentAddress;
+#[cfg(feature = "progress-bars")]
+pub use observer::TerminalProgress;
+pub use observer::{ObservedItem, Progress, ProgressObserver, Retry, TaskState};
+pub use overwrite::OverwritePolicy;
+pub use pins::PinStore;
+pub use preset::Profile;
+pub use ramp::AutoRampPolicy;
+pub use redirect::{CrossOriginHeaders, RedirectPolicy};
+pub use remote::RemoteMetadata;
+pub use report::{
+  AttemptMetrics, DownloadOutcome, DownloadReport, DownloadResult, TagSummary, VerificationSource,
+  summarize_by_tag,
+};
+pub use retry::{Jitter, RetryPolicy};
+pub use sign::RequestSigner;
+#[cfg(feature = "sigv4")]
+pub use sign::SigV4Signer;
+#[cfg(feature = "pgp")]
+pub use signature::{PgpSignature, SignatureSource};
+pub use spot::SpotCheck;
+pub use staging::Staging;
+pub use start::{StartCandidate, StartDecision, StartPolicy};
+pub use stats::DownloadStats;
+pub use storage::{LocalStorage, MemoryStorage, Storage};
+#[cfg(feature = "sha2")]
+pub use sums::{IntegrityFile, SHA256SUMS, write_sha256sums};
+#[cfg(feature = "decrypt")]
+pub use transform::Aes256GcmStream;
+pub use transform::{ChunkTransform, CrlfToLf};
+#[cfg(feature = "unpack")]
+pub use unpack::{IntegrityOf, Unpack};
+pub use verifier::{Digest, IntegrityVerifier};
 
 /// A robust, concurrent file downloader with retry capabilities and progress tracking.
 ///
 /// `RobustDownloader` provides a reliable way to download multiple files concurrently with features like:
 /// - Automatic retries with exponential backoff
+/// - Requests to hosts answering 429 Too Many Requests spaced out, adaptively
 /// - Progress bars for visual feedback
 /// - Concurrent downloads with configurable limits
 /// - Timeouts and connection management
@@ -75,40 +217,326 @@ pub struct RobustDownloader {
   #[builder(default = Duration::from_millis(500))]
   read_chunk_timeout: Duration,
 
+  /// The HTTP client to download with, e.g. one with custom TLS roots, a proxy or
+  /// middleware-like defaults. `connect_timeout`, `dns_cache_ttl`, `tcp_keepalive` and
+  /// `http2_ping_interval` then do not apply.
+  ///
+  /// The downloader follows redirects itself, hop by hop, to apply its [`RedirectPolicy`];
+  /// build the client with `.redirect(reqwest::redirect::Policy::none())` or the client's
+  /// own policy takes over. Files are stored and verified as the server sent them, so a
+  /// client built with reqwest's `gzip`, `brotli` or `deflate` features must also disable
+  /// them (`.no_gzip().no_brotli().no_deflate()`), or compressed responses are decoded and
+  /// fail their integrity check.
+  /// Defaults to a client built per batch without connection reuse.
+  #[builder(default, setter(strip_option))]
+  client: Option<reqwest::Client>,
+
+  /// Resolve every host of a batch once up front and cache the results for this long.
+  /// Defaults to no caching beyond the system resolver's own.
+  #[builder(default, setter(strip_option))]
+  dns_cache_ttl: Option<Duration>,
+
+  /// Send TCP keepalive probes after a connection was idle this long, so that NATs and
+  /// firewalls do not drop it while a slow server prepares the data; otherwise that shows
+  /// up as a reset in the middle of the body, which costs a retry.
+  /// Defaults to the system's setting, usually no probes.
+  #[builder(default, setter(strip_option))]
+  tcp_keepalive: Option<Duration>,
+
+  /// Send HTTP/2 pings this often, also while no request is running, for the same reason.
+  /// A connection whose ping is not answered within 20 seconds is closed, so a dead session
+  /// is noticed before a read times out on it. Defaults to no pings.
+  #[cfg(feature = "http2")]
+  #[builder(default, setter(strip_option))]
+  http2_ping_interval: Option<Duration>,
+
   /// Buffer size threshold for flushing downloaded data to disk.
   /// Defaults to 512KB.
   #[builder(default = 512 * 1024)]
   flush_threshold: usize,
 
+  /// Maximum number of chunks buffered between the network reader and the disk writer
+  /// of each download. When full, reading pauses so TCP backpressure slows the server
+  /// down instead of memory growing. Defaults to 16.
+  #[builder(default = 16)]
+  buffer_chunks: usize,
+
   /// Maximum number of concurrent downloads.
   /// Defaults to 2.
   #[builder(default = 2)]
   max_concurrent: usize,
+
+  /// Start every batch with fewer downloads at a time and ramp up to `max_concurrent`
+  /// while few attempts fail, see [`AutoRampPolicy`]. Defaults to none: batches start
+  /// with `max_concurrent` downloads.
+  #[builder(default, setter(strip_option))]
+  auto_ramp: Option<AutoRampPolicy>,
+
+  /// Upper bound in bytes for the write buffers of all running downloads combined.
+  /// Buffers shrink (down to 64KB each) when many downloads share the budget.
+  /// Defaults to no limit, i.e. 1MB per download.
+  #[builder(default, setter(transform = |limit: usize| Some(Arc::new(MemoryBudget::new(limit)))))]
+  memory_limit: Option<Arc<MemoryBudget>>,
+
+  /// Upper bound in bytes per second for the network reads of all running downloads
+  /// combined, e.g. to leave room for others on a shared link. Downloads that would exceed
+  /// it stop reading for a while, so TCP backpressure slows the servers down.
+  /// Defaults to no limit.
+  #[builder(default, setter(transform = |limit: u64| Some(Arc::new(BandwidthLimiter::new(limit)))))]
+  max_bandwidth: Option<Arc<BandwidthLimiter>>,
+
+  /// How redirects are followed and which item headers survive cross-origin hops.
+  /// Defaults to 10 hops, stripping credentials when leaving the original origin.
+  #[builder(default)]
+  redirect_policy: RedirectPolicy,
+
+  /// What to do when the remote file turned out smaller than an existing partial download,
+  /// i.e. it changed since the partial was written. When true the partial is discarded and
+  /// the download restarts from scratch (recorded in
+  /// [`AttemptMetrics::discarded_partial`]); when false the item fails with
+  /// [`ProgressDownloadError::RemoteFileChanged`]. Defaults to true.
+  #[builder(default = true)]
+  restart_on_remote_change: bool,
+
+  /// Whether an item whose target already exists and matches its `integrity` is skipped
+  /// without a request, and reported as [`DownloadOutcome::AlreadyValid`]. This makes
+  /// re-running the same batch cheap. Defaults to true.
+  #[builder(default = true)]
+  #[cfg_attr(
+    not(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    )),
+    allow(dead_code)
+  )]
+  skip_valid_targets: bool,
+
+  /// Digests of targets, trusted while a target's size and modification time are unchanged,
+  /// so `skip_valid_targets` only hashes what changed since an earlier run. Every verified
+  /// download is recorded in it once placed. Write it back with [`DigestCache::save`] after
+  /// the batch. Defaults to none.
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  #[builder(default, setter(strip_option))]
+  digest_cache: Option<Arc<DigestCache>>,
+
+  /// Whether items without an `integrity` are verified against checksums the server sends
+  /// with a full response (`x-amz-checksum-sha256`, `Digest`, `Content-MD5`), as far as the
+  /// enabled hash features allow. A mismatch discards the file and is retried like a
+  /// network error ([`ProgressDownloadError::ChecksumHeaderMismatch`]);
+  /// [`DownloadReport::verified_by`] tells which checksum was used. Defaults to true.
+  #[builder(default = true)]
+  verify_server_checksums: bool,
+
+  /// Trust on first use for items without an `integrity`: their first download pins the
+  /// URL's SHA-256 in this store, and a later download that differs fails with
+  /// [`ProgressDownloadError::PinMismatch`]. Needs the `sha2` feature. See [`PinStore`].
+  #[builder(default, setter(strip_option))]
+  pin_store: Option<Arc<PinStore>>,
+
+  /// How failed attempts are retried, unless an item has a policy of its own.
+  /// Defaults to [`RetryPolicy::default`].
+  #[builder(default)]
+  retry_policy: RetryPolicy,
+
+  /// Consulted whenever an item gets a concurrency slot, see [`StartPolicy`].
+  /// Defaults to none: every item starts as soon as it gets a slot.
+  #[builder(default, setter(strip_option))]
+  start_policy: Option<Arc<dyn StartPolicy>>,
+
+  /// Credentials for every item that has none of its own, see [`Auth`].
+  /// Defaults to none.
+  #[builder(default, setter(strip_option))]
+  auth: Option<Auth>,
+
+  /// Signs every request right before it is sent, see [`RequestSigner`].
+  /// Defaults to none.
+  #[builder(default, setter(strip_option))]
+  request_signer: Option<Arc<dyn RequestSigner>>,
+
+  /// Where verified downloads are placed.
+  /// Defaults to [`LocalStorage`].
+  #[builder(default = Arc::new(LocalStorage))]
+  storage: Arc<dyn Storage>,
+
+  /// Receives the progress of every item, see [`ProgressObserver`]. Replaces the built-in
+  /// progress bars, and is notified even when the downloader is quiet. Defaults to none.
+  #[builder(default, setter(strip_option))]
+  progress_observer: Option<Arc<dyn ProgressObserver>>,
+
+  /// How often observers hear about the progress of a running item: chunks arriving faster
+  /// are coalesced, and a stalled item's progress is repeated at this pace, see
+  /// [`ProgressObserver`]. Zero reports every chunk. Defaults to 250ms.
+  #[builder(default = Duration::from_millis(250))]
+  progress_interval: Duration,
+
+  /// Forwards progress to a parent process instead of drawing it.
+  /// See the [`ipc`] module.
+  #[cfg(all(unix, feature = "ipc"))]
+  #[builder(default, setter(strip_option))]
+  progress_forwarder: Option<ipc::ProgressForwarder>,
+
+  /// Never write to stdout or stderr: the built-in progress bars are not drawn, and
+  /// everything the crate reports goes through the [`log`] facade only (targets follow the
+  /// module path, e.g. `robust_downloader::task`), so the embedding application decides what
+  /// is shown. Terminal state is not touched either (no cursor movement or clearing), so a
+  /// surrounding TUI keeps full control of the screen. A configured `progress_observer` is
+  /// still notified. Defaults to false.
+  #[builder(default = false)]
+  #[cfg_attr(not(feature = "progress-bars"), allow(dead_code))]
+  quiet: bool,
+
+  /// When a failed item aborts the rest of a [`download`](Self::download) batch.
+  /// Defaults to [`ErrorPolicy::FailFast`].
+  #[builder(default)]
+  error_policy: ErrorPolicy,
+
+  /// Where items are downloaded to before they are verified and placed. Defaults to
+  /// [`Staging::TempDir`].
+  #[builder(default)]
+  staging: Staging,
+
+  /// What happens to partial temp files of items that do not complete.
+  /// Defaults to [`CleanupPolicy::KeepPartial`].
+  #[builder(default)]
+  cleanup_policy: CleanupPolicy,
+
+  /// What to do with items whose target already exists; items may override it.
+  /// Defaults to [`OverwritePolicy::Overwrite`].
+  #[builder(default)]
+  overwrite: OverwritePolicy,
+
+  /// How file names taken from URLs and `Content-Disposition` headers are decoded; items
+  /// may override it. Defaults to [`FilenamePolicy::default`].
+  #[builder(default)]
+  filename_policy: FilenamePolicy,
+
+  /// The most bytes an item may write, e.g. when downloading URLs supplied by users from
+  /// servers that might stream forever; items may override it. An item that would grow
+  /// beyond it fails without retries. Defaults to no limit.
+  #[builder(default, setter(strip_option))]
+  max_size: Option<u64>,
+
+  /// Check before a batch starts that it fits on the filesystems it writes to, and fail
+  /// with [`ProgressDownloadError::InsufficientSpace`] listing what each item needs if not.
+  ///
+  /// Sizes come from the items' [`expected_size`](DownloadItem::expected_size) or
+  /// [`size_hint`](DownloadItem::size_hint), or else from a `HEAD` request; items of
+  /// unknown size count as empty. An item needs its size, less any partial download, where
+  /// it is staged, and its full size where it is placed if that is another filesystem.
+  /// Items that turn out up to date are counted all the same. A [`DownloadGroup`] also
+  /// needs room for the file its parts are joined into. Defaults to false.
+  #[builder(default)]
+  check_space: bool,
+
+  /// With `check_space`, run the items that fit instead of failing: items are admitted in
+  /// order while there is space left, and the others are reported as
+  /// [`DownloadOutcome::NoSpace`]. Groups still fail as a whole. Implies `check_space`.
+  /// Defaults to false.
+  #[builder(default)]
+  best_effort_space: bool,
+
+  /// Write a marker next to every downloaded target once it is placed, named like the
+  /// target plus this suffix, e.g. `.ok` for `file.tar.gz.ok`. Tools watching the
+  /// directory can wait for the marker instead of guessing when the target is complete.
+  ///
+  /// The marker is placed through the [`Storage`] like the target, so with the default
+  /// storage it appears in one rename. It holds the target's line in `sha256sum` format
+  /// when the item was verified against a SHA-256 and is empty otherwise. Items that were
+  /// not downloaded, e.g. because the target was already valid, keep the marker they have.
+  /// Defaults to none.
+  #[builder(default, setter(into, strip_option))]
+  completion_marker: Option<String>,
+
+  /// Where the current time is read from, e.g. [`TokioClock`] for tests that pause and
+  /// advance time. See [`Clock`]. Defaults to the [`SystemClock`].
+  #[builder(default = Arc::new(SystemClock))]
+  clock: Arc<dyn Clock>,
+
+  /// Rolling activity counters, shared between clones of the downloader.
+  #[builder(default, setter(skip))]
+  stats: Arc<StatsCollector>,
+
+  /// Per-host delays learned from 429 responses, shared between clones of the downloader.
+  #[builder(default, setter(skip))]
+  pacer: Arc<HostPacer>,
+
+  /// Slots shared by the [`DownloadQueue`]s of this downloader and its clones.
+  #[builder(default = Arc::new(FairScheduler::new(max_concurrent)), setter(skip))]
+  scheduler: Arc<FairScheduler>,
+
+  /// Items that can currently be cancelled by tag, shared between clones of the downloader.
+  #[builder(default, setter(skip))]
+  cancel_registry: Arc<CancelRegistry>,
 }
 
 impl RobustDownloader {
-  /// Creates an exponential backoff configuration for retry attempts.
-  ///
-  /// The configuration uses the following parameters:
-  /// - Initial interval: 500ms
-  /// - Randomization factor: 15%
-  /// - Multiplier: 1.5x
-  /// - Maximum interval: 5 seconds
-  /// - Maximum elapsed time: 120 seconds
-  fn backoff(&self) -> ExponentialBackoff {
-    ExponentialBackoff {
-      // 初始等待 0.5 秒,加快重试速度
-      initial_interval: Duration::from_millis(500),
-      // 保持 15% 随机波动不变
-      randomization_factor: 0.15,
-      // 每次增加 1.5 倍,减缓增长速度
-      multiplier: 1.5,
-      // 最大等待 5 秒,缩短最大等待时间
-      max_interval: Duration::from_secs(5),
-      // 最多重试 1 分钟
-      max_elapsed_time: Some(Duration::from_secs(120)),
-      ..Default::default()
-    }
+  /// Creates a downloader tuned for `profile`, with everything else at its defaults.
+  ///
+  /// A preset sets the options that only make sense together — concurrency, connection
+  /// and read timeouts, and how failed attempts are retried — so picking one is enough to
+  /// adapt the downloader to a network or server. Use the [`builder`](Self::builder) to
+  /// set options individually.
+  ///
+  /// ```rust
+  /// use robust_downloader::{Profile, RobustDownloader};
+  ///
+  /// let downloader = RobustDownloader::preset(Profile::Conservative);
+  /// ```
+  pub fn preset(profile: Profile) -> Self {
+    Self::builder()
+      .max_concurrent(profile.max_concurrent())
+      .connect_timeout(profile.connect_timeout())
+      .timeout(profile.timeout())
+      .read_chunk_timeout(profile.read_chunk_timeout())
+      .retry_policy(profile.retry_policy())
+      .build()
+  }
+
+  /// Cancels every queued or running item tagged with `tag`, returning how many were hit.
+  ///
+  /// Cancelled items stop at their next chunk and do not fail the batch: they are
+  /// reported with [`DownloadOutcome::Cancelled`], and their partial files are handled per
+  /// the [`CleanupPolicy`]. Items started after this call are not affected.
+  pub fn cancel_tagged(&self, tag: &str) -> usize {
+    self.cancel_registry.cancel_tagged(tag)
+  }
+
+  /// Returns a snapshot of the downloads currently running on this downloader.
+  ///
+  /// Clones of a downloader share the same counters, so the snapshot covers every
+  /// batch started from any of them.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use robust_downloader::RobustDownloader;
+  ///
+  /// let downloader = RobustDownloader::builder().build();
+  /// let stats = downloader.stats();
+  /// assert_eq!(stats.active, 0);
+  /// ```
+  pub fn stats(&self) -> DownloadStats {
+    self.stats.snapshot(self.clock.now())
+  }
+
+  /// Returns the queue called `name`, registering it with `weight` or updating its weight.
+  ///
+  /// See [`DownloadQueue`] for how slots are shared between queues.
+  pub fn queue(&self, name: impl Into<String>, weight: u32) -> DownloadQueue {
+    let name: Arc<str> = name.into().into();
+    self.scheduler.set_weight(&name, weight);
+    DownloadQueue::new(self.clone(), name)
   }
 
   /// Downloads multiple files concurrently with progress tracking and retry capabilities.
@@ -116,12 +544,14 @@ impl RobustDownloader {
   /// # Arguments
   ///
   /// * `downloads` - A vector of tuples containing (url, target_path) pairs.
-  ///                 The URL specifies where to download from, and target_path is where to save the file.
+  ///   The URL specifies where to download from, and target_path is where to save the file.
   ///
   /// # Returns
   ///
-  /// Returns `Ok(())` if all downloads complete successfully, or a `ProgressDownloadError`
-  /// if any download fails after all retry attempts.
+  /// Returns one [`DownloadReport`] per item, in the order of `downloads`, if all
+  /// downloads complete successfully, or a `ProgressDownloadError` if any download fails
+  /// after all retry attempts. Whether the other items keep running after a failure
+  /// depends on the [`ErrorPolicy`].
   ///
   /// # Example
   ///
@@ -143,141 +573,1526 @@ impl RobustDownloader {
   /// # Ok(())
   /// # }
   /// ```
+  ///
+  /// # Cancellation
+  ///
+  /// Dropping the returned future stops every download at its next `.await` point. Items
+  /// that did not complete behave as if they had failed: observers see them end with
+  /// [`ProgressDownloadError::Cancelled`] (progress bars are abandoned) and their temp files
+  /// are kept or removed according to the configured [`CleanupPolicy`]. Bytes still
+  /// buffered in memory are discarded, but a kept temp file always holds a contiguous
+  /// prefix of the remote file, so the next run resumes from it. Completed downloads are
+  /// never affected.
   pub async fn download<U, P>(
     &self,
     downloads: Vec<DownloadItem<U, P>>,
-  ) -> Result<(), ProgressDownloadError>
+  ) -> Result<Vec<DownloadReport>, ProgressDownloadError>
   where
     U: IntoUrl + Clone,
     P: AsRef<Path>,
   {
-    let client = reqwest::Client::builder()
-      .connect_timeout(self.connect_timeout)
-      .pool_max_idle_per_host(0)
-      .build()?;
+    self.download_in(None, downloads, None).await
+  }
 
-    let mp = indicatif::MultiProgress::new();
+  /// Like [`download`](Self::download), but stops early once `token` is cancelled.
+  ///
+  /// Running items stop at their next chunk, flush what they received and keep their
+  /// partial files whatever the [`CleanupPolicy`], so a later call resumes them; items
+  /// that have not started are skipped. Once every item has stopped, the call returns
+  /// [`ProgressDownloadError::Cancelled`], even if some items completed in the meantime.
+  ///
+  /// ```rust,no_run
+  /// use robust_downloader::{CancellationToken, DownloadItem, RobustDownloader};
+  ///
+  /// # async fn quit_requested() {}
+  /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+  /// let token = CancellationToken::new();
+  /// let on_quit = token.clone();
+  /// tokio::spawn(async move {
+  ///   quit_requested().await;
+  ///   on_quit.cancel();
+  /// });
+  ///
+  /// let item = DownloadItem::builder()
+  ///   .url("https://example.com/big.iso")
+  ///   .target("big.iso")
+  ///   .build();
+  /// RobustDownloader::builder()
+  ///   .build()
+  ///   .download_with_cancel(vec![item], token)
+  ///   .await?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn download_with_cancel<U, P>(
+    &self,
+    downloads: Vec<DownloadItem<U, P>>,
+    token: CancellationToken,
+  ) -> Result<Vec<DownloadReport>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    self.download_in(None, downloads, Some(&token)).await
+  }
 
-    // 创建信号量来控制并发
-    let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
+  /// Like [`download`](Self::download), but one failed item does not abort the others:
+  /// every item runs to completion and gets a [`DownloadResult`] with its report or its
+  /// error, so callers can decide what to retry.
+  ///
+  /// Results come in the order of `downloads`, however the items were scheduled, and carry
+  /// the item's position as their `index`.
+  ///
+  /// ```rust,no_run
+  /// use robust_downloader::{DownloadItem, RobustDownloader};
+  ///
+  /// # async fn run(items: Vec<DownloadItem<String, String>>) -> Result<(), Box<dyn std::error::Error>> {
+  /// let results = RobustDownloader::builder().build().download_all(items).await?;
+  /// for failed in results.iter().filter(|result| !result.is_ok()) {
+  ///   eprintln!("{} failed after {:?}", failed.url, failed.elapsed);
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn download_all<U, P>(
+    &self,
+    downloads: Vec<DownloadItem<U, P>>,
+  ) -> Result<Vec<DownloadResult>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    self
+      .run_batch(None, downloads, None, ErrorPolicy::ContinueAll)
+      .await
+  }
 
-    let futures = downloads.into_iter().map(|item| {
-      let sem = semaphore.clone();
-      let client = client.clone();
-      let mp = mp.clone();
+  /// Downloads a single item and returns its report.
+  ///
+  /// ```rust,no_run
+  /// use robust_downloader::{DownloadItem, RobustDownloader};
+  ///
+  /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+  /// let item = DownloadItem::builder()
+  ///   .url("https://example.com/file.zip")
+  ///   .target("local/file.zip")
+  ///   .build();
+  /// let report = RobustDownloader::builder().build().download_one(item).await?;
+  /// println!("{} bytes in {:?}", report.bytes(), report.elapsed);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn download_one<U, P>(
+    &self,
+    item: DownloadItem<U, P>,
+  ) -> Result<DownloadReport, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    Ok(
+      self
+        .download(vec![item])
+        .await?
+        .pop()
+        .expect("one report per item"),
+    )
+  }
 
-      async move {
-        // 获取信号量许可
-        let _permit = sem.acquire().await?;
-        self.download_with_retry(&client, &mp, item).await
-      }
+  /// Downloads the parts of `group` and joins them into its target, see [`DownloadGroup`].
+  ///
+  /// A part that fails for good fails the whole group, which then stops the other parts;
+  /// what was downloaded so far stays in the parts directory, and partial parts resume on
+  /// the next call.
+  pub async fn download_group<U, P>(
+    &self,
+    group: DownloadGroup<U, P>,
+  ) -> Result<DownloadReport, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let started = self.clock.now();
+    let target = group.target.as_ref().to_path_buf();
+    let url = group
+      .parts
+      .first()
+      .map(|part| part.as_str().to_string())
+      .unwrap_or_default();
+    let parts_dir = group::parts_dir(&target);
+    let part_files = (1..=group.parts.len())
+      .map(|number| parts_dir.join(format!("{number:03}")))
+      .collect::<Vec<_>>();
+
+    // Parts are plain local files, whatever the downloader does with finished items.
+    let mut downloader = self.clone();
+    downloader.storage = Arc::new(LocalStorage);
+    downloader.error_policy = ErrorPolicy::FailFast;
+    downloader.overwrite = OverwritePolicy::Overwrite;
+    // The space is checked for the group as a whole, which is nothing without all parts.
+    downloader.check_space = false;
+    downloader.best_effort_space = false;
+    let observer = self.observer().map(|observer| {
+      let item = ObservedItem::new(url.clone(), target.clone(), group.tags.clone());
+      Arc::new(GroupObserver::new(observer, item))
     });
+    if let Some(observer) = &observer {
+      observer.start();
+      downloader.progress_observer = Some(observer.clone());
+    }
 
-    futures::future::try_join_all(futures).await?;
-    mp.set_move_cursor(true);
-    mp.clear()?;
-
-    Ok(())
-  }
-
-  /// Creates a new progress bar with a standardized style for download tracking.
-  ///
-  /// The progress bar includes:
-  /// - A green spinner
-  /// - Elapsed time
-  /// - A 25-character wide progress bar
-  /// - Downloaded bytes / Total bytes
-  /// - Additional status messages
-  fn prepare_progress_bar(&self) -> ProgressBar {
-    let progress_bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::stdout());
-    progress_bar.set_style(
-            indicatif::ProgressStyle::with_template(
-                "{spinner:.green} [{elapsed_precise}] {bar:25.green/white.dim} {bytes}/{total_bytes} {wide_msg:.dim}",
-            )
-            .unwrap()
-            .progress_chars("━━"),
-        );
-    progress_bar
+    let parts = group
+      .parts
+      .into_iter()
+      .zip(&part_files)
+      .map(|(part, file)| {
+        DownloadItem::builder()
+          .url(part)
+          .target(file.clone())
+          .tags(group.tags.clone())
+          .build()
+      })
+      .collect::<Vec<_>>();
+
+    let result: Result<_, ProgressDownloadError> = async {
+      if self.check_space || self.best_effort_space {
+        self.check_group_space(&parts, &parts_dir, &target).await?;
+      }
+      let reports = downloader.download(parts).await?;
+      // A part cancelled by tag leaves nothing complete to join.
+      if reports
+        .iter()
+        .any(|report| report.outcome == DownloadOutcome::Cancelled)
+      {
+        return Err(ProgressDownloadError::Cancelled);
+      }
+
+      let joined = parts_dir.join("joined");
+      group::join(&part_files, &joined).await?;
+
+      #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha2",
+        feature = "sha3",
+        feature = "blake2",
+        feature = "blake3"
+      ))]
+      if let Some(integrity) = &group.integrity {
+        let actual = hashery::Hashery::builder()
+          .algorithm(integrity.algorithm())
+          .build()
+          .digest(&joined)
+          .await?;
+        if actual != integrity.value() {
+          tokio::fs::remove_file(&joined).await?;
+          return Err(ProgressDownloadError::IntegrityHash {
+            expect: integrity.value().to_string(),
+            actual,
+            actual_file: joined,
+            target_file: target.clone(),
+          });
+        }
+      }
+
+      self.storage.place(&joined, &target).await?;
+      tokio::fs::remove_dir_all(&parts_dir).await?;
+      info!(
+        "joined {} parts into {}",
+        part_files.len(),
+        target.display()
+      );
+
+      Ok(group::report(
+        url.clone(),
+        target.clone(),
+        group.tags.clone(),
+        reports,
+        self.clock.elapsed(started),
+      ))
+    }
+    .await;
+
+    if let Some(observer) = &observer {
+      observer.finish(&result);
+    }
+    result
   }
 
-  /// Attempts to download a single file with automatic retries on failure.
+  /// Asks the server about `url` with a `HEAD` request instead of downloading it, e.g. to
+  /// plan disk space or decide what needs refreshing.
   ///
-  /// This method implements the retry logic using exponential backoff and
-  /// handles temporary file management to ensure atomic file operations.
+  /// The request is made like a download's: same client, authentication, request signer,
+  /// redirect policy and host pacing, and retried per the downloader's retry policy.
   ///
-  /// # Arguments
+  /// ```rust,no_run
+  /// use robust_downloader::RobustDownloader;
   ///
-  /// * `client` - The HTTP client to use for the download
-  /// * `mp` - Multi-progress bar for tracking multiple downloads
-  /// * `url` - The URL to download from
-  /// * `target` - The local path where the file should be saved
+  /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+  /// let remote = RobustDownloader::builder()
+  ///   .build()
+  ///   .head("https://example.com/file.zip")
+  ///   .await?;
+  /// println!("{:?} bytes, served by {}", remote.size, remote.final_url);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn head<U>(&self, url: U) -> Result<RemoteMetadata, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+  {
+    let items = [DownloadItem::builder()
+      .url(url)
+      .target(PathBuf::new())
+      .build()];
+    let item = &items[0];
+    let client = match &self.client {
+      Some(client) => client.clone(),
+      None => self.build_client(&items).await?,
+    };
+
+    let runner = self.request_runner(client, item);
+    backoff::future::retry(self.retry_policy.backoff(&self.clock), || async {
+      runner
+        .head()
+        .await
+        .map_err(ProgressDownloadError::into_backoff_err)
+    })
+    .await
+  }
+
+  /// Compares a few ranges of `local` with the file at `url` instead of downloading it
+  /// again, to cheaply tell whether a copy of a huge file has diverged, e.g. before deciding
+  /// to refresh a multi-hundred-GB object. See [`SpotCheck`] for which ranges.
   ///
-  /// # Returns
+  /// Returns `false` as soon as a range differs, when the sizes differ, and when there is no
+  /// file at `local`. A match only means the sampled ranges agree. The requests are made
+  /// like a download's and retried per the downloader's retry policy; a server that does
+  /// not serve ranges fails the check with [`ProgressDownloadError::RangesNotSupported`].
   ///
-  /// Returns `Ok(())` if the download succeeds, or a `ProgressDownloadError`
-  /// if the download fails after all retry attempts.
-  async fn download_with_retry<U, P>(
+  /// ```rust,no_run
+  /// use robust_downloader::{RobustDownloader, SpotCheck};
+  ///
+  /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+  /// let downloader = RobustDownloader::builder().build();
+  /// let url = "https://example.com/dataset.tar";
+  /// if !downloader.spot_check(url, "dataset.tar", &SpotCheck::default()).await? {
+  ///   println!("dataset.tar is stale");
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn spot_check<U>(
     &self,
-    client: &reqwest::Client,
-    mp: &indicatif::MultiProgress,
-    item: DownloadItem<U, P>,
-  ) -> Result<(), ProgressDownloadError>
+    url: U,
+    local: impl AsRef<Path>,
+    check: &SpotCheck,
+  ) -> Result<bool, ProgressDownloadError>
   where
     U: IntoUrl + Clone,
-    P: AsRef<Path>,
   {
-    let target_file = item.target.as_ref();
+    let local = local.as_ref();
+    let Some(size) = tokio::fs::metadata(local)
+      .await
+      .ok()
+      .filter(|metadata| metadata.is_file())
+      .map(|metadata| metadata.len())
+    else {
+      return Ok(false);
+    };
 
-    let Some(file_name) = target_file.file_name() else {
-      return Err(ProgressDownloadError::Path {
-        path: target_file.to_string_lossy().to_string(),
-      });
+    let items = [DownloadItem::builder()
+      .url(url)
+      .target(PathBuf::new())
+      .build()];
+    let item = &items[0];
+    let client = match &self.client {
+      Some(client) => client.clone(),
+      None => self.build_client(&items).await?,
     };
+    let runner = self.request_runner(client, item);
+
+    let backoff = self.retry_policy.backoff(&self.clock);
+    let remote = backoff::future::retry(backoff, || async {
+      runner
+        .head()
+        .await
+        .map_err(ProgressDownloadError::into_backoff_err)
+    })
+    .await?;
+    if remote.size.is_some_and(|remote| remote != size) {
+      return Ok(false);
+    }
 
-    let temp_dir = env::temp_dir();
-    let temp_file = temp_dir.join(file_name);
+    let ranges = check.ranges(size);
+    backoff::future::retry(self.retry_policy.backoff(&self.clock), || async {
+      runner
+        .spot_check(local, &ranges)
+        .await
+        .map_err(ProgressDownloadError::into_backoff_err)
+    })
+    .await
+  }
 
-    let progress_bar = self.prepare_progress_bar();
-    let progress_bar = mp.add(progress_bar);
+  /// Hashes the file at `url` with `algorithm` without storing it, e.g. to audit a mirror
+  /// against a manifest without the disk space for its files.
+  ///
+  /// The body is streamed like a download's, with the same client, authentication, request
+  /// signer, redirect policy, bandwidth limit and progress reporting, and retried per the
+  /// downloader's retry policy; a retry continues hashing where the failed attempt stopped
+  /// when the server supports ranges. Returns the digest as lowercase hex, like
+  /// [`Integrity::value`].
+  ///
+  /// ```rust,no_run
+  /// use robust_downloader::{Integrity, RobustDownloader};
+  ///
+  /// # #[cfg(feature = "sha2")]
+  /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+  /// let listed = Integrity::SHA256(
+  ///   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+  /// );
+  /// let digest = RobustDownloader::builder()
+  ///   .build()
+  ///   .hash_remote("https://mirror.example.com/tool.tar.gz", listed.algorithm())
+  ///   .await?;
+  /// if digest != listed.value() {
+  ///   println!("the mirror serves a different tool.tar.gz");
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  pub async fn hash_remote<U>(
+    &self,
+    url: U,
+    algorithm: hashery::Algorithm,
+  ) -> Result<String, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+  {
+    let items = [DownloadItem::builder()
+      .url(url)
+      .target(PathBuf::new())
+      .build()];
+    let item = &items[0];
+    let client = match &self.client {
+      Some(client) => client.clone(),
+      None => self.build_client(&items).await?,
+    };
 
-    let task_runner = DownloadTaskRunner::builder()
-      .client(client.clone())
-      .progress_bar(progress_bar)
-      .item(item)
-      .tmp_file(temp_file)
-      .read_chunk_timeout(self.read_chunk_timeout)
-      .timeout(self.timeout)
-      .flush_threshold(self.flush_threshold)
-      .build();
+    let observer = Arc::new(ItemObserver::new(
+      self.observer(),
+      self.progress_interval,
+      self.clock.clone(),
+      item.url.as_str().to_string(),
+      PathBuf::new(),
+      Vec::new(),
+    ));
+    observer.start();
+    let runner = self.observed_request_runner(client, item, observer.clone());
+    let new_hasher = hasher::StreamHasher::maker(algorithm);
+    let resume = std::sync::Mutex::new(None);
 
-    backoff::future::retry(self.backoff(), || async {
-      task_runner
-        .download()
+    let backoff = self.retry_policy.backoff(&self.clock);
+    let digest = backoff::future::retry(backoff, || async {
+      runner
+        .hash(&resume, new_hasher)
         .await
         .map_err(ProgressDownloadError::into_backoff_err)
     })
-    .await?;
+    .await;
+    match &digest {
+      Ok(_) => observer.complete(DownloadOutcome::Downloaded),
+      Err(e) => observer.fail(e),
+    }
+    digest
+  }
 
-    Ok(())
+  /// A runner that only sends requests for `item`, the way a download of it would: nothing
+  /// is written and nobody is observing.
+  fn request_runner<'a, U>(
+    &self,
+    client: reqwest::Client,
+    item: &'a DownloadItem<U, PathBuf>,
+  ) -> DownloadTaskRunner<'a, U, PathBuf, PathBuf>
+  where
+    U: IntoUrl + Clone,
+  {
+    let observer = ItemObserver::new(
+      None,
+      self.progress_interval,
+      self.clock.clone(),
+      item.url.as_str().to_string(),
+      PathBuf::new(),
+      Vec::new(),
+    );
+    self.observed_request_runner(client, item, Arc::new(observer))
   }
-}
 
-#[cfg(test)]
-mod tests {
+  /// A [`request_runner`](Self::request_runner) reporting to `observer`.
+  fn observed_request_runner<'a, U>(
+    &self,
+    client: reqwest::Client,
+    item: &'a DownloadItem<U, PathBuf>,
+    observer: Arc<ItemObserver>,
+  ) -> DownloadTaskRunner<'a, U, PathBuf, PathBuf>
+  where
+    U: IntoUrl + Clone,
+  {
+    DownloadTaskRunner::builder()
+      .client(client)
+      .observer(observer)
+      .item(item)
+      .url(&item.url)
+      .tmp_file(PathBuf::new())
+      .read_chunk_timeout(self.read_chunk_timeout)
+      .timeout(self.timeout)
+      .flush_threshold(self.flush_threshold)
+      .stats(self.stats.clone())
+      .storage(self.storage.clone())
+      .redirect_policy(self.redirect_policy.clone())
+      .pacer(self.pacer.clone())
+      .clock(self.clock.clone())
+      .auth(self.auth.clone())
+      .request_signer(self.request_signer.clone())
+      .bandwidth(self.max_bandwidth.clone())
+      .build()
+  }
 
-  use super::*;
+  /// Looks up the integrity of an item that has an [`IntegrityFile`] or an integrity URL
+  /// but no integrity of its own, under the target's file name and then the URL's.
+  #[cfg(feature = "sha2")]
+  async fn listed_integrity<U, P>(
+    &self,
+    client: &reqwest::Client,
+    item: &DownloadItem<U, P>,
+  ) -> Result<Option<Integrity>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    if item.integrity.is_some() {
+      return Ok(None);
+    }
+    let (file, lenient) = match (&item.integrity_file, &item.integrity_url) {
+      (Some(file), _) => (file.clone(), false),
+      (None, Some(url)) => (IntegrityFile::Url(url.clone()), true),
+      (None, None) => return Ok(None),
+    };
 
-  #[tokio::test]
-  async fn test_download() {
-    let downloader = RobustDownloader::builder()
-      .connect_timeout(Duration::from_secs(1))
-      .timeout(Duration::from_secs(60))
-      .flush_threshold(1024 * 1024)
-      .build();
-    let downloads = vec![
-      DownloadItem::builder()
-        .url("https://nodejs.org/dist/v23.9.0/node-v23.9.0.tar.gz")
-        .target("local/node-v23.9.0.tar.gz")
+    let list = match &file {
+      IntegrityFile::Path(path) => tokio::fs::read_to_string(path).await?,
+      IntegrityFile::Url(url) => {
+        let items = [DownloadItem::builder()
+          .url(url.as_str())
+          .target(PathBuf::new())
+          .build()];
+        let runner = self.request_runner(client.clone(), &items[0]);
+        let policy = item.retry_policy.as_ref().unwrap_or(&self.retry_policy);
+        backoff::future::retry(policy.backoff(&self.clock), || async {
+          runner
+            .text()
+            .await
+            .map_err(ProgressDownloadError::into_backoff_err)
+        })
+        .await?
+      }
+    };
+
+    let target = Some(item.target.as_ref())
+      .filter(|_| item.content_address.is_none() && !item.target_is_dir)
+      .and_then(Path::file_name)
+      .map(|name| name.to_string_lossy().to_string());
+    let from_url = Url::parse(item.url.as_str()).ok().and_then(|url| {
+      let policy = item.filename_policy.unwrap_or(self.filename_policy);
+      policy.file_name_from_url(&url)
+    });
+    let mut names = target.into_iter().chain(from_url).collect::<Vec<_>>();
+    names.dedup();
+
+    let found = sums::find(&list, &names).or_else(|| {
+      Some(list.as_str())
+        .filter(|_| lenient)
+        .and_then(sums::single)
+    });
+    match found {
+      Some(integrity) => {
+        debug!("checksum of {} listed in {}", item.url.as_str(), file);
+        Ok(Some(integrity))
+      }
+      None => Err(ProgressDownloadError::ChecksumNotListed {
+        list: file.to_string(),
+        names,
+      }),
+    }
+  }
+
+  /// The signature of an item whose [`PgpSignature`] is at a URL, fetched so it is checked
+  /// against every attempt without fetching it again.
+  #[cfg(feature = "pgp")]
+  async fn fetched_signature<U, P>(
+    &self,
+    client: &reqwest::Client,
+    item: &DownloadItem<U, P>,
+  ) -> Result<Option<PgpSignature>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let Some(signature) = &item.signature else {
+      return Ok(None);
+    };
+    let SignatureSource::Url(url) = &signature.signature else {
+      return Ok(None);
+    };
+
+    let items = [DownloadItem::builder()
+      .url(url.as_str())
+      .target(PathBuf::new())
+      .build()];
+    let runner = self.request_runner(client.clone(), &items[0]);
+    let policy = item.retry_policy.as_ref().unwrap_or(&self.retry_policy);
+    let bytes = backoff::future::retry(policy.backoff(&self.clock), || async {
+      runner
+        .bytes()
+        .await
+        .map_err(ProgressDownloadError::into_backoff_err)
+    })
+    .await?;
+    debug!(
+      "fetched the signature of {} from {}",
+      item.url.as_str(),
+      url
+    );
+
+    Ok(Some(PgpSignature {
+      signature: SignatureSource::Bytes(bytes),
+      keyring: signature.keyring.clone(),
+    }))
+  }
+
+  /// Downloads `item`, verifies it and only then writes its content to stdout.
+  ///
+  /// Meant for "fetch and pipe" provisioning steps (`... | sh`): the content is staged in a
+  /// temp file and checked against the item's integrity before a single byte reaches stdout,
+  /// so a truncated or tampered download can never be half-executed. Items with no
+  /// integrity, integrity file, integrity URL, verifier or signature are rejected with
+  /// [`ProgressDownloadError::MissingIntegrity`]. Progress bars are not drawn for this call,
+  /// and the item's target is not written.
+  ///
+  /// Only [`DownloadOutcome::Downloaded`] items are written; the report tells which.
+  pub async fn download_verified_to_stdout<U, P>(
+    &self,
+    item: DownloadItem<U, P>,
+  ) -> Result<DownloadReport, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    self
+      .download_verified_to(item, &mut tokio::io::stdout())
+      .await
+  }
+
+  /// Downloads `item`, verifies it and only then writes its content to `writer`.
+  async fn download_verified_to<U, P, W>(
+    &self,
+    mut item: DownloadItem<U, P>,
+    writer: &mut W,
+  ) -> Result<DownloadReport, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+    W: tokio::io::AsyncWrite + Unpin,
+  {
+    use tokio::io::AsyncWriteExt;
+
+    #[cfg(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    ))]
+    let integrity = item.integrity.is_some();
+    #[cfg(not(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    )))]
+    let integrity = false;
+    #[cfg(feature = "sha2")]
+    let listed = item.integrity_file.is_some() || item.integrity_url.is_some();
+    #[cfg(not(feature = "sha2"))]
+    let listed = false;
+    #[cfg(feature = "pgp")]
+    let signed = item.signature.is_some();
+    #[cfg(not(feature = "pgp"))]
+    let signed = false;
+    if !integrity && !listed && !signed && item.verifier.is_none() {
+      return Err(ProgressDownloadError::MissingIntegrity {
+        url: item.url.as_str().to_string(),
+      });
+    }
+
+    let holding = Arc::new(storage::HoldingStorage::default());
+    let mut downloader = self.clone();
+    downloader.quiet = true;
+    // The target on disk is not what gets written, so it neither stands in for a download,
+    // nor is in the way of one, nor gets marked complete.
+    downloader.skip_valid_targets = false;
+    downloader.overwrite = OverwritePolicy::Overwrite;
+    downloader.completion_marker = None;
+    item.overwrite = None;
+    #[cfg(feature = "unpack")]
+    {
+      item.unpack = None;
+    }
+    downloader.storage = holding.clone();
+
+    let report = downloader.download_one(item).await?;
+
+    if let Some(verified) = holding.take() {
+      let written = async {
+        let mut file = tokio::fs::File::open(&verified).await?;
+        tokio::io::copy(&mut file, writer).await?;
+        writer.flush().await
+      }
+      .await;
+      tokio::fs::remove_file(&verified).await?;
+      written?;
+    }
+
+    Ok(report)
+  }
+
+  /// Downloads a batch, taking slots from `queue` of the shared scheduler if given and
+  /// from a semaphore of the batch's own otherwise.
+  pub(crate) async fn download_in<U, P>(
+    &self,
+    queue: Option<&Arc<str>>,
+    downloads: Vec<DownloadItem<U, P>>,
+    token: Option<&CancellationToken>,
+  ) -> Result<Vec<DownloadReport>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    self
+      .run_batch(queue, downloads, token, self.error_policy)
+      .await?
+      .into_iter()
+      .map(|result| result.result)
+      .collect()
+  }
+
+  /// Runs a batch to completion. A failed item that makes `error_policy` abort the batch is
+  /// returned as the error; otherwise every item gets its [`DownloadResult`].
+  async fn run_batch<U, P>(
+    &self,
+    queue: Option<&Arc<str>>,
+    downloads: Vec<DownloadItem<U, P>>,
+    token: Option<&CancellationToken>,
+    error_policy: ErrorPolicy,
+  ) -> Result<Vec<DownloadResult>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let left_out = if self.check_space || self.best_effort_space {
+      self.plan_space(&downloads).await?
+    } else {
+      HashSet::new()
+    };
+
+    let client = match &self.client {
+      Some(client) => client.clone(),
+      None => self.build_client(&downloads).await?,
+    };
+
+    let observer = self.observer();
+
+    // 创建信号量来控制并发
+    let ramp = self
+      .auto_ramp
+      .clone()
+      .map(|policy| Ramp::new(policy, self.max_concurrent));
+    let semaphore = match &ramp {
+      Some(ramp) => ramp.semaphore(),
+      None => Arc::new(Semaphore::new(self.max_concurrent)),
+    };
+    let ramp = ramp.as_ref();
+    let failures = &AtomicUsize::new(0);
+
+    #[cfg_attr(not(any(feature = "sha2", feature = "pgp")), allow(unused_mut))]
+    let futures = downloads.into_iter().enumerate().map(|(index, mut item)| {
+      let sem = semaphore.clone();
+      let client = client.clone();
+      let observer = observer.clone();
+      let left_out = left_out.contains(&index);
+
+      async move {
+        if left_out {
+          return Ok(self.no_space(observer, index, &item));
+        }
+        #[cfg(all(
+          feature = "unpack",
+          any(
+            feature = "md5",
+            feature = "sha1",
+            feature = "sha2",
+            feature = "sha3",
+            feature = "blake2",
+            feature = "blake3"
+          )
+        ))]
+        unpack::defer_decompressed_integrity(&mut item);
+
+        let registration = self.cancel_registry.register(&item.tags);
+        let queued = self.stats.enqueue();
+        let cancelled = registration.flag();
+        let (_slot, permit) = loop {
+          // 获取信号量许可
+          let slot = match queue {
+            Some(queue) => Some(self.scheduler.acquire(queue).await),
+            None => None,
+          };
+          let permit = match queue {
+            Some(_) => None,
+            None => Some(sem.acquire().await?),
+          };
+
+          let wait = match &self.start_policy {
+            Some(policy)
+              if !cancelled.load(Ordering::Relaxed)
+                && !token.is_some_and(CancellationToken::is_cancelled) =>
+            {
+              let candidate = StartCandidate {
+                url: item.url.as_str(),
+                target: item.target.as_ref(),
+                tags: &item.tags,
+                size_hint: item.expected_size.or(item.size_hint),
+              };
+              match policy.should_start(candidate).await {
+                StartDecision::Start => None,
+                StartDecision::Defer(wait) => Some(wait),
+              }
+            }
+            _ => None,
+          };
+          let Some(wait) = wait else {
+            break (slot, permit);
+          };
+
+          // Hand the slot back while waiting.
+          drop((slot, permit));
+          debug!("deferring {} for {:?}", item.url.as_str(), wait);
+          tokio::time::sleep(wait).await;
+        };
+        let _active = queued.activate();
+        let started = self.clock.now();
+        let url = item.url.as_str().to_string();
+        let target = item.target.as_ref().to_path_buf();
+        let observed = Arc::new(ItemObserver::new(
+          observer,
+          self.progress_interval,
+          self.clock.clone(),
+          url.clone(),
+          target.clone(),
+          item.tags.clone(),
+        ));
+        observed.start();
+
+        // A panic (e.g. in a hook) only fails its own item instead of tearing down the batch.
+        let download = async {
+          #[cfg(feature = "sha2")]
+          if let Some(integrity) = self.listed_integrity(&client, &item).await? {
+            item.integrity = Some(integrity);
+          }
+          #[cfg(feature = "pgp")]
+          if let Some(signature) = self.fetched_signature(&client, &item).await? {
+            item.signature = Some(signature);
+          }
+          self
+            .download_with_retry(&client, observed.clone(), index, &item, cancelled, token)
+            .await
+        };
+        let outcome = tokio::select! {
+          outcome = AssertUnwindSafe(download).catch_unwind() => outcome,
+          _ = observed.heartbeat() => unreachable!("the heartbeat never ends"),
+        }
+        .unwrap_or_else(|payload| Err(ProgressDownloadError::from_panic(payload).into()));
+        #[cfg(feature = "unpack")]
+        let outcome = match outcome {
+          Ok(report) => Self::unpack(&item, report).await,
+          failure => failure,
+        };
+        match &outcome {
+          Ok(report) => observed.complete(report.outcome),
+          Err(failure) => observed.fail(&failure.error),
+        }
+        if let Some(ramp) = ramp {
+          let attempts = match &outcome {
+            Ok(report) => &report.attempts,
+            Err(failure) => &failure.attempts,
+          };
+          ramp.finish(permit, attempts);
+        }
+
+        match outcome {
+          Ok(report) => Ok(DownloadResult {
+            index,
+            url,
+            target,
+            bytes: report.bytes(),
+            elapsed: report.elapsed,
+            result: Ok(report),
+          }),
+          Err(failure) => {
+            let failed = failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if error_policy.aborts(failed) {
+              // `try_join_all` drops the other items' futures right away: their connections
+              // are closed and their partial files handled by their `PartialGuard`.
+              warn!("{} failed, aborting the batch: {}", url, failure.error);
+              return Err(failure.error);
+            }
+            Ok(DownloadResult {
+              index,
+              url,
+              target,
+              bytes: failure.attempts.iter().map(|attempt| attempt.bytes).sum(),
+              elapsed: self.clock.elapsed(started),
+              result: Err(failure.error),
+            })
+          }
+        }
+      }
+    });
+
+    let reports = futures::future::try_join_all(futures).await?;
+
+    if token.is_some_and(CancellationToken::is_cancelled) {
+      return Err(ProgressDownloadError::Cancelled);
+    }
+
+    Ok(reports)
+  }
+
+  /// Builds the client for a batch when none was given.
+  async fn build_client<U, P>(
+    &self,
+    downloads: &[DownloadItem<U, P>],
+  ) -> Result<reqwest::Client, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+  {
+    // Redirects are followed by the task runner so headers can be filtered per hop. Bodies
+    // are stored as sent, so a `Content-Encoding` must not be undone even if another crate
+    // in the tree enables reqwest's decoders.
+    let mut client = reqwest::Client::builder()
+      .connect_timeout(self.connect_timeout)
+      .pool_max_idle_per_host(0)
+      .redirect(reqwest::redirect::Policy::none())
+      .no_gzip()
+      .no_brotli()
+      .no_deflate()
+      .tcp_keepalive(self.tcp_keepalive);
+
+    #[cfg(feature = "http2")]
+    if let Some(interval) = self.http2_ping_interval {
+      client = client
+        .http2_keep_alive_interval(interval)
+        .http2_keep_alive_while_idle(true);
+    }
+
+    if let Some(ttl) = self.dns_cache_ttl {
+      let resolver = CachingResolver::new(ttl, self.clock.clone());
+      let hosts = downloads
+        .iter()
+        .filter_map(|item| reqwest::Url::parse(item.url.as_str()).ok())
+        .filter_map(|url| url.host_str().map(ToString::to_string))
+        .collect::<HashSet<_>>();
+      resolver.prefetch(hosts).await;
+      client = client.dns_resolver(Arc::new(resolver));
+    }
+
+    Ok(client.build()?)
+  }
+
+  /// Who is told about the progress of this downloader's items: the configured observer,
+  /// else the parent process of a worker, else the terminal unless quiet.
+  fn observer(&self) -> Option<Arc<dyn ProgressObserver>> {
+    if let Some(observer) = &self.progress_observer {
+      return Some(observer.clone());
+    }
+    #[cfg(all(unix, feature = "ipc"))]
+    if let Some(forwarder) = &self.progress_forwarder {
+      return Some(Arc::new(forwarder.clone().with_clock(self.clock.clone())));
+    }
+    #[cfg(feature = "progress-bars")]
+    if !self.quiet {
+      return Some(Arc::new(TerminalProgress::new()));
+    }
+    None
+  }
+
+  /// Records the digest `item` was just verified against for its placed `target`.
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  async fn record_digest<U, P>(&self, item: &DownloadItem<U, P>, target: &Path) {
+    let (Some(cache), Some(integrity)) = (&self.digest_cache, &item.integrity) else {
+      return;
+    };
+    // Not every storage places the file on the local disk.
+    if let Err(e) = cache.record(target, integrity, integrity.value()).await {
+      debug!("not caching the digest of {}: {}", target.display(), e);
+    }
+  }
+
+  /// Installs the archive of an item with [`Unpack`] once it is in place and verified.
+  #[cfg(feature = "unpack")]
+  async fn unpack<U, P>(
+    item: &DownloadItem<U, P>,
+    report: DownloadReport,
+  ) -> Result<DownloadReport, ItemFailure> {
+    let Some(unpack) = &item.unpack else {
+      return Ok(report);
+    };
+    // A skipped target was never checked, a cancelled one is not there.
+    if !matches!(
+      report.outcome,
+      DownloadOutcome::Downloaded | DownloadOutcome::NotModified | DownloadOutcome::AlreadyValid
+    ) {
+      return Ok(report);
+    }
+
+    match unpack.install(&report.target).await {
+      Ok(installed) => {
+        info!(
+          "installed {} into {}",
+          report.target.display(),
+          installed.display()
+        );
+        Ok(report)
+      }
+      Err(error) => Err(ItemFailure {
+        error,
+        attempts: report.attempts,
+      }),
+    }
+  }
+
+  /// Checks that `downloads` fit on the filesystems they write to, see `check_space`.
+  /// Returns the positions of the items left out for lack of space.
+  async fn plan_space<U, P>(
+    &self,
+    downloads: &[DownloadItem<U, P>],
+  ) -> Result<HashSet<usize>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let mut volumes = Volumes::default();
+    let needs = self.space_needs(&mut volumes, downloads).await?;
+    volumes.plan(&needs, self.best_effort_space)
+  }
+
+  /// Checks that a group fits on disk as a whole, see `check_space`: its parts, the file
+  /// they are joined into next to them, and the placed file if that is on another
+  /// filesystem. Best effort or not, a group that does not fit fails.
+  async fn check_group_space<U>(
+    &self,
+    parts: &[DownloadItem<U, PathBuf>],
+    parts_dir: &Path,
+    target: &Path,
+  ) -> Result<(), ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+  {
+    let mut volumes = Volumes::default();
+    let mut needs = self.space_needs(&mut volumes, parts).await?;
+
+    let size = needs.iter().map(|need| need.size).sum::<u64>();
+    let joined_on = volumes.locate(parts_dir).await?;
+    let placed_on = volumes
+      .locate(target.parent().unwrap_or(Path::new("")))
+      .await?;
+    let mut bytes = vec![(joined_on.clone(), size)];
+    if placed_on != joined_on {
+      bytes.push((placed_on, size));
+    }
+    needs.push(SpaceNeed {
+      url: target.display().to_string(),
+      size,
+      bytes,
+    });
+
+    volumes.plan(&needs, false).map(drop)
+  }
+
+  /// What each of `downloads` needs on the filesystems it writes to, see `check_space`.
+  async fn space_needs<U, P>(
+    &self,
+    volumes: &mut Volumes,
+    downloads: &[DownloadItem<U, P>],
+  ) -> Result<Vec<SpaceNeed>, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    use futures::StreamExt;
+
+    let sizes = futures::stream::iter(downloads)
+      .map(|item| async move {
+        match item.expected_size.or(item.size_hint) {
+          Some(size) => Some(size),
+          None => self
+            .head(item.url.clone())
+            .await
+            .ok()
+            .and_then(|remote| remote.size),
+        }
+      })
+      .buffered(self.max_concurrent.max(1))
+      .collect::<Vec<_>>()
+      .await;
+
+    let mut needs = Vec::with_capacity(downloads.len());
+    for (item, size) in downloads.iter().zip(sizes) {
+      let url = item.url.as_str().to_string();
+      let Some(size) = size else {
+        debug!(
+          "size of {} unknown, not counted against the free space",
+          url
+        );
+        needs.push(SpaceNeed {
+          url,
+          size: 0,
+          bytes: Vec::new(),
+        });
+        continue;
+      };
+
+      let temp_file = self.temp_file(item)?;
+      // Transformed partials are not resumed but started over.
+      let partial = if item.transforms.is_empty() {
+        tokio::fs::metadata(&temp_file)
+          .await
+          .map_or(0, |metadata| metadata.len())
+      } else {
+        0
+      };
+      let staged_on = volumes
+        .locate(temp_file.parent().unwrap_or(Path::new("")))
+        .await?;
+      let placed_on = volumes.locate(self.target_dir(item)).await?;
+      let mut bytes = vec![(staged_on.clone(), size.saturating_sub(partial))];
+      // Placing across filesystems copies the file before the temp file is removed.
+      if placed_on != staged_on {
+        bytes.push((placed_on, size));
+      }
+      needs.push(SpaceNeed { url, size, bytes });
+    }
+    Ok(needs)
+  }
+
+  /// The result of an item left out of its batch for lack of space.
+  fn no_space<U, P>(
+    &self,
+    observer: Option<Arc<dyn ProgressObserver>>,
+    index: usize,
+    item: &DownloadItem<U, P>,
+  ) -> DownloadResult
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let url = item.url.as_str().to_string();
+    let target = item.target.as_ref().to_path_buf();
+    let observed = ItemObserver::new(
+      observer,
+      self.progress_interval,
+      self.clock.clone(),
+      url.clone(),
+      target.clone(),
+      item.tags.clone(),
+    );
+    observed.start();
+    observed.complete(DownloadOutcome::NoSpace);
+
+    DownloadResult {
+      index,
+      url: url.clone(),
+      target: target.clone(),
+      bytes: 0,
+      elapsed: Duration::ZERO,
+      result: Ok(DownloadReport {
+        index,
+        url,
+        target,
+        outcome: DownloadOutcome::NoSpace,
+        tags: item.tags.clone(),
+        attempts: Vec::new(),
+        elapsed: Duration::ZERO,
+      }),
+    }
+  }
+
+  /// The directory `item` is placed in.
+  fn target_dir<'a, U, P>(&self, item: &'a DownloadItem<U, P>) -> &'a Path
+  where
+    P: AsRef<Path>,
+  {
+    let target = item.target.as_ref();
+    if item.target_is_dir {
+      target
+    } else {
+      target.parent().unwrap_or(Path::new(""))
+    }
+  }
+
+  /// Where `item` is downloaded to before it is verified and placed.
+  fn temp_file<U, P>(&self, item: &DownloadItem<U, P>) -> Result<PathBuf, ProgressDownloadError>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let target = item.target.as_ref();
+    let Some(file_name) = target.file_name() else {
+      return Err(ProgressDownloadError::Path {
+        path: target.to_string_lossy().to_string(),
+      });
+    };
+
+    // Items sharing a target directory are told apart by their URL until the response
+    // names the file.
+    let url = item.url.as_str();
+    let url_name = item
+      .target_is_dir
+      .then(|| Url::parse(url).ok())
+      .flatten()
+      .and_then(|url| {
+        let policy = item.filename_policy.unwrap_or(self.filename_policy);
+        policy.file_name_from_url(&url)
+      });
+    // Keyed by the primary URL, so a partial download carries over to the mirrors.
+    Ok(self.staging.temp_file(
+      self.target_dir(item),
+      url_name.as_deref().map_or(file_name, OsStr::new),
+      &format!("{}\n{}", url, target.display()),
+    ))
+  }
+
+  /// Attempts to download a single file with automatic retries on failure.
+  ///
+  /// This method implements the retry logic using exponential backoff and
+  /// handles temporary file management to ensure atomic file operations.
+  ///
+  /// # Arguments
+  ///
+  /// * `client` - The HTTP client to use for the download
+  /// * `observer` - Receives the item's progress events
+  /// * `index` - The item's position in its batch
+  /// * `url` - The URL to download from
+  /// * `target` - The local path where the file should be saved
+  ///
+  /// # Returns
+  ///
+  /// Returns the item's [`DownloadReport`] if the download succeeds, or a
+  /// `ProgressDownloadError` if the download fails after all retry attempts.
+  async fn download_with_retry<U, P>(
+    &self,
+    client: &reqwest::Client,
+    observer: Arc<ItemObserver>,
+    index: usize,
+    item: &DownloadItem<U, P>,
+    cancelled: Arc<AtomicBool>,
+    token: Option<&CancellationToken>,
+  ) -> Result<DownloadReport, ItemFailure>
+  where
+    U: IntoUrl + Clone,
+    P: AsRef<Path>,
+  {
+    let started = self.clock.now();
+    let url = item.url.as_str().to_string();
+    let tags = item.tags.clone();
+    let only_if_newer = item.only_if_newer;
+    let mut target = item.target.as_ref().to_path_buf();
+    let temp_file = self.temp_file(item)?;
+
+    let report = |target, outcome, attempts| DownloadReport {
+      index,
+      url: url.clone(),
+      target,
+      outcome,
+      tags: tags.clone(),
+      attempts,
+      elapsed: self.clock.elapsed(started),
+    };
+
+    let guard = PartialGuard::new(temp_file.clone(), self.cleanup_policy);
+
+    if token.is_some_and(CancellationToken::is_cancelled) {
+      guard.keep_partial();
+      return Ok(report(target, DownloadOutcome::Cancelled, Vec::new()));
+    }
+    if cancelled.load(Ordering::Relaxed) {
+      return Ok(report(target, DownloadOutcome::Cancelled, Vec::new()));
+    }
+
+    #[cfg(feature = "sha2")]
+    if let (Some(store), Some(Integrity::SHA256(digest))) = (&item.link_store, &item.integrity) {
+      let stored = naming::sha256_path(store, digest);
+      if tokio::fs::try_exists(&stored)
+        .await
+        .map_err(ProgressDownloadError::from)?
+      {
+        naming::relink(&stored, &target)
+          .await
+          .map_err(ProgressDownloadError::from)?;
+        info!(
+          "linked {} to stored copy {}",
+          target.display(),
+          stored.display()
+        );
+        guard.complete();
+        return Ok(report(target, DownloadOutcome::NotModified, Vec::new()));
+      }
+    }
+
+    let policy = item.retry_policy.as_ref().unwrap_or(&self.retry_policy);
+    let build_runner = |url, target| {
+      DownloadTaskRunner::builder()
+        .client(client.clone())
+        .observer(observer.clone())
+        .item(item)
+        .url(url)
+        .target(target)
+        .tmp_file(temp_file.clone())
+        .read_chunk_timeout(self.read_chunk_timeout)
+        .timeout(self.timeout)
+        .flush_threshold(self.flush_threshold)
+        .buffer_chunks(self.buffer_chunks)
+        .stats(self.stats.clone())
+        .storage(self.storage.clone())
+        .memory(self.memory_limit.clone())
+        .bandwidth(self.max_bandwidth.clone())
+        .cancelled(cancelled.clone())
+        .token(token.cloned())
+        .redirect_policy(self.redirect_policy.clone())
+        .pacer(self.pacer.clone())
+        .clock(self.clock.clone())
+        .auth(self.auth.clone())
+        .request_signer(self.request_signer.clone())
+        .restart_on_remote_change(self.restart_on_remote_change)
+        .verify_server_checksums(self.verify_server_checksums)
+        .max_attempts(policy.max_attempts())
+        .overwrite(item.overwrite.unwrap_or(self.overwrite))
+        .filename_policy(item.filename_policy.unwrap_or(self.filename_policy))
+        .pin_store(self.pin_store.clone())
+        .max_size(item.max_size.or(self.max_size))
+        .completion_marker(self.completion_marker.clone())
+        .build()
+    };
+
+    let mut mirrors = item.mirrors.iter();
+    let mut task_runner = build_runner(&item.url, target.clone());
+
+    #[cfg(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    ))]
+    if self.skip_valid_targets
+      && task_runner
+        .is_already_valid(self.digest_cache.as_deref())
+        .await?
+    {
+      info!(
+        "target already matches its integrity, skipping: {}",
+        target.display()
+      );
+      guard.complete();
+      return Ok(report(target, DownloadOutcome::AlreadyValid, Vec::new()));
+    }
+
+    if item.content_address.is_none() && !item.target_is_dir {
+      let overwrite = item.overwrite.unwrap_or(self.overwrite);
+      let Some(resolved) = overwrite.resolve(&target).await? else {
+        info!("target exists, skipping: {}", target.display());
+        guard.complete();
+        return Ok(report(target, DownloadOutcome::Skipped, Vec::new()));
+      };
+      if resolved != target {
+        info!("target exists, downloading to {}", resolved.display());
+        target = resolved;
+        task_runner = build_runner(&item.url, target.clone());
+      }
+    }
+
+    if only_if_newer {
+      let up_to_date = backoff::future::retry(policy.backoff(&self.clock), || async {
+        task_runner
+          .is_up_to_date()
+          .await
+          .map_err(ProgressDownloadError::into_backoff_err)
+      })
+      .await?;
+
+      if up_to_date {
+        info!("target is up to date, skipping: {}", target.display());
+        guard.complete();
+        return Ok(report(target, DownloadOutcome::NotModified, Vec::new()));
+      }
+    }
+
+    if let Some(existing) = &item.existing_partial {
+      // The download goes ahead without a partial that cannot be adopted.
+      if let Err(e) = task_runner
+        .adopt_partial(existing, item.existing_partial_check)
+        .await
+      {
+        warn!("could not resume from {}: {}", existing.display(), e);
+      }
+    }
+
+    let mut attempts = Vec::new();
+    let mut failed_attempts = 0;
+
+    loop {
+      let url = task_runner.url().to_string();
+      let backoff = policy.backoff(&self.clock);
+      let budget = policy.max_elapsed;
+      let retry_started = self.clock.now();
+      let retrying_for = || self.clock.elapsed(retry_started);
+      let failures = AtomicU32::new(0);
+
+      let result = backoff::future::retry_notify(
+        backoff,
+        || async {
+          task_runner.download().await.map_err(|e| {
+            self.stats.record_failure(self.clock.now());
+            let failed = failures.fetch_add(1, Ordering::Relaxed) + 1;
+            match e.into_backoff_err() {
+              backoff::Error::Transient { err, .. } if policy.exhausted(failed) => {
+                backoff::Error::permanent(err)
+              }
+              // A server-advertised wait replaces the policy's, but not its budget.
+              backoff::Error::Transient {
+                err,
+                retry_after: Some(wait),
+              } if budget.is_some_and(|budget| retrying_for() + wait > budget) => {
+                backoff::Error::permanent(err)
+              }
+              e => e,
+            }
+          })
+        },
+        |e, wait: Duration| {
+          failed_attempts += 1;
+          warn!(
+            "attempt {} failed for {}, retrying in {:?}: {}",
+            failed_attempts, url, wait, e
+          );
+          observer.retry(&Retry {
+            failed_attempts,
+            max_attempts: policy.max_attempts(),
+            url: &url,
+            wait,
+            budget_left: budget.map(|budget| budget.saturating_sub(retrying_for())),
+            error: &e,
+          });
+        },
+      )
+      .await;
+      attempts.extend(task_runner.take_attempts());
+
+      match result {
+        Ok(target) => {
+          guard.complete();
+          #[cfg(any(
+            feature = "md5",
+            feature = "sha1",
+            feature = "sha2",
+            feature = "sha3",
+            feature = "blake2",
+            feature = "blake3"
+          ))]
+          self.record_digest(item, &target).await;
+          return Ok(DownloadReport {
+            url,
+            ..report(target, DownloadOutcome::Downloaded, attempts)
+          });
+        }
+        // Cancelled by tag: the partial file is handled by the guard like any unfinished item.
+        // A cancelled call keeps it to resume from; `download_in` reports the cancellation.
+        Err(ProgressDownloadError::Cancelled) => {
+          if token.is_some_and(CancellationToken::is_cancelled) {
+            guard.keep_partial();
+          }
+          return Ok(report(target, DownloadOutcome::Cancelled, attempts));
+        }
+        // The name the response gave the file is taken; its partial goes like a failed one's.
+        Err(ProgressDownloadError::TargetSkipped { path }) => {
+          info!("target exists, skipping: {}", path);
+          return Ok(report(
+            PathBuf::from(path),
+            DownloadOutcome::Skipped,
+            attempts,
+          ));
+        }
+        Err(e) => {
+          let Some(mirror) = mirrors.next() else {
+            return Err(ItemFailure { error: e, attempts });
+          };
+          warn!("{} failed, falling back to {}: {}", url, mirror.as_str(), e);
+          task_runner = build_runner(mirror, target.clone());
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+
+  use std::env;
+
+  use super::*;
+  use test_server::Response;
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_download() {
+    let downloader = RobustDownloader::builder()
+      .connect_timeout(Duration::from_secs(1))
+      .timeout(Duration::from_secs(60))
+      .flush_threshold(1024 * 1024)
+      .build();
+    let downloads = vec![
+      DownloadItem::builder()
+        .url("https://nodejs.org/dist/v23.9.0/node-v23.9.0.tar.gz")
+        .target("local/node-v23.9.0.tar.gz")
         .integrity(Integrity::SHA256(
           "164ec8fe82aac21f74efc0d5890d9f6c0e0ba22ca285d400c0266913fb4ff8a0".to_string(),
         ))
@@ -285,4 +2100,1254 @@ mod tests {
     ];
     downloader.download(downloads).await.unwrap();
   }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_verified_download_is_written_once_verified() {
+    let base = test_server::serve(|_| Response::ok("abc")).await;
+    let downloader = RobustDownloader::builder().build();
+    let item = |sha256: &str| {
+      DownloadItem::builder()
+        .url(format!("{base}/script.sh"))
+        .target("rd-verified-script.sh")
+        .integrity(Integrity::SHA256(sha256.to_string()))
+        .build()
+    };
+
+    let mut written = Vec::new();
+    let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let report = downloader
+      .download_verified_to(item(sha256), &mut written)
+      .await
+      .unwrap();
+    assert_eq!(report.outcome, DownloadOutcome::Downloaded);
+    assert_eq!(written, b"abc");
+    assert!(!Path::new("rd-verified-script.sh").exists());
+
+    // Content that does not match never reaches the writer.
+    let mut written = Vec::new();
+    let result = downloader
+      .download_verified_to(item(&"0".repeat(64)), &mut written)
+      .await;
+    assert!(matches!(
+      result,
+      Err(ProgressDownloadError::IntegrityHash { .. })
+    ));
+    assert!(written.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_content_encoding_is_stored_as_sent() {
+    // `abc`, gzipped.
+    let gzipped: &[u8] = &[
+      0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0x4c, 0x4a, 0x06, 0x00,
+      0xc2, 0x41, 0x24, 0x35, 0x03, 0x00, 0x00, 0x00,
+    ];
+    let base = test_server::serve(move |_| {
+      let head = format!(
+        "HTTP/1.1 200 OK\r\ncontent-encoding: gzip\r\ncontent-length: {}\r\n\
+         connection: close\r\n\r\n",
+        gzipped.len()
+      );
+      Response::raw([head.as_bytes(), gzipped].concat())
+    })
+    .await;
+    let target = env::temp_dir().join("rd-content-encoding.tar.gz");
+    let _ = std::fs::remove_file(&target);
+
+    let downloader = RobustDownloader::builder().build();
+    downloader
+      .download(vec![
+        DownloadItem::builder()
+          .url(format!("{base}/archive.tar.gz"))
+          .target(&target)
+          .build(),
+      ])
+      .await
+      .unwrap();
+
+    assert_eq!(std::fs::read(&target).unwrap(), gzipped);
+    std::fs::remove_file(&target).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_complete_partial_is_not_restarted() {
+    // Refuses ranges starting at the end of the 9-byte file, as servers do.
+    let base = test_server::serve(|request| match request.range_start {
+      Some(9) => Response::raw(
+        "HTTP/1.1 416 Range Not Satisfiable\r\ncontent-range: bytes */9\r\n\
+         content-length: 5\r\nconnection: close\r\n\r\noops!",
+      ),
+      _ => Response::file(b"fresh new", request),
+    })
+    .await;
+
+    let dir = env::temp_dir().join(format!("rd-complete-partial-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(dir.join("file.bin.part"), b"fresh new")
+      .await
+      .unwrap();
+
+    let reports = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .build()
+      .download(vec![
+        DownloadItem::builder()
+          .url(format!("{base}/file.bin"))
+          .target(dir.join("file.bin"))
+          .build(),
+      ])
+      .await
+      .unwrap();
+
+    assert_eq!(
+      tokio::fs::read(dir.join("file.bin")).await.unwrap(),
+      b"fresh new"
+    );
+    assert_eq!(reports[0].attempts.len(), 1);
+    assert_eq!(reports[0].attempts[0].discarded_partial, None);
+    assert_eq!(reports[0].bytes(), 0);
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_failed_item_falls_back_to_mirror() {
+    let base = test_server::serve(|request| match request.path.as_str() {
+      "/mirror/tool.bin" => Response::file(b"abc", request),
+      _ => Response::ok("not the file"),
+    })
+    .await;
+    let dir = env::temp_dir().join(format!("rd-mirror-{}", std::process::id()));
+
+    let reports = RobustDownloader::builder()
+      .quiet(true)
+      .build()
+      .download(vec![
+        DownloadItem::builder()
+          .url(format!("{base}/primary/tool.bin"))
+          .mirrors(vec![format!("{base}/mirror/tool.bin")])
+          .target(dir.join("rd-mirror-tool.bin"))
+          .integrity(Integrity::SHA256(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+          ))
+          .build(),
+      ])
+      .await
+      .unwrap();
+
+    let report = &reports[0];
+    assert_eq!(report.url, format!("{base}/mirror/tool.bin"));
+    assert_eq!(report.attempts.len(), 2);
+    assert_eq!(report.attempts[0].url, format!("{base}/primary/tool.bin"));
+    assert!(report.attempts[0].error.is_some());
+    assert_eq!(
+      tokio::fs::read(dir.join("rd-mirror-tool.bin"))
+        .await
+        .unwrap(),
+      b"abc"
+    );
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_cancelled_call_keeps_its_partial() {
+    let base =
+      test_server::serve(|_| Response::ok("0123456789").paused(Duration::from_millis(500))).await;
+    let dir = env::temp_dir().join(format!("rd-cancelled-{}", std::process::id()));
+
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      cancel.cancel();
+    });
+
+    let result = RobustDownloader::builder()
+      .quiet(true)
+      .read_chunk_timeout(Duration::from_secs(5))
+      .staging(Staging::NextToTarget)
+      .build()
+      .download_with_cancel(
+        vec![
+          DownloadItem::builder()
+            .url(format!("{base}/file.bin"))
+            .target(dir.join("file.bin"))
+            .build(),
+        ],
+        token,
+      )
+      .await;
+
+    assert!(matches!(result, Err(ProgressDownloadError::Cancelled)));
+    assert!(!dir.join("file.bin").exists());
+    // What arrived before the cancellation is kept to resume from.
+    assert_eq!(
+      tokio::fs::read(dir.join("file.bin.part")).await.unwrap(),
+      b"01234"
+    );
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_checksum_header_mismatch_is_retried() {
+    let requests = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let served = requests.clone();
+    let base = test_server::serve(move |_| {
+      // The first transfer arrives corrupted.
+      let body = match served.fetch_add(1, Ordering::Relaxed) {
+        0 => "abd",
+        _ => "abc",
+      };
+      Response::raw(format!(
+        "HTTP/1.1 200 OK\r\nx-amz-checksum-sha256: ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=\r\n\
+         content-length: 3\r\nconnection: close\r\n\r\n{body}"
+      ))
+    })
+    .await;
+    let dir = env::temp_dir().join(format!("rd-checksum-header-{}", std::process::id()));
+
+    let reports = RobustDownloader::builder()
+      .quiet(true)
+      .build()
+      .download(vec![
+        DownloadItem::builder()
+          .url(format!("{base}/file.txt"))
+          .target(dir.join("rd-checksum-header.txt"))
+          .build(),
+      ])
+      .await
+      .unwrap();
+
+    assert_eq!(requests.load(Ordering::Relaxed), 2);
+    assert_eq!(reports[0].attempts.len(), 2);
+    assert!(reports[0].attempts[0].error.is_some());
+    assert_eq!(
+      reports[0].verified_by(),
+      Some(VerificationSource::AmzChecksum)
+    );
+    assert_eq!(
+      tokio::fs::read(dir.join("rd-checksum-header.txt"))
+        .await
+        .unwrap(),
+      b"abc"
+    );
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_deferred_item_hands_its_slot_over() {
+    #[derive(Debug, Default)]
+    struct DeferBulkOnce {
+      deferred: AtomicBool,
+    }
+
+    impl StartPolicy for DeferBulkOnce {
+      fn should_start<'a>(
+        &'a self,
+        item: StartCandidate<'a>,
+      ) -> futures::future::BoxFuture<'a, StartDecision> {
+        Box::pin(async move {
+          let bulk = item.tags.iter().any(|tag| tag == "bulk");
+          if bulk && !self.deferred.swap(true, Ordering::Relaxed) {
+            StartDecision::Defer(Duration::from_millis(50))
+          } else {
+            StartDecision::Start
+          }
+        })
+      }
+    }
+
+    let requested = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = requested.clone();
+    let base = test_server::serve(move |request| {
+      log.lock().unwrap().push(request.path.clone());
+      Response::ok("data")
+    })
+    .await;
+    let dir = env::temp_dir().join(format!("rd-deferred-{}", std::process::id()));
+    let item = |name: &str, tag: &str| {
+      DownloadItem::builder()
+        .url(format!("{base}/{name}"))
+        .target(dir.join(name))
+        .tags(vec![tag.to_string()])
+        .build()
+    };
+
+    RobustDownloader::builder()
+      .quiet(true)
+      .max_concurrent(1)
+      .start_policy(Arc::new(DeferBulkOnce::default()))
+      .build()
+      .download(vec![item("bulk.bin", "bulk"), item("urgent.bin", "urgent")])
+      .await
+      .unwrap();
+
+    assert_eq!(*requested.lock().unwrap(), ["/urgent.bin", "/bulk.bin"]);
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_every_item_gets_a_result() {
+    let base = test_server::serve(|_| Response::ok("abc")).await;
+    let dir = env::temp_dir().join(format!("rd-download-all-{}", std::process::id()));
+    let item = |name: &str, sha256: &str| {
+      DownloadItem::builder()
+        .url(format!("{base}/{name}"))
+        .target(dir.join(name))
+        .integrity(Integrity::SHA256(sha256.to_string()))
+        .build()
+    };
+    let items = || {
+      vec![
+        item("corrupt.bin", &"0".repeat(64)),
+        item(
+          "fine.bin",
+          "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ),
+      ]
+    };
+    let downloader = RobustDownloader::builder().quiet(true).build();
+
+    let results = downloader.download_all(items()).await.unwrap();
+    assert!(matches!(
+      results[0].result,
+      Err(ProgressDownloadError::IntegrityHash { .. })
+    ));
+    assert_eq!(results[0].bytes, 3);
+    assert!(results[1].is_ok());
+    assert!(dir.join("fine.bin").exists());
+
+    // `download` gives up on the batch at the first failure instead.
+    assert!(downloader.download(items()).await.is_err());
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_results_keep_the_input_order() {
+    // The first item finishes last.
+    let base = test_server::serve(|request| match request.path.as_str() {
+      "/slow.bin" => Response::ok("slow").paused(Duration::from_millis(300)),
+      _ => Response::ok("fast"),
+    })
+    .await;
+    let dir = env::temp_dir().join(format!("rd-input-order-{}", std::process::id()));
+    let items = || {
+      ["slow.bin", "fast-1.bin", "fast-2.bin"]
+        .map(|name| {
+          DownloadItem::builder()
+            .url(format!("{base}/{name}"))
+            .target(dir.join(name))
+            .build()
+        })
+        .to_vec()
+    };
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .read_chunk_timeout(Duration::from_secs(5))
+      .build();
+
+    let reports = downloader.download(items()).await.unwrap();
+    let order: Vec<_> = reports.iter().map(|report| report.index).collect();
+    assert_eq!(order, [0, 1, 2]);
+    assert!(reports[0].url.ends_with("/slow.bin"));
+
+    let results = downloader.download_all(items()).await.unwrap();
+    let order: Vec<_> = results.iter().map(|result| result.index).collect();
+    assert_eq!(order, [0, 1, 2]);
+    assert!(results[0].url.ends_with("/slow.bin"));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_error_policy_decides_whether_siblings_finish() {
+    let base = test_server::serve(|request| match request.path.as_str() {
+      "/missing.bin" => {
+        Response::raw("HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+      }
+      _ => Response::ok("slow").paused(Duration::from_millis(300)),
+    })
+    .await;
+    let dir = env::temp_dir().join(format!("rd-error-policy-{}", std::process::id()));
+    let items = || {
+      ["missing.bin", "slow.bin"]
+        .map(|name| {
+          DownloadItem::builder()
+            .url(format!("{base}/{name}"))
+            .target(dir.join(name))
+            .build()
+        })
+        .to_vec()
+    };
+    let downloader = |error_policy| {
+      RobustDownloader::builder()
+        .quiet(true)
+        .read_chunk_timeout(Duration::from_secs(5))
+        .error_policy(error_policy)
+        .build()
+    };
+
+    // The missing file aborts the batch before the slow one is complete.
+    let result = downloader(ErrorPolicy::FailFast).download(items()).await;
+    assert!(matches!(
+      result.unwrap_err().root(),
+      ProgressDownloadError::Reqwest(_)
+    ));
+    assert!(!dir.join("slow.bin").exists());
+
+    // The slow one completes, then the failure is reported.
+    let result = downloader(ErrorPolicy::ContinueAll).download(items()).await;
+    assert!(matches!(
+      result.unwrap_err().root(),
+      ProgressDownloadError::Reqwest(_)
+    ));
+    assert_eq!(
+      tokio::fs::read(dir.join("slow.bin")).await.unwrap(),
+      b"slow"
+    );
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_digest_cache_records_verified_downloads() {
+    let base = test_server::serve(|_| Response::ok("abc")).await;
+    let dir = std::env::temp_dir().join(format!("rd-digest-cache-{}", std::process::id()));
+    let cache = Arc::new(DigestCache::open(dir.join("digests.tsv")).await.unwrap());
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .digest_cache(cache.clone())
+      .build();
+    let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let item = || {
+      DownloadItem::builder()
+        .url(format!("{base}/file.bin"))
+        .target(dir.join("file.bin"))
+        .integrity(Integrity::SHA256(sha256.to_string()))
+        .build()
+    };
+
+    let report = downloader.download_one(item()).await.unwrap();
+    assert_eq!(report.outcome, DownloadOutcome::Downloaded);
+    cache.save().await.unwrap();
+    let saved = tokio::fs::read_to_string(dir.join("digests.tsv"))
+      .await
+      .unwrap();
+    assert!(saved.starts_with("sha256\t3\t"));
+    assert!(saved.contains(sha256));
+
+    // Checked against the recorded digest, without hashing the target again.
+    let report = downloader.download_one(item()).await.unwrap();
+    assert_eq!(report.outcome, DownloadOutcome::AlreadyValid);
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_fail_fast_closes_sibling_connections() {
+    use tokio::{
+      io::{AsyncReadExt, AsyncWriteExt},
+      net::TcpListener,
+      sync::oneshot,
+    };
+
+    // Accepts the request and never answers it.
+    let stalled = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stalled_url = format!("http://{}/slow.bin", stalled.local_addr().unwrap());
+    // Answers 404, but only once the stalled request is in flight.
+    let missing = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let missing_url = format!("http://{}/missing.bin", missing.local_addr().unwrap());
+
+    let (connected_tx, connected_rx) = oneshot::channel();
+    let stalled_server = tokio::spawn(async move {
+      let (mut socket, _) = stalled.accept().await.unwrap();
+      let _ = connected_tx.send(());
+      let mut buf = [0; 1024];
+      // Returns once the client closes the connection.
+      while socket.read(&mut buf).await.is_ok_and(|read| read > 0) {}
+    });
+    tokio::spawn(async move {
+      connected_rx.await.unwrap();
+      loop {
+        let (mut socket, _) = missing.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let _ = socket.read(&mut buf).await;
+        let _ = socket
+          .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+          .await;
+      }
+    });
+
+    let downloader = RobustDownloader::builder().quiet(true).build();
+    let downloads = vec![
+      DownloadItem::builder()
+        .url(stalled_url)
+        .target("local/fail-fast-slow.bin")
+        .build(),
+      DownloadItem::builder()
+        .url(missing_url)
+        .target("local/fail-fast-missing.bin")
+        .build(),
+    ];
+
+    let result = tokio::time::timeout(Duration::from_secs(10), downloader.download(downloads))
+      .await
+      .expect("the batch waited for its stalled item");
+    assert!(result.is_err());
+    tokio::time::timeout(Duration::from_secs(5), stalled_server)
+      .await
+      .expect("the stalled connection was left open")
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_named_target_follows_overwrite_policy() {
+    let base = test_server::serve(|_| Response::ok("new")).await;
+    let dir = std::env::temp_dir().join(format!("rd-named-target-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let taken = dir.join("new report.pdf");
+    let download = |overwrite, policy: Option<FilenamePolicy>| {
+      let downloader = RobustDownloader::builder()
+        .quiet(true)
+        .overwrite(overwrite)
+        .build();
+      let item = DownloadItem::builder()
+        .url(format!("{base}/new%20report.pdf"))
+        .target(dir.clone())
+        .target_is_dir(true)
+        .filename_policy(policy.unwrap_or_default())
+        .build();
+      async move { downloader.download_one(item).await }
+    };
+
+    tokio::fs::write(&taken, b"old").await.unwrap();
+    let report = download(OverwritePolicy::Skip, None).await.unwrap();
+    assert_eq!(report.outcome, DownloadOutcome::Skipped);
+    assert_eq!(report.target, taken);
+    assert_eq!(tokio::fs::read(&taken).await.unwrap(), b"old");
+
+    assert!(matches!(
+      download(OverwritePolicy::Error, None).await,
+      Err(ProgressDownloadError::TargetExists { .. })
+    ));
+
+    let report = download(OverwritePolicy::RenameWithSuffix, None)
+      .await
+      .unwrap();
+    assert_eq!(report.target, dir.join("new report (1).pdf"));
+    assert_eq!(tokio::fs::read(&report.target).await.unwrap(), b"new");
+
+    // The item's policy keeps the name as the URL has it, which is not taken.
+    let raw = FilenamePolicy::builder().percent_decode(false).build();
+    let report = download(OverwritePolicy::Error, Some(raw)).await.unwrap();
+    assert_eq!(report.target, dir.join("new%20report.pdf"));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_same_file_names_do_not_share_a_temp_file() {
+    // Serves the request path, repeated, in two halves so that both downloads overlap.
+    let base = test_server::serve(|request| {
+      Response::ok(request.path.repeat(1000)).paused(Duration::from_millis(200))
+    })
+    .await;
+
+    let dir = std::env::temp_dir().join(format!("rd-same-name-{}", std::process::id()));
+    let downloads = ["a", "b"]
+      .map(|sub| {
+        DownloadItem::builder()
+          .url(format!("{base}/{sub}/file.bin"))
+          .target(dir.join(sub).join("file.bin"))
+          .build()
+      })
+      .to_vec();
+    RobustDownloader::builder()
+      .quiet(true)
+      .build()
+      .download(downloads)
+      .await
+      .unwrap();
+
+    for sub in ["a", "b"] {
+      assert_eq!(
+        tokio::fs::read_to_string(dir.join(sub).join("file.bin"))
+          .await
+          .unwrap(),
+        format!("/{sub}/file.bin").repeat(1000)
+      );
+    }
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_head_reports_the_redirected_file() {
+    let methods = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = methods.clone();
+    let base = test_server::serve(move |request| {
+      log.lock().unwrap().push(request.method.clone());
+      match request.path.as_str() {
+        "/latest.zip" => Response::raw(
+          "HTTP/1.1 302 Found\r\nlocation: /v2/tool.zip\r\ncontent-length: 0\r\n\
+           connection: close\r\n\r\n",
+        ),
+        _ => Response::raw(
+          "HTTP/1.1 200 OK\r\ncontent-length: 1234\r\netag: \"v2\"\r\n\
+           last-modified: Wed, 21 Oct 2015 07:28:00 GMT\r\naccept-ranges: bytes\r\n\
+           connection: close\r\n\r\n",
+        ),
+      }
+    })
+    .await;
+
+    let remote = RobustDownloader::builder()
+      .quiet(true)
+      .build()
+      .head(format!("{base}/latest.zip"))
+      .await
+      .unwrap();
+
+    assert_eq!(remote.size, Some(1234));
+    assert_eq!(remote.etag.as_deref(), Some("\"v2\""));
+    assert_eq!(
+      remote.last_modified,
+      Some(httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap())
+    );
+    assert!(remote.accepts_ranges);
+    assert_eq!(remote.final_url.as_str(), format!("{base}/v2/tool.zip"));
+    assert_eq!(*methods.lock().unwrap(), ["HEAD", "HEAD"]);
+  }
+
+  #[tokio::test]
+  async fn test_restarts_when_server_ignores_range() {
+    // Always answers with the whole file, whatever range was asked for.
+    let base = test_server::serve(|_| Response::ok("fresh new")).await;
+    let url = format!("{base}/file.bin");
+
+    let dir = std::env::temp_dir().join(format!("rd-ignored-range-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    // Shorter than the remote file, so only the ignored range can give it away.
+    tokio::fs::write(dir.join("file.bin.part"), b"stale")
+      .await
+      .unwrap();
+
+    let report = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .build()
+      .download_one(
+        DownloadItem::builder()
+          .url(url)
+          .target(dir.join("file.bin"))
+          .build(),
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(
+      tokio::fs::read(dir.join("file.bin")).await.unwrap(),
+      b"fresh new"
+    );
+    assert_eq!(report.attempts[0].discarded_partial, Some(5));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_restarts_when_range_starts_elsewhere() {
+    // Answers a resume from byte 5 with the range from byte 3, and anything else in full.
+    let base = test_server::serve(|request| match request.range_start {
+      Some(5) => Response::range(b"fresh new", 3),
+      _ => Response::ok("fresh new"),
+    })
+    .await;
+    let url = format!("{base}/file.bin");
+
+    let dir = std::env::temp_dir().join(format!("rd-misplaced-range-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(dir.join("file.bin.part"), b"fresh")
+      .await
+      .unwrap();
+
+    let report = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .build()
+      .download_one(
+        DownloadItem::builder()
+          .url(url)
+          .target(dir.join("file.bin"))
+          .build(),
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(
+      tokio::fs::read(dir.join("file.bin")).await.unwrap(),
+      b"fresh new"
+    );
+    assert_eq!(report.attempts[0].discarded_partial, Some(5));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_short_body_is_resumed_up_to_expected_size() {
+    // Sends the first bytes without a length and hangs up, then serves the rest as a range.
+    let base = test_server::serve(|request| match request.range_start {
+      Some(3) => Response::range(b"fresh new", 3),
+      _ => Response::raw("HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nfre"),
+    })
+    .await;
+    let url = format!("{base}/file.bin");
+
+    let dir = std::env::temp_dir().join(format!("rd-expected-size-{}", std::process::id()));
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .retry_policy(
+        RetryPolicy::builder()
+          .initial_interval(Duration::from_millis(10))
+          .build(),
+      )
+      .build();
+    let item = |target: &str, expected_size| {
+      DownloadItem::builder()
+        .url(url.clone())
+        .target(dir.join(target))
+        .expected_size(expected_size)
+        .build()
+    };
+
+    let report = downloader.download_one(item("file.bin", 9)).await.unwrap();
+    assert_eq!(
+      tokio::fs::read(dir.join("file.bin")).await.unwrap(),
+      b"fresh new"
+    );
+    assert_eq!(report.attempts.len(), 2);
+
+    // The range reveals the remote size, which is not the expected one.
+    let error = downloader
+      .download_one(item("other.bin", 5))
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      error,
+      ProgressDownloadError::SizeMismatch {
+        expected: 5,
+        actual: 9,
+        ..
+      }
+    ));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_body_beyond_max_size_is_aborted() {
+    use tokio::{
+      io::{AsyncReadExt, AsyncWriteExt},
+      net::TcpListener,
+    };
+
+    // Announces no length, so only the bytes themselves can give it away.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/endless", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 4096];
+        let _ = socket.read(&mut buf).await;
+        let _ = socket
+          .write_all(b"HTTP/1.1 200 OK\r\nconnection: close\r\n\r\n")
+          .await;
+        let _ = socket.write_all(&[b'x'; 64]).await;
+      }
+    });
+
+    let dir = std::env::temp_dir().join(format!("rd-max-size-{}", std::process::id()));
+    let error = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .max_size(10)
+      .build()
+      .download_one(
+        DownloadItem::builder()
+          .url(url)
+          .target(dir.join("endless"))
+          .build(),
+      )
+      .await
+      .unwrap_err();
+
+    assert!(matches!(
+      error,
+      ProgressDownloadError::TooLarge { max_size: 10, .. }
+    ));
+    assert!(!dir.join("endless.part").exists());
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+  }
+
+  #[tokio::test]
+  async fn test_resumes_from_existing_partial() {
+    // Serves "fresh new", or the requested range of it.
+    let base = test_server::serve(|request| Response::file(b"fresh new", request)).await;
+    let url = format!("{base}/file.bin");
+
+    let dir = std::env::temp_dir().join(format!("rd-existing-partial-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .build();
+    let download = |name: &str, partial: &'static [u8]| {
+      let dir = dir.clone();
+      let url = url.clone();
+      let downloader = downloader.clone();
+      let name = name.to_string();
+      async move {
+        let existing = dir.join(format!("{name}.crdownload"));
+        tokio::fs::write(&existing, partial).await.unwrap();
+        let report = downloader
+          .download_one(
+            DownloadItem::builder()
+              .url(url)
+              .target(dir.join(&name))
+              .existing_partial(&existing)
+              .existing_partial_check(2)
+              .build(),
+          )
+          .await
+          .unwrap();
+        assert_eq!(
+          tokio::fs::read(dir.join(&name)).await.unwrap(),
+          b"fresh new"
+        );
+        (report.bytes(), existing.exists())
+      }
+    };
+
+    // Adopted: only the rest is fetched, and the partial moved away.
+    assert_eq!(download("adopted", b"fresh").await, (4, false));
+    // Its tail does not match, so it is left alone and the file fetched in full.
+    assert_eq!(download("foreign", b"frexx").await, (9, true));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_verifies_against_integrity_file() {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let _ = socket
+          .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\nconnection: close\r\n\r\nabc")
+          .await;
+      }
+    });
+
+    let dir = std::env::temp_dir().join(format!("rd-integrity-file-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let list = dir.join("SHASUMS256.txt");
+    tokio::fs::write(
+      &list,
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  tool.tar.gz\n\
+       0000000000000000000000000000000000000000000000000000000000000000  other.tar.gz\n",
+    )
+    .await
+    .unwrap();
+    let downloader = RobustDownloader::builder().quiet(true).build();
+    let download = |file: &str, target: &str| {
+      downloader.download_one(
+        DownloadItem::builder()
+          .url(format!("{base}/{file}"))
+          .target(dir.join(target))
+          .integrity_file(IntegrityFile::Path(list.clone()))
+          .build(),
+      )
+    };
+
+    // Listed under the URL's name only.
+    let report = download("tool.tar.gz", "tool-renamed.tar.gz")
+      .await
+      .unwrap();
+    assert_eq!(report.verified_by(), Some(VerificationSource::Item));
+    assert!(matches!(
+      download("other.tar.gz", "other.tar.gz").await,
+      Err(ProgressDownloadError::IntegrityHash { .. })
+    ));
+    assert!(matches!(
+      download("missing.tar.gz", "missing.tar.gz").await,
+      Err(ProgressDownloadError::ChecksumNotListed { .. })
+    ));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_verifies_against_integrity_url() {
+    use tokio::{
+      io::{AsyncReadExt, AsyncWriteExt},
+      net::TcpListener,
+    };
+
+    // Serves "abc", and its SHA-256 next to it the way release pipelines publish it.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 4096];
+        let n = socket.read(&mut buf).await.unwrap_or(0);
+        let request = String::from_utf8_lossy(&buf[..n]).to_string();
+        let body = if request.starts_with("GET /tool.tar.gz.sha256 ") {
+          "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  dist/tool.tar.gz\n"
+        } else {
+          "abc"
+        };
+        let response = format!(
+          "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+          body.len()
+        );
+        let _ = socket.write_all(response.as_bytes()).await;
+      }
+    });
+
+    let dir = std::env::temp_dir().join(format!("rd-integrity-url-{}", std::process::id()));
+    let downloader = RobustDownloader::builder().quiet(true).build();
+    let download = |checksum: &str| {
+      downloader.download_one(
+        DownloadItem::builder()
+          .url(format!("{base}/tool.tar.gz"))
+          .target(dir.join("tool.tar.gz"))
+          .integrity_url(format!("{base}/{checksum}"))
+          .build(),
+      )
+    };
+
+    let report = download("tool.tar.gz.sha256").await.unwrap();
+    assert_eq!(report.verified_by(), Some(VerificationSource::Item));
+    // "abc" is no checksum, so nothing can be verified.
+    assert!(matches!(
+      download("tool.tar.gz.sha512").await,
+      Err(ProgressDownloadError::ChecksumNotListed { .. })
+    ));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_target_untouched_until_verified() {
+    use std::sync::Mutex;
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    /// Records every state with what the target held when it was entered.
+    #[derive(Debug, Default)]
+    struct States(Mutex<Vec<(TaskState, Vec<u8>)>>);
+
+    impl ProgressObserver for States {
+      fn on_state(&self, item: &ObservedItem, state: TaskState) {
+        let target = std::fs::read(&item.target).unwrap_or_default();
+        self.0.lock().unwrap().push((state, target));
+      }
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let _ = socket
+          .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\nconnection: close\r\n\r\nabc")
+          .await;
+      }
+    });
+
+    let dir = std::env::temp_dir().join(format!("rd-states-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let target = dir.join("file.bin");
+    let states = Arc::new(States::default());
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .staging(Staging::NextToTarget)
+      .progress_observer(states.clone() as Arc<dyn ProgressObserver>)
+      .build();
+    let download = |sha256: &str| {
+      downloader.download_one(
+        DownloadItem::builder()
+          .url(url.clone())
+          .target(target.clone())
+          .integrity(Integrity::SHA256(sha256.to_string()))
+          .build(),
+      )
+    };
+
+    // A mismatch fails in `Verifying`, leaving the old file in place.
+    tokio::fs::write(&target, b"old").await.unwrap();
+    assert!(download(&"0".repeat(64)).await.is_err());
+    assert_eq!(tokio::fs::read(&target).await.unwrap(), b"old");
+    let recorded = std::mem::take(&mut *states.0.lock().unwrap());
+    assert!(recorded.iter().all(|(_, target)| target == b"old"));
+    assert_eq!(
+      recorded.last().map(|(state, _)| *state),
+      Some(TaskState::Verifying)
+    );
+
+    let report = download("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+      .await
+      .unwrap();
+    assert_eq!(report.attempts[0].state, TaskState::Done);
+    let recorded = std::mem::take(&mut *states.0.lock().unwrap());
+    assert_eq!(
+      recorded,
+      [
+        (TaskState::Connecting, b"old".to_vec()),
+        (TaskState::Streaming, b"old".to_vec()),
+        (TaskState::Verifying, b"old".to_vec()),
+        (TaskState::Placing, b"old".to_vec()),
+        (TaskState::Done, b"abc".to_vec()),
+      ]
+    );
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_completion_marker() {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let _ = socket
+          .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\nconnection: close\r\n\r\nabc")
+          .await;
+      }
+    });
+
+    let dir = std::env::temp_dir().join(format!("rd-marker-{}", std::process::id()));
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .completion_marker(".ok")
+      .build();
+    let download = |name: &str, sha256: &str| {
+      downloader.download_one(
+        DownloadItem::builder()
+          .url(url.clone())
+          .target(dir.join(name))
+          .integrity(Integrity::SHA256(sha256.to_string()))
+          .build(),
+      )
+    };
+
+    let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    download("good.bin", sha256).await.unwrap();
+    assert_eq!(
+      tokio::fs::read_to_string(dir.join("good.bin.ok"))
+        .await
+        .unwrap(),
+      format!("{sha256}  good.bin\n")
+    );
+    assert!(download("bad.bin", &"0".repeat(64)).await.is_err());
+    assert!(!dir.join("bad.bin.ok").exists());
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_hash_remote_resumes_hashing() {
+    // Hangs up after the first byte of "abc", then serves the rest as a range.
+    let base = test_server::serve(|request| match request.range_start {
+      Some(1) => Response::range(b"abc", 1),
+      _ => Response::raw("HTTP/1.1 200 OK\r\ncontent-length: 3\r\nconnection: close\r\n\r\na"),
+    })
+    .await;
+    let url = format!("{base}/file.bin");
+
+    let downloader = RobustDownloader::builder()
+      .quiet(true)
+      .retry_policy(
+        RetryPolicy::builder()
+          .initial_interval(Duration::from_millis(10))
+          .build(),
+      )
+      .build();
+    let digest = downloader
+      .hash_remote(url, hashery::Algorithm::SHA256)
+      .await
+      .unwrap();
+    assert_eq!(
+      digest,
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_custom_verifier() {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    /// The length of the body, which no hash feature provides.
+    #[derive(Debug)]
+    struct Length(&'static str);
+
+    struct Counting(usize);
+
+    impl Digest for Counting {
+      fn update(&mut self, bytes: &[u8]) {
+        self.0 += bytes.len();
+      }
+
+      fn finish(self: Box<Self>) -> String {
+        format!("{:x}", self.0)
+      }
+    }
+
+    impl IntegrityVerifier for Length {
+      fn algorithm_name(&self) -> &str {
+        "length"
+      }
+
+      fn expected(&self) -> &str {
+        self.0
+      }
+
+      fn digest(&self) -> Box<dyn Digest> {
+        Box::new(Counting(0))
+      }
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let _ = socket
+          .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\nconnection: close\r\n\r\nabc")
+          .await;
+      }
+    });
+
+    let dir = std::env::temp_dir().join(format!("rd-verifier-{}", std::process::id()));
+    let downloader = RobustDownloader::builder().quiet(true).build();
+    let download = |name: &str, length: &'static str| {
+      downloader.download_one(
+        DownloadItem::builder()
+          .url(url.clone())
+          .target(dir.join(name))
+          .verifier(Arc::new(Length(length)))
+          .build(),
+      )
+    };
+
+    let report = download("good.bin", "3").await.unwrap();
+    assert_eq!(report.verified_by(), Some(VerificationSource::Item));
+    assert!(matches!(
+      download("bad.bin", "4").await,
+      Err(ProgressDownloadError::IntegrityHash { .. })
+    ));
+    assert!(!dir.join("bad.bin").exists());
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_spot_check() {
+    // Serves ranges of a 100-byte file that differs from the local copy in its last byte.
+    let remote = (0..100u8).collect::<Vec<_>>();
+    let served = remote.clone();
+    let base = test_server::serve(move |request| Response::file(&served, request)).await;
+    let url = format!("{base}/file.bin");
+
+    let dir = std::env::temp_dir().join(format!("rd-spot-check-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let downloader = RobustDownloader::builder().quiet(true).build();
+    let check = SpotCheck::builder().samples(4).sample_size(10).build();
+    let spot_check = |name: &str| downloader.spot_check(url.clone(), dir.join(name), &check);
+
+    tokio::fs::write(dir.join("same.bin"), &remote)
+      .await
+      .unwrap();
+    assert!(spot_check("same.bin").await.unwrap());
+    // The last range is always compared.
+    let mut changed = remote.clone();
+    changed[99] = 0;
+    tokio::fs::write(dir.join("changed.bin"), &changed)
+      .await
+      .unwrap();
+    assert!(!spot_check("changed.bin").await.unwrap());
+    tokio::fs::write(dir.join("short.bin"), &remote[..50])
+      .await
+      .unwrap();
+    assert!(!spot_check("short.bin").await.unwrap());
+    assert!(!spot_check("missing.bin").await.unwrap());
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_retries_wait_on_the_clock() {
+    // Unavailable for the first three requests.
+    let requests = Arc::new(AtomicUsize::new(0));
+    let served = requests.clone();
+    let base = test_server::serve(
+      move |request| match served.fetch_add(1, Ordering::Relaxed) {
+        0..3 => Response::raw(
+          "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        ),
+        _ => Response::file(b"fresh new", request),
+      },
+    )
+    .await;
+
+    let dir = std::env::temp_dir().join(format!("rd-paused-retries-{}", std::process::id()));
+    let started = std::time::Instant::now();
+    // Paused time skips ahead to the next timer whenever the runtime waits, on the loopback
+    // connection too. Ticking every second keeps those skips short, so they never reach the
+    // timeouts, which are far beyond the waits.
+    let ticker = tokio::spawn(async {
+      loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+      }
+    });
+    let report = RobustDownloader::builder()
+      .quiet(true)
+      .clock(Arc::new(TokioClock))
+      .connect_timeout(Duration::from_secs(86_400))
+      .timeout(Duration::from_secs(86_400))
+      .read_chunk_timeout(Duration::from_secs(86_400))
+      .retry_policy(
+        RetryPolicy::builder()
+          .initial_interval(Duration::from_secs(60))
+          .multiplier(2.0)
+          .max_interval(Duration::from_secs(600))
+          .max_retries(5)
+          .max_elapsed(Some(Duration::from_secs(3_600)))
+          .jitter(Jitter::Proportional(0.0))
+          .build(),
+      )
+      .build()
+      .download_one(
+        DownloadItem::builder()
+          .url(format!("{base}/file.bin"))
+          .target(dir.join("file.bin"))
+          .build(),
+      )
+      .await
+      .unwrap();
+    ticker.abort();
+
+    assert_eq!(
+      tokio::fs::read(dir.join("file.bin")).await.unwrap(),
+      b"fresh new"
+    );
+    assert_eq!(requests.load(Ordering::Relaxed), 4);
+    assert_eq!(report.attempts.len(), 4);
+    // Waited 1 + 2 + 4 minutes on the clock, and next to nothing in real time.
+    assert!(report.elapsed >= Duration::from_secs(420));
+    assert!(report.elapsed < Duration::from_secs(3_600));
+    assert!(started.elapsed() < Duration::from_secs(60));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
 }
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+
+/// The smallest write buffer a download is ever given.
+pub(crate) const MIN_BUFFER: usize = 64 * 1024;
+
+/// A global byte budget shared by the write buffers of all running downloads.
+///
+/// Each attempt reserves its buffer before streaming. When the budget is under pressure
+/// buffers shrink down to [`MIN_BUFFER`] instead of growing memory unpredictably with
+/// concurrency; once even that is not available, the attempt waits for others to finish.
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+  limit: usize,
+  semaphore: Arc<Semaphore>,
+}
+
+impl MemoryBudget {
+  pub fn new(limit: usize) -> Self {
+    let limit = limit.clamp(MIN_BUFFER, u32::MAX as usize);
+    Self {
+      limit,
+      semaphore: Arc::new(Semaphore::new(limit)),
+    }
+  }
+
+  /// Reserves up to `desired` bytes, shrinking the reservation when the budget is tight.
+  pub async fn reserve(&self, desired: usize) -> Result<BufferReservation, AcquireError> {
+    let available = self.semaphore.available_permits();
+    let size = desired
+      .min(available.max(MIN_BUFFER))
+      .clamp(MIN_BUFFER, self.limit);
+
+    let permit = self
+      .semaphore
+      .clone()
+      .acquire_many_owned(size as u32)
+      .await?;
+
+    Ok(BufferReservation {
+      size,
+      _permit: permit,
+    })
+  }
+}
+
+/// Bytes reserved from a [`MemoryBudget`], released on drop.
+#[derive(Debug)]
+pub(crate) struct BufferReservation {
+  pub size: usize,
+  _permit: OwnedSemaphorePermit,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_reservations_shrink_under_pressure() {
+    let budget = MemoryBudget::new(1024 * 1024);
+
+    let first = budget.reserve(768 * 1024).await.unwrap();
+    assert_eq!(first.size, 768 * 1024);
+
+    let second = budget.reserve(768 * 1024).await.unwrap();
+    assert_eq!(second.size, 256 * 1024);
+
+    drop(first);
+    let third = budget.reserve(512 * 1024).await.unwrap();
+    assert_eq!(third.size, 512 * 1024);
+  }
+}
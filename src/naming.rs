@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "sha2")]
+use hashery::Hashery;
+
+use crate::err::ProgressDownloadError;
+#[cfg(feature = "sha2")]
+use crate::storage::{LocalStorage, Storage};
+
+/// Names the downloaded file after its content instead of a caller-chosen file name.
+///
+/// With a content address set, the item's `target` is treated as the root directory of
+/// a content-addressed store, and the resolved path is returned in the
+/// [`DownloadReport`](crate::DownloadReport).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentAddress {
+  /// `<root>/sha256/ab/cd/abcd…`, from the SHA-256 of the downloaded bytes.
+  #[cfg(feature = "sha2")]
+  Sha256,
+  /// `<root>/etag/<etag>`, from the response's `ETag` header (quotes and the weak
+  /// validator prefix removed, unsafe characters replaced by `_`).
+  ETag,
+}
+
+impl ContentAddress {
+  /// Resolves the final path of `file` under `root`.
+  #[cfg_attr(not(feature = "sha2"), allow(unused_variables))]
+  pub(crate) async fn resolve(
+    &self,
+    root: &Path,
+    file: &Path,
+    known_sha256: Option<&str>,
+    etag: Option<&str>,
+    url: &str,
+  ) -> Result<PathBuf, ProgressDownloadError> {
+    match self {
+      #[cfg(feature = "sha2")]
+      ContentAddress::Sha256 => {
+        let digest = match known_sha256 {
+          Some(digest) => digest.to_string(),
+          None => {
+            Hashery::builder()
+              .algorithm(hashery::Algorithm::SHA256)
+              .build()
+              .digest(file)
+              .await?
+          }
+        };
+        Ok(sha256_path(root, &digest))
+      }
+      ContentAddress::ETag => {
+        let etag = etag
+          .map(sanitize_etag)
+          .filter(|etag| !etag.is_empty())
+          .ok_or_else(|| ProgressDownloadError::MissingETag {
+            url: url.to_string(),
+          })?;
+        Ok(root.join("etag").join(etag))
+      }
+    }
+  }
+}
+
+/// `<root>/sha256/ab/cd/abcd…`
+#[cfg(feature = "sha2")]
+pub(crate) fn sha256_path(root: &Path, digest: &str) -> PathBuf {
+  root
+    .join("sha256")
+    .join(&digest[..2])
+    .join(&digest[2..4])
+    .join(digest)
+}
+
+/// Moves a verified file into the content-addressed `store`, unless an identical copy is
+/// already there, and points `target` at the stored copy with a symlink.
+#[cfg(feature = "sha2")]
+pub(crate) async fn link_into_store(
+  store: &Path,
+  staged: &Path,
+  known_sha256: Option<&str>,
+  target: &Path,
+) -> Result<PathBuf, ProgressDownloadError> {
+  let stored = ContentAddress::Sha256
+    .resolve(store, staged, known_sha256, None, "")
+    .await?;
+
+  if tokio::fs::try_exists(&stored).await? {
+    tokio::fs::remove_file(staged).await?;
+  } else {
+    LocalStorage.place(staged, &stored).await?;
+  }
+
+  relink(&stored, target).await?;
+  Ok(stored)
+}
+
+/// Makes `target` a symlink to `stored`, replacing whatever is there unless it already
+/// points at `stored`. Repairs dangling links left behind by a cleaned-up store.
+#[cfg(feature = "sha2")]
+pub(crate) async fn relink(stored: &Path, target: &Path) -> std::io::Result<()> {
+  // Links must not depend on the working directory of the process that made them.
+  let stored = std::path::absolute(stored)?;
+
+  if tokio::fs::symlink_metadata(target).await.is_ok() {
+    if tokio::fs::read_link(target)
+      .await
+      .is_ok_and(|current| current == stored)
+    {
+      return Ok(());
+    }
+    tokio::fs::remove_file(target).await?;
+  } else if let Some(parent) = target.parent() {
+    tokio::fs::create_dir_all(parent).await?;
+  }
+
+  #[cfg(unix)]
+  tokio::fs::symlink(&stored, target).await?;
+  #[cfg(windows)]
+  tokio::fs::symlink_file(&stored, target).await?;
+  Ok(())
+}
+
+fn sanitize_etag(etag: &str) -> String {
+  etag
+    .trim()
+    .trim_start_matches("W/")
+    .trim_matches('"')
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect::<String>()
+    .trim_start_matches('.')
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_sanitize_etag() {
+    assert_eq!(sanitize_etag("\"abc-123\""), "abc-123");
+    assert_eq!(sanitize_etag("W/\"5f/3a:1\""), "5f_3a_1");
+    assert_eq!(sanitize_etag("\"..\""), "");
+  }
+
+  #[cfg(all(unix, feature = "sha2"))]
+  #[tokio::test]
+  async fn test_link_into_store_reuses_stored_copy() {
+    let dir = std::env::temp_dir().join(format!("rd-link-farm-{}", std::process::id()));
+    let store = dir.join("store");
+    let digest = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+
+    for project in ["a", "b"] {
+      let staged = dir.join(format!("{project}.part"));
+      tokio::fs::write(&staged, b"").await.unwrap();
+      let target = dir.join(project).join("tool");
+      let stored = link_into_store(&store, &staged, Some(digest), &target)
+        .await
+        .unwrap();
+
+      assert_eq!(stored, sha256_path(&store, digest));
+      assert!(!staged.exists());
+      assert_eq!(
+        tokio::fs::read_link(&target).await.unwrap(),
+        std::path::absolute(&stored).unwrap()
+      );
+    }
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
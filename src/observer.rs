@@ -0,0 +1,515 @@
+use std::{
+  fmt::Debug,
+  path::PathBuf,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use crate::{clock::Clock, err::ProgressDownloadError, report::DownloadOutcome};
+
+/// Ids are unique within the process, so observers shared between downloaders never see
+/// two items with the same id.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The item an observer event is about.
+#[derive(Debug, Clone)]
+pub struct ObservedItem {
+  /// Unique within the process; the same for every event of one item.
+  pub id: u64,
+  pub url: String,
+  pub target: PathBuf,
+  pub tags: Vec<String>,
+}
+
+impl ObservedItem {
+  pub(crate) fn new(url: String, target: PathBuf, tags: Vec<String>) -> Self {
+    Self {
+      id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+      url,
+      target,
+      tags,
+    }
+  }
+}
+
+/// How far an attempt has come.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+  /// Bytes of the file on disk, including those resumed from a previous attempt.
+  pub downloaded: u64,
+  /// Expected size of the file: the resumed bytes plus the size the server announced for
+  /// this attempt, which is nothing when it did not tell.
+  pub total: u64,
+  /// 1-based number of the attempt at the item's current URL.
+  pub attempt: u32,
+  /// How many attempts the URL gets at most; `None` when only the retry budget's time
+  /// limits them.
+  pub max_attempts: Option<u32>,
+}
+
+/// Where an attempt at an item is, in the order an attempt goes through them.
+///
+/// The target is only written in [`Placing`](Self::Placing), once the bytes passed every
+/// check of [`Verifying`](Self::Verifying): until then they are only in the staging file,
+/// so something watching the target's directory never sees a file that is not verified
+/// under the target's name. An attempt that fails stops in the state it failed in, and the
+/// next one starts over at [`Connecting`](Self::Connecting).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum TaskState {
+  /// Sending the request and waiting for the response headers.
+  #[default]
+  Connecting,
+  /// Writing the body to the staging file.
+  Streaming,
+  /// Checking the complete staging file: its size, integrity, checksum headers and pin.
+  Verifying,
+  /// Moving the verified file to the target and any extra targets.
+  Placing,
+  /// The file is at the target.
+  Done,
+}
+
+/// An attempt that failed and is about to be retried.
+#[derive(Debug)]
+pub struct Retry<'a> {
+  /// How many attempts have failed so far.
+  pub failed_attempts: u32,
+  /// How many attempts the URL gets at most; `None` when only the retry budget's time
+  /// limits them.
+  pub max_attempts: Option<u32>,
+  /// The URL being retried, which may be a mirror of the item's URL.
+  pub url: &'a str,
+  /// How long until the next attempt.
+  pub wait: Duration,
+  /// What is left of the retry budget, if there is one.
+  pub budget_left: Option<Duration>,
+  pub error: &'a ProgressDownloadError,
+}
+
+/// Receives the progress of every item, e.g. to drive a GUI instead of terminal progress
+/// bars.
+///
+/// Every item gets exactly one `on_start` once it has a concurrency slot, followed by any
+/// number of `on_chunk`, `on_state` and `on_retry` calls and exactly one of `on_complete`
+/// or `on_error`. Each attempt reports its [`TaskState`]s in order, from `Connecting` up to
+/// `Done` or the state it failed in. An item whose download future is dropped ends with
+/// `on_error` and [`ProgressDownloadError::Cancelled`]. Calls come from the download tasks
+/// themselves, so they should return quickly.
+///
+/// `on_chunk` keeps to the downloader's `progress_interval`, so a UI can redraw on every
+/// call: chunks arriving faster are coalesced into one call per interval, and while an
+/// item runs without new bytes (stalled, or waiting for a retry) its last progress is
+/// repeated once per interval. The final progress of an item is always reported before
+/// `on_complete`.
+///
+/// With the `progress-bars` feature, [`TerminalProgress`](crate::TerminalProgress) draws
+/// `indicatif` progress bars and is used unless an observer is set or the downloader is
+/// quiet.
+pub trait ProgressObserver: Debug + Send + Sync {
+  fn on_start(&self, item: &ObservedItem) {
+    let _ = item;
+  }
+
+  /// Called when an attempt starts and then once per progress interval.
+  fn on_chunk(&self, item: &ObservedItem, progress: Progress) {
+    let _ = (item, progress);
+  }
+
+  /// Called when an attempt enters a new [`TaskState`].
+  fn on_state(&self, item: &ObservedItem, state: TaskState) {
+    let _ = (item, state);
+  }
+
+  fn on_retry(&self, item: &ObservedItem, retry: &Retry<'_>) {
+    let _ = (item, retry);
+  }
+
+  fn on_complete(&self, item: &ObservedItem, outcome: DownloadOutcome) {
+    let _ = (item, outcome);
+  }
+
+  fn on_error(&self, item: &ObservedItem, error: &ProgressDownloadError) {
+    let _ = (item, error);
+  }
+}
+
+/// The status line of an item, as shown next to its progress bar.
+#[cfg_attr(not(feature = "progress-bars"), allow(dead_code))]
+pub(crate) fn progress_message(item: &ObservedItem, progress: Progress) -> String {
+  let percentage = (progress.downloaded * 100)
+    .checked_div(progress.total)
+    .unwrap_or(0);
+  if progress.attempt > 1 {
+    format!(
+      "{}% {} (attempt {}) ",
+      percentage,
+      item.url,
+      attempt_count(progress.attempt, progress.max_attempts)
+    )
+  } else {
+    format!("{}% {} ", percentage, item.url)
+  }
+}
+
+/// The retry line of an item, as shown next to its progress bar.
+#[cfg_attr(not(feature = "progress-bars"), allow(dead_code))]
+pub(crate) fn retry_message(retry: &Retry<'_>) -> String {
+  let remaining = retry
+    .budget_left
+    .map(|left| format!(", {}s of retry budget left", left.as_secs()))
+    .unwrap_or_default();
+  format!(
+    "attempt {} failed, retrying in {:.1}s{} {}",
+    attempt_count(retry.failed_attempts, retry.max_attempts),
+    retry.wait.as_secs_f64(),
+    remaining,
+    retry.url
+  )
+}
+
+/// `3/7` out of a known number of attempts, just `3` otherwise.
+#[cfg_attr(not(feature = "progress-bars"), allow(dead_code))]
+fn attempt_count(attempt: u32, max_attempts: Option<u32>) -> String {
+  match max_attempts {
+    Some(max) => format!("{attempt}/{max}"),
+    None => attempt.to_string(),
+  }
+}
+
+/// Reports the events of one item to the downloader's observer, if any, and makes sure
+/// the item's last event is sent exactly once.
+#[derive(Debug)]
+pub(crate) struct ItemObserver {
+  observer: Option<Arc<dyn ProgressObserver>>,
+  item: ObservedItem,
+  done: AtomicBool,
+  /// Zero reports every chunk and never repeats.
+  interval: Duration,
+  clock: Arc<dyn Clock>,
+  cadence: Mutex<Cadence>,
+}
+
+#[derive(Debug, Default)]
+struct Cadence {
+  latest: Option<Progress>,
+  reported: Option<(Instant, Progress)>,
+}
+
+impl ItemObserver {
+  pub fn new(
+    observer: Option<Arc<dyn ProgressObserver>>,
+    interval: Duration,
+    clock: Arc<dyn Clock>,
+    url: String,
+    target: PathBuf,
+    tags: Vec<String>,
+  ) -> Self {
+    Self {
+      observer,
+      item: ObservedItem::new(url, target, tags),
+      done: AtomicBool::new(false),
+      interval,
+      clock,
+      cadence: Mutex::default(),
+    }
+  }
+
+  pub fn start(&self) {
+    if let Some(observer) = &self.observer {
+      observer.on_start(&self.item);
+    }
+  }
+
+  pub fn chunk(&self, progress: Progress) {
+    let Some(observer) = &self.observer else {
+      return;
+    };
+    let due = {
+      let mut cadence = self.lock();
+      cadence.latest = Some(progress);
+      // A new attempt is reported right away, however recent the last report.
+      let due = cadence.reported.is_none_or(|(at, reported)| {
+        self.clock.elapsed(at) >= self.interval || reported.attempt != progress.attempt
+      });
+      if due {
+        cadence.reported = Some((self.clock.now(), progress));
+      }
+      due
+    };
+    if due {
+      observer.on_chunk(&self.item, progress);
+    }
+  }
+
+  /// Repeats the last progress whenever an interval passes without a report. Never
+  /// returns; meant to run next to the item's download.
+  pub async fn heartbeat(&self) {
+    if self.observer.is_none() || self.interval.is_zero() {
+      return std::future::pending().await;
+    }
+    let mut ticks = tokio::time::interval(self.interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+      ticks.tick().await;
+      let stale = {
+        let mut cadence = self.lock();
+        let stale = cadence.latest.filter(|_| {
+          cadence
+            .reported
+            .is_none_or(|(at, _)| self.clock.elapsed(at) >= self.interval)
+        });
+        if let Some(progress) = stale {
+          cadence.reported = Some((self.clock.now(), progress));
+        }
+        stale
+      };
+      if let (Some(observer), Some(progress)) = (&self.observer, stale) {
+        observer.on_chunk(&self.item, progress);
+      }
+    }
+  }
+
+  pub fn state(&self, state: TaskState) {
+    if let Some(observer) = &self.observer {
+      observer.on_state(&self.item, state);
+    }
+  }
+
+  pub fn retry(&self, retry: &Retry<'_>) {
+    if let Some(observer) = &self.observer {
+      observer.on_retry(&self.item, retry);
+    }
+  }
+
+  pub fn complete(&self, outcome: DownloadOutcome) {
+    if self.done.swap(true, Ordering::Relaxed) {
+      return;
+    }
+    let Some(observer) = &self.observer else {
+      return;
+    };
+    // The last chunks may have been coalesced away.
+    let pending = {
+      let cadence = self.lock();
+      cadence.latest.filter(|latest| {
+        cadence
+          .reported
+          .is_none_or(|(_, reported)| reported != *latest)
+      })
+    };
+    if let Some(progress) = pending {
+      observer.on_chunk(&self.item, progress);
+    }
+    observer.on_complete(&self.item, outcome);
+  }
+
+  pub fn fail(&self, error: &ProgressDownloadError) {
+    if self.done.swap(true, Ordering::Relaxed) {
+      return;
+    }
+    if let Some(observer) = &self.observer {
+      observer.on_error(&self.item, error);
+    }
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Cadence> {
+    self.cadence.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl Drop for ItemObserver {
+  fn drop(&mut self) {
+    self.fail(&ProgressDownloadError::Cancelled);
+  }
+}
+
+#[cfg(feature = "progress-bars")]
+pub use terminal::TerminalProgress;
+
+#[cfg(feature = "progress-bars")]
+mod terminal {
+  use std::{collections::HashMap, fmt, sync::Mutex};
+
+  use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+  use super::{ObservedItem, Progress, ProgressObserver, Retry, progress_message, retry_message};
+  use crate::{err::ProgressDownloadError, report::DownloadOutcome};
+
+  /// Draws one `indicatif` progress bar per running item.
+  ///
+  /// Completed items are cleared from the terminal; failed ones stay, abandoned, with
+  /// their last message. Pass your own [`MultiProgress`] to
+  /// [`with_multi_progress`](Self::with_multi_progress) to draw next to other bars.
+  pub struct TerminalProgress {
+    mp: MultiProgress,
+    style: ProgressStyle,
+    bars: Mutex<HashMap<u64, ProgressBar>>,
+  }
+
+  // `ProgressStyle` is not `Debug`.
+  impl fmt::Debug for TerminalProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      f.debug_struct("TerminalProgress")
+        .field("mp", &self.mp)
+        .field("bars", &self.bars)
+        .finish_non_exhaustive()
+    }
+  }
+
+  impl Default for TerminalProgress {
+    fn default() -> Self {
+      Self::new()
+    }
+  }
+
+  impl TerminalProgress {
+    pub fn new() -> Self {
+      Self::with_multi_progress(MultiProgress::new())
+    }
+
+    pub fn with_multi_progress(mp: MultiProgress) -> Self {
+      let style = ProgressStyle::with_template(
+        "{spinner:.green} [{elapsed_precise}] {bar:25.green/white.dim} {bytes}/{total_bytes} {prefix:.cyan}{wide_msg:.dim}",
+      )
+      .unwrap()
+      .progress_chars("━━");
+      Self {
+        mp,
+        style,
+        bars: Mutex::default(),
+      }
+    }
+
+    fn bar(&self, item: &ObservedItem) -> Option<ProgressBar> {
+      self.lock().get(&item.id).cloned()
+    }
+
+    fn take(&self, item: &ObservedItem) -> Option<ProgressBar> {
+      self.lock().remove(&item.id)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, ProgressBar>> {
+      self.bars.lock().unwrap_or_else(|e| e.into_inner())
+    }
+  }
+
+  impl ProgressObserver for TerminalProgress {
+    fn on_start(&self, item: &ObservedItem) {
+      let bar = ProgressBar::new(0).with_style(self.style.clone());
+      if !item.tags.is_empty() {
+        bar.set_prefix(format!("[{}] ", item.tags.join(",")));
+      }
+      let bar = self.mp.add(bar);
+      self.lock().insert(item.id, bar);
+    }
+
+    fn on_chunk(&self, item: &ObservedItem, progress: Progress) {
+      if let Some(bar) = self.bar(item) {
+        bar.set_length(progress.total);
+        bar.set_position(progress.downloaded);
+        bar.set_message(progress_message(item, progress));
+      }
+    }
+
+    fn on_retry(&self, item: &ObservedItem, retry: &Retry<'_>) {
+      if let Some(bar) = self.bar(item) {
+        bar.set_message(retry_message(retry));
+      }
+    }
+
+    fn on_complete(&self, item: &ObservedItem, _outcome: DownloadOutcome) {
+      if let Some(bar) = self.take(item) {
+        bar.finish_and_clear();
+        self.mp.remove(&bar);
+      }
+    }
+
+    fn on_error(&self, item: &ObservedItem, _error: &ProgressDownloadError) {
+      if let Some(bar) = self.take(item) {
+        bar.abandon();
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, Default)]
+  struct Recorder(Mutex<Vec<u64>>);
+
+  impl ProgressObserver for Recorder {
+    fn on_chunk(&self, _item: &ObservedItem, progress: Progress) {
+      self.0.lock().unwrap().push(progress.downloaded);
+    }
+  }
+
+  #[test]
+  fn test_chunks_are_coalesced_and_flushed_on_complete() {
+    let recorder = Arc::new(Recorder::default());
+    let observed = ItemObserver::new(
+      Some(recorder.clone()),
+      Duration::from_secs(3600),
+      Arc::new(crate::clock::SystemClock),
+      "https://example.com/a".to_string(),
+      PathBuf::from("a"),
+      Vec::new(),
+    );
+    let progress = |downloaded, attempt| Progress {
+      downloaded,
+      total: 30,
+      attempt,
+      max_attempts: Some(3),
+    };
+
+    observed.chunk(progress(0, 1));
+    observed.chunk(progress(10, 1));
+    // A retry is reported at once.
+    observed.chunk(progress(0, 2));
+    observed.chunk(progress(30, 2));
+    observed.complete(DownloadOutcome::Downloaded);
+
+    assert_eq!(*recorder.0.lock().unwrap(), vec![0, 0, 30]);
+  }
+
+  #[test]
+  fn test_messages_count_attempts() {
+    let item = ObservedItem {
+      id: 0,
+      url: "https://example.com/a".to_string(),
+      target: PathBuf::new(),
+      tags: Vec::new(),
+    };
+    let progress = |max_attempts| Progress {
+      downloaded: 15,
+      total: 30,
+      attempt: 3,
+      max_attempts,
+    };
+    assert_eq!(
+      progress_message(&item, progress(Some(7))),
+      "50% https://example.com/a (attempt 3/7) "
+    );
+    assert_eq!(
+      progress_message(&item, progress(None)),
+      "50% https://example.com/a (attempt 3) "
+    );
+
+    let retry = Retry {
+      failed_attempts: 2,
+      max_attempts: Some(7),
+      url: "https://example.com/a",
+      wait: Duration::from_secs(1),
+      budget_left: None,
+      error: &ProgressDownloadError::Cancelled,
+    };
+    assert_eq!(
+      retry_message(&retry),
+      "attempt 2/7 failed, retrying in 1.0s https://example.com/a"
+    );
+  }
+}
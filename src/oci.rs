@@ -0,0 +1,447 @@
+//! Pulling blobs and image layers from OCI registries as [`DownloadItem`]s.
+//!
+//! Registries answer anonymous requests with `401` and a `WWW-Authenticate: Bearer` challenge
+//! naming a token endpoint; the token fetched from there (optionally with basic credentials)
+//! authorizes the manifest and blob requests. Every layer's digest from the manifest becomes the
+//! item's [`Integrity`], so a corrupted or tampered layer fails like any other hash mismatch.
+//! Blob requests usually redirect to a CDN; the default [`RedirectPolicy`](crate::RedirectPolicy)
+//! drops the bearer token on that hop.
+//!
+//! ```rust,no_run
+//! use robust_downloader::{RobustDownloader, oci::{OciAuth, OciReference}};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let image: OciReference = "ghcr.io/acme/tools:1.2".parse()?;
+//! let items = image
+//!   .resolve_layers(&OciAuth::Anonymous, "linux/amd64", "layers")
+//!   .await?;
+//!
+//! RobustDownloader::builder().build().download(items).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{path::Path, path::PathBuf, str::FromStr};
+
+use cow_utils::CowUtils;
+use reqwest::{
+  StatusCode,
+  header::{ACCEPT, AUTHORIZATION, HeaderMap, HeaderValue, WWW_AUTHENTICATE},
+};
+use serde::Deserialize;
+
+use crate::{err::ProgressDownloadError, integrity::Integrity, item::DownloadItem};
+
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_API: &str = "registry-1.docker.io";
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+  application/vnd.oci.image.manifest.v1+json, \
+  application/vnd.docker.distribution.manifest.list.v2+json, \
+  application/vnd.docker.distribution.manifest.v2+json";
+
+/// How to authenticate against the registry's token endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OciAuth {
+  /// Ask for an anonymous pull token; enough for public images.
+  Anonymous,
+  /// Exchange username and password (or a personal access token) for a pull token.
+  Basic { username: String, password: String },
+  /// Use an already issued registry token as is.
+  Bearer(String),
+}
+
+/// An image reference, written `[registry/]repository[:tag|@digest]`.
+///
+/// Without a registry the reference points at Docker Hub, where single-component names live
+/// under `library/`. Without a tag or digest it points at `latest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciReference {
+  pub registry: String,
+  pub repository: String,
+  /// A tag or a `algorithm:hex` manifest digest.
+  pub reference: String,
+}
+
+impl FromStr for OciReference {
+  type Err = ProgressDownloadError;
+
+  fn from_str(spec: &str) -> Result<Self, Self::Err> {
+    let invalid = || ProgressDownloadError::InvalidOciReference {
+      reference: spec.to_string(),
+    };
+
+    let (name, reference) = match spec.split_once('@') {
+      Some((name, digest)) => (name, digest.to_string()),
+      None => match spec.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+        _ => (spec, "latest".to_string()),
+      },
+    };
+
+    let (registry, repository) = match name.split_once('/') {
+      Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => {
+        (host.to_string(), rest.to_string())
+      }
+      _ => (DOCKER_HUB.to_string(), name.to_string()),
+    };
+    let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+      format!("library/{repository}")
+    } else {
+      repository
+    };
+
+    if repository.is_empty() || reference.is_empty() || repository.split('/').any(str::is_empty) {
+      return Err(invalid());
+    }
+
+    Ok(Self {
+      registry,
+      repository,
+      reference,
+    })
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+  media_type: Option<String>,
+  #[serde(default)]
+  manifests: Vec<Descriptor>,
+  config: Option<Descriptor>,
+  #[serde(default)]
+  layers: Vec<Descriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+  digest: String,
+  platform: Option<Platform>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Platform {
+  os: String,
+  architecture: String,
+  variant: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  token: Option<String>,
+  access_token: Option<String>,
+}
+
+impl OciReference {
+  /// Resolves the image manifest and returns one item per layer, each saved as
+  /// `target_dir/<algorithm>-<hex>`.
+  ///
+  /// Multi-platform images are narrowed to `platform`, written `os/architecture[/variant]`
+  /// (e.g. `linux/arm64/v8`); single-platform images ignore it.
+  pub async fn resolve_layers(
+    &self,
+    auth: &OciAuth,
+    platform: &str,
+    target_dir: impl AsRef<Path>,
+  ) -> Result<Vec<DownloadItem<String, PathBuf>>, ProgressDownloadError> {
+    let client = reqwest::Client::new();
+    let headers = self.authorize(&client, auth).await?;
+
+    let mut manifest = self
+      .fetch_manifest(&client, &headers, &self.reference)
+      .await?;
+    if !manifest.manifests.is_empty() {
+      let digest = manifest
+        .manifests
+        .iter()
+        .find(|entry| entry.platform.as_ref().is_some_and(|p| p.matches(platform)))
+        .map(|entry| entry.digest.clone())
+        .ok_or_else(|| ProgressDownloadError::OciPlatformNotFound {
+          reference: self.to_string(),
+          platform: platform.to_string(),
+        })?;
+      manifest = self.fetch_manifest(&client, &headers, &digest).await?;
+    }
+
+    if manifest.layers.is_empty() {
+      return Err(self.invalid_manifest());
+    }
+
+    manifest
+      .layers
+      .iter()
+      .map(|layer| self.blob_item(&headers, &layer.digest, target_dir.as_ref()))
+      .collect()
+  }
+
+  /// Resolves the image config blob (the JSON holding the image's environment, entrypoint and
+  /// history) and returns an item that downloads it to `target`.
+  pub async fn resolve_config<P>(
+    &self,
+    auth: &OciAuth,
+    target: P,
+  ) -> Result<DownloadItem<String, P>, ProgressDownloadError> {
+    let client = reqwest::Client::new();
+    let headers = self.authorize(&client, auth).await?;
+    let manifest = self
+      .fetch_manifest(&client, &headers, &self.reference)
+      .await?;
+    let config = manifest.config.ok_or_else(|| self.invalid_manifest())?;
+
+    Ok(
+      DownloadItem::builder()
+        .url(self.blob_url(&config.digest))
+        .target(target)
+        .headers(headers)
+        .integrity(self.integrity(&config.digest)?)
+        .build(),
+    )
+  }
+
+  fn blob_item(
+    &self,
+    headers: &HeaderMap,
+    digest: &str,
+    target_dir: &Path,
+  ) -> Result<DownloadItem<String, PathBuf>, ProgressDownloadError> {
+    Ok(
+      DownloadItem::builder()
+        .url(self.blob_url(digest))
+        .target(target_dir.join(digest.cow_replace(':', "-").as_ref()))
+        .headers(headers.clone())
+        .integrity(self.integrity(digest)?)
+        .build(),
+    )
+  }
+
+  fn api_root(&self) -> String {
+    let host = if self.registry == DOCKER_HUB {
+      DOCKER_HUB_API
+    } else {
+      &self.registry
+    };
+    format!("https://{host}/v2/{}", self.repository)
+  }
+
+  fn blob_url(&self, digest: &str) -> String {
+    format!("{}/blobs/{digest}", self.api_root())
+  }
+
+  fn integrity(&self, digest: &str) -> Result<Integrity, ProgressDownloadError> {
+    match digest.split_once(':') {
+      Some(("sha256", hex)) => Ok(Integrity::SHA256(hex.to_string())),
+      Some(("sha512", hex)) => Ok(Integrity::SHA512(hex.to_string())),
+      _ => Err(self.invalid_manifest()),
+    }
+  }
+
+  /// Performs the token dance and returns the headers that authorize pulls from the repository.
+  async fn authorize(
+    &self,
+    client: &reqwest::Client,
+    auth: &OciAuth,
+  ) -> Result<HeaderMap, ProgressDownloadError> {
+    let mut headers = HeaderMap::new();
+    if let OciAuth::Bearer(token) = auth {
+      headers.insert(AUTHORIZATION, self.bearer(token)?);
+      return Ok(headers);
+    }
+
+    // A HEAD on the manifest tells us whether the registry wants a token and where to get it.
+    let probe = client
+      .head(format!("{}/manifests/{}", self.api_root(), self.reference))
+      .header(ACCEPT, MANIFEST_TYPES)
+      .send()
+      .await?;
+    if probe.status() != StatusCode::UNAUTHORIZED {
+      return Ok(headers);
+    }
+
+    let challenge = probe
+      .headers()
+      .get(WWW_AUTHENTICATE)
+      .and_then(|value| value.to_str().ok())
+      .and_then(BearerChallenge::parse)
+      .ok_or_else(|| self.invalid_manifest())?;
+
+    let mut request = client.get(&challenge.realm);
+    let scope = challenge
+      .scope
+      .unwrap_or_else(|| format!("repository:{}:pull", self.repository));
+    request = request.query(&[("scope", scope)]);
+    if let Some(service) = challenge.service {
+      request = request.query(&[("service", service)]);
+    }
+    if let OciAuth::Basic { username, password } = auth {
+      request = request.basic_auth(username, Some(password));
+    }
+
+    let body = request.send().await?.error_for_status()?.bytes().await?;
+    let response: TokenResponse =
+      serde_json::from_slice(&body).map_err(|_| self.invalid_manifest())?;
+    let token = response
+      .token
+      .or(response.access_token)
+      .ok_or_else(|| self.invalid_manifest())?;
+
+    headers.insert(AUTHORIZATION, self.bearer(&token)?);
+    Ok(headers)
+  }
+
+  async fn fetch_manifest(
+    &self,
+    client: &reqwest::Client,
+    headers: &HeaderMap,
+    reference: &str,
+  ) -> Result<Manifest, ProgressDownloadError> {
+    let body = client
+      .get(format!("{}/manifests/{reference}", self.api_root()))
+      .headers(headers.clone())
+      .header(ACCEPT, MANIFEST_TYPES)
+      .send()
+      .await?
+      .error_for_status()?
+      .bytes()
+      .await?;
+    let manifest: Manifest = serde_json::from_slice(&body).map_err(|_| self.invalid_manifest())?;
+
+    let is_index = manifest.media_type.as_deref().is_some_and(|media_type| {
+      media_type.ends_with("index.v1+json") || media_type.ends_with("list.v2+json")
+    });
+    if is_index && manifest.manifests.is_empty() {
+      return Err(self.invalid_manifest());
+    }
+    Ok(manifest)
+  }
+
+  fn bearer(&self, token: &str) -> Result<HeaderValue, ProgressDownloadError> {
+    let mut value =
+      HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| self.invalid_manifest())?;
+    value.set_sensitive(true);
+    Ok(value)
+  }
+
+  fn invalid_manifest(&self) -> ProgressDownloadError {
+    ProgressDownloadError::InvalidOciManifest {
+      reference: self.to_string(),
+    }
+  }
+}
+
+impl std::fmt::Display for OciReference {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let separator = if self.reference.contains(':') {
+      '@'
+    } else {
+      ':'
+    };
+    write!(
+      f,
+      "{}/{}{separator}{}",
+      self.registry, self.repository, self.reference
+    )
+  }
+}
+
+impl Platform {
+  fn matches(&self, spec: &str) -> bool {
+    let mut parts = spec.split('/');
+    let (Some(os), Some(architecture)) = (parts.next(), parts.next()) else {
+      return false;
+    };
+    let variant = parts.next();
+
+    self.os == os
+      && self.architecture == architecture
+      && variant.is_none_or(|variant| self.variant.as_deref() == Some(variant))
+  }
+}
+
+/// The parameters of a `WWW-Authenticate: Bearer ...` challenge.
+#[derive(Debug, PartialEq, Eq)]
+struct BearerChallenge {
+  realm: String,
+  service: Option<String>,
+  scope: Option<String>,
+}
+
+impl BearerChallenge {
+  fn parse(header: &str) -> Option<Self> {
+    let params = header.strip_prefix("Bearer ")?;
+
+    let mut realm = None;
+    let mut service = None;
+    let mut scope = None;
+    let mut rest = params.trim();
+    while !rest.is_empty() {
+      let (key, after) = rest.split_once('=')?;
+      let (value, after) = match after.strip_prefix('"') {
+        // Quoted values may contain commas, e.g. `repository:a/b:pull,push`.
+        Some(quoted) => {
+          let (value, after) = quoted.split_once('"')?;
+          (value, after)
+        }
+        None => after
+          .split_once(',')
+          .map_or((after, ""), |(value, after)| (value, after)),
+      };
+      match key.trim() {
+        "realm" => realm = Some(value.to_string()),
+        "service" => service = Some(value.to_string()),
+        "scope" => scope = Some(value.to_string()),
+        _ => {}
+      }
+      rest = after.trim_start_matches([',', ' ']);
+    }
+
+    Some(Self {
+      realm: realm?,
+      service,
+      scope,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_oci_reference() {
+    let image: OciReference = "ubuntu".parse().unwrap();
+    assert_eq!(image.registry, "docker.io");
+    assert_eq!(image.repository, "library/ubuntu");
+    assert_eq!(image.reference, "latest");
+
+    let image: OciReference = "localhost:5000/team/app:v2".parse().unwrap();
+    assert_eq!(image.registry, "localhost:5000");
+    assert_eq!(image.repository, "team/app");
+    assert_eq!(image.reference, "v2");
+
+    let image: OciReference = "ghcr.io/acme/tools@sha256:abc".parse().unwrap();
+    assert_eq!(image.registry, "ghcr.io");
+    assert_eq!(image.repository, "acme/tools");
+    assert_eq!(image.reference, "sha256:abc");
+    assert_eq!(image.to_string(), "ghcr.io/acme/tools@sha256:abc");
+
+    assert!("ghcr.io//tools".parse::<OciReference>().is_err());
+    assert!("ubuntu@".parse::<OciReference>().is_err());
+  }
+
+  #[test]
+  fn test_parse_bearer_challenge() {
+    let challenge = BearerChallenge::parse(
+      r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/ubuntu:pull,push""#,
+    )
+    .unwrap();
+    assert_eq!(challenge.realm, "https://auth.docker.io/token");
+    assert_eq!(challenge.service.as_deref(), Some("registry.docker.io"));
+    assert_eq!(
+      challenge.scope.as_deref(),
+      Some("repository:library/ubuntu:pull,push")
+    );
+
+    assert!(BearerChallenge::parse(r#"Basic realm="registry""#).is_none());
+  }
+}
@@ -0,0 +1,99 @@
+use std::{
+  ffi::OsString,
+  path::{Path, PathBuf},
+};
+
+use crate::err::ProgressDownloadError;
+
+/// What happens to an item whose target already exists, decided before any request is
+/// made. Set on the downloader and overridden per [`DownloadItem`](crate::DownloadItem).
+///
+/// Targets that already match the item's integrity are reported as
+/// [`AlreadyValid`](crate::DownloadOutcome::AlreadyValid) before the policy is consulted,
+/// and content-addressed items ignore it. For
+/// [`target_is_dir`](crate::DownloadItem::target_is_dir) items it is decided once the
+/// response names the file, before its body is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+  /// Replace the existing file once the download is complete and verified.
+  #[default]
+  Overwrite,
+  /// Leave the existing file alone and report the item as
+  /// [`Skipped`](crate::DownloadOutcome::Skipped).
+  Skip,
+  /// Fail the item with [`ProgressDownloadError::TargetExists`].
+  Error,
+  /// Download next to the existing file, under the first free name with a numeric suffix:
+  /// `report.pdf` becomes `report (1).pdf`, then `report (2).pdf`.
+  RenameWithSuffix,
+}
+
+impl OverwritePolicy {
+  /// Returns where to download to, or `None` if the item is skipped.
+  pub(crate) async fn resolve(
+    self,
+    target: &Path,
+  ) -> Result<Option<PathBuf>, ProgressDownloadError> {
+    // A dangling symlink counts as existing: placing the file would replace it.
+    if self == OverwritePolicy::Overwrite || !exists(target).await {
+      return Ok(Some(target.to_path_buf()));
+    }
+
+    match self {
+      OverwritePolicy::Overwrite => Ok(Some(target.to_path_buf())),
+      OverwritePolicy::Skip => Ok(None),
+      OverwritePolicy::Error => Err(ProgressDownloadError::TargetExists {
+        path: target.to_string_lossy().to_string(),
+      }),
+      OverwritePolicy::RenameWithSuffix => {
+        let mut n = 1;
+        loop {
+          let candidate = with_suffix(target, n);
+          if !exists(&candidate).await {
+            return Ok(Some(candidate));
+          }
+          n += 1;
+        }
+      }
+    }
+  }
+}
+
+async fn exists(path: &Path) -> bool {
+  tokio::fs::symlink_metadata(path).await.is_ok()
+}
+
+/// `dir/name (n).ext`. The suffix goes before the first dot of the file name, so that
+/// `archive.tar.gz` keeps its double extension; a leading dot is part of the name.
+fn with_suffix(target: &Path, n: u32) -> PathBuf {
+  let name = target
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  let split = name
+    .char_indices()
+    .skip(1)
+    .find(|&(_, c)| c == '.')
+    .map_or(name.len(), |(index, _)| index);
+  let (stem, extension) = name.split_at(split);
+
+  let mut renamed = OsString::from(stem);
+  renamed.push(format!(" ({n})"));
+  renamed.push(extension);
+  target.with_file_name(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_suffix_goes_before_the_extensions() {
+    let suffixed = |path: &str| with_suffix(Path::new(path), 2);
+
+    assert_eq!(suffixed("out/report.pdf"), Path::new("out/report (2).pdf"));
+    assert_eq!(suffixed("archive.tar.gz"), Path::new("archive (2).tar.gz"));
+    assert_eq!(suffixed("out/.env"), Path::new("out/.env (2)"));
+    assert_eq!(suffixed("LICENSE"), Path::new("LICENSE (2)"));
+  }
+}
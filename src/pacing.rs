@@ -0,0 +1,118 @@
+use std::{
+  collections::HashMap,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use log::{debug, info};
+use reqwest::StatusCode;
+
+/// Delay between requests to a host right after its first 429.
+const MIN_DELAY: Duration = Duration::from_millis(250);
+/// The delay never grows beyond this, however often a host keeps answering 429.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+/// Successful responses in a row after which the delay is halved.
+const DECAY_AFTER: u32 = 8;
+
+/// Spaces out requests to hosts that answered 429 Too Many Requests, shared by every item
+/// of a downloader and its clones.
+///
+/// Each 429 doubles the delay between two requests to that host; every [`DECAY_AFTER`]
+/// successes in a row halve it again, until the host is left alone. Hosts that never
+/// answered 429 are not delayed at all.
+#[derive(Debug, Default)]
+pub(crate) struct HostPacer {
+  hosts: Mutex<HashMap<String, HostPace>>,
+}
+
+#[derive(Debug)]
+struct HostPace {
+  delay: Duration,
+  /// When the next request to the host may go out.
+  next_slot: Instant,
+  successes: u32,
+}
+
+impl HostPacer {
+  /// Waits for the next request slot of `host`, as of `now`.
+  pub async fn wait(&self, host: &str, now: Instant) {
+    let wait = self.reserve(host, now);
+    if !wait.is_zero() {
+      debug!("pacing request to {} by {:?}", host, wait);
+      tokio::time::sleep(wait).await;
+    }
+  }
+
+  /// Takes the next request slot of `host` and returns how long until it is due.
+  fn reserve(&self, host: &str, now: Instant) -> Duration {
+    let mut hosts = self.lock();
+    let Some(pace) = hosts.get_mut(host) else {
+      return Duration::ZERO;
+    };
+    let slot = pace.next_slot.max(now);
+    pace.next_slot = slot + pace.delay;
+    slot - now
+  }
+
+  /// Adjusts the delay of `host` to the status it answered with at `now`.
+  pub fn record(&self, host: &str, status: StatusCode, now: Instant) {
+    let mut hosts = self.lock();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+      let pace = hosts.entry(host.to_string()).or_insert(HostPace {
+        delay: Duration::ZERO,
+        next_slot: now,
+        successes: 0,
+      });
+      pace.delay = (pace.delay * 2).clamp(MIN_DELAY, MAX_DELAY);
+      pace.successes = 0;
+      pace.next_slot = pace.next_slot.max(now + pace.delay);
+      info!(
+        "{} is rate limiting, spacing requests {:?} apart",
+        host, pace.delay
+      );
+    } else if status.is_success() {
+      let Some(pace) = hosts.get_mut(host) else {
+        return;
+      };
+      pace.successes += 1;
+      if pace.successes < DECAY_AFTER {
+        return;
+      }
+      pace.successes = 0;
+      pace.delay /= 2;
+      if pace.delay < MIN_DELAY {
+        debug!("{} recovered, no longer pacing requests", host);
+        hosts.remove(host);
+      }
+    }
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, HostPace>> {
+    self.hosts.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_delay_grows_on_429_and_decays_on_success() {
+    let pacer = HostPacer::default();
+    let now = Instant::now();
+    assert_eq!(pacer.reserve("example.com", now), Duration::ZERO);
+
+    pacer.record("example.com", StatusCode::TOO_MANY_REQUESTS, now);
+    pacer.record("example.com", StatusCode::TOO_MANY_REQUESTS, now);
+    assert_eq!(pacer.reserve("example.com", now), MIN_DELAY * 2);
+    // Requests queue up behind one another.
+    assert_eq!(pacer.reserve("example.com", now), MIN_DELAY * 4);
+    // Other hosts are not affected.
+    assert_eq!(pacer.reserve("other.example.com", now), Duration::ZERO);
+
+    for _ in 0..DECAY_AFTER * 2 {
+      pacer.record("example.com", StatusCode::OK, now);
+    }
+    assert!(pacer.lock().is_empty());
+  }
+}
@@ -0,0 +1,166 @@
+use std::{
+  collections::HashMap,
+  io,
+  path::{Path, PathBuf},
+  sync::{
+    Mutex,
+    atomic::{AtomicBool, Ordering},
+  },
+};
+
+/// Trust-on-first-use pins: the SHA-256 of the first download of each URL.
+///
+/// For files without a published hash this still catches changes: once a URL is pinned,
+/// a download of it with different content fails with a pin mismatch instead of replacing
+/// the file. Items with their own `integrity` are checked against that instead and never
+/// pinned. Pins are keyed by the item's primary URL, so a mirror has to serve the same
+/// bytes. Verifying against pins needs the `sha2` feature.
+///
+/// The store file is a plain tab-separated text file; it is read by [`PinStore::open`]
+/// and written back by [`PinStore::save`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use robust_downloader::{DownloadItem, PinStore, RobustDownloader};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let pins = Arc::new(PinStore::open(".pins").await?);
+/// let downloader = RobustDownloader::builder().pin_store(pins.clone()).build();
+/// downloader
+///   .download(vec![
+///     DownloadItem::builder()
+///       .url("https://example.com/unsigned-tool.tar.gz")
+///       .target("local/unsigned-tool.tar.gz")
+///       .build(),
+///   ])
+///   .await?;
+/// pins.save().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct PinStore {
+  path: Option<PathBuf>,
+  pins: Mutex<HashMap<String, String>>,
+  dirty: AtomicBool,
+}
+
+impl PinStore {
+  /// Creates a store that lives only as long as this value.
+  pub fn in_memory() -> Self {
+    Self::default()
+  }
+
+  /// Loads the store saved at `path`. A missing file yields an empty store.
+  pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+    let path = path.as_ref().to_path_buf();
+
+    let content = match tokio::fs::read_to_string(&path).await {
+      Ok(content) => content,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+      Err(e) => return Err(e),
+    };
+
+    let pins = content
+      .lines()
+      .filter_map(|line| {
+        let (digest, url) = line.split_once('\t')?;
+        Some((url.to_string(), digest.to_string()))
+      })
+      .collect();
+
+    Ok(Self {
+      path: Some(path),
+      pins: Mutex::new(pins),
+      dirty: AtomicBool::new(false),
+    })
+  }
+
+  /// The SHA-256 pinned for `url`, if it was downloaded before.
+  pub fn get(&self, url: &str) -> Option<String> {
+    self.lock().get(url).cloned()
+  }
+
+  /// Pins `url` to `sha256`, replacing any earlier pin.
+  pub fn pin(&self, url: &str, sha256: &str) {
+    self.lock().insert(url.to_string(), sha256.to_string());
+    self.dirty.store(true, Ordering::Relaxed);
+  }
+
+  /// Forgets the pin of `url`, e.g. after checking that a reported change is legitimate;
+  /// its next download is pinned again. Returns whether it was pinned.
+  pub fn unpin(&self, url: &str) -> bool {
+    let removed = self.lock().remove(url).is_some();
+    if removed {
+      self.dirty.store(true, Ordering::Relaxed);
+    }
+    removed
+  }
+
+  /// Writes the store back to the file it was opened from, if anything changed.
+  ///
+  /// URLs containing newlines are only pinned in memory.
+  pub async fn save(&self) -> io::Result<()> {
+    let Some(path) = &self.path else {
+      return Ok(());
+    };
+
+    if !self.dirty.swap(false, Ordering::Relaxed) {
+      return Ok(());
+    }
+
+    let mut pins = self
+      .lock()
+      .iter()
+      .filter(|(url, _)| !url.contains('\n'))
+      .map(|(url, digest)| (url.clone(), digest.clone()))
+      .collect::<Vec<_>>();
+    // Sorted, so the file diffs cleanly when kept under version control.
+    pins.sort();
+    let content = pins
+      .iter()
+      .map(|(url, digest)| format!("{}\t{}\n", digest, url))
+      .collect::<String>();
+
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(&tmp, path).await
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+    self.pins.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_pins_survive_save_and_open() {
+    let dir = std::env::temp_dir().join(format!("rd-pins-{}", std::process::id()));
+    let path = dir.join("pins.tsv");
+
+    let pins = PinStore::open(&path).await.unwrap();
+    pins.pin("https://example.com/b", "bb");
+    pins.pin("https://example.com/a", "aa");
+    pins.save().await.unwrap();
+    assert_eq!(
+      tokio::fs::read_to_string(&path).await.unwrap(),
+      "aa\thttps://example.com/a\nbb\thttps://example.com/b\n"
+    );
+
+    let reopened = PinStore::open(&path).await.unwrap();
+    assert_eq!(reopened.get("https://example.com/a").as_deref(), Some("aa"));
+    assert!(reopened.unpin("https://example.com/a"));
+    assert_eq!(reopened.get("https://example.com/a"), None);
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
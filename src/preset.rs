@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use crate::retry::RetryPolicy;
+
+/// A ready-made combination of concurrency, timeouts and retry policy, see
+/// [`RobustDownloader::preset`](crate::RobustDownloader::preset).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Profile {
+  /// Many downloads at once, quick to give up on a slow connection and quick to retry,
+  /// for fast networks and servers that can take the load. Retries stop after 30 seconds.
+  Aggressive,
+  /// The builder's defaults: two downloads at once and retries for up to two minutes.
+  #[default]
+  Balanced,
+  /// One download at a time with patient timeouts and slowly spaced retries for up to 15
+  /// minutes, for flaky networks and servers that throttle or ban eager clients.
+  Conservative,
+}
+
+impl Profile {
+  pub(crate) fn max_concurrent(self) -> usize {
+    match self {
+      Profile::Aggressive => 8,
+      Profile::Balanced => 2,
+      Profile::Conservative => 1,
+    }
+  }
+
+  pub(crate) fn connect_timeout(self) -> Duration {
+    match self {
+      Profile::Aggressive => Duration::from_secs(1),
+      Profile::Balanced => Duration::from_secs(2),
+      Profile::Conservative => Duration::from_secs(10),
+    }
+  }
+
+  pub(crate) fn timeout(self) -> Duration {
+    match self {
+      Profile::Aggressive | Profile::Balanced => Duration::from_secs(60),
+      Profile::Conservative => Duration::from_secs(600),
+    }
+  }
+
+  pub(crate) fn read_chunk_timeout(self) -> Duration {
+    match self {
+      Profile::Aggressive | Profile::Balanced => Duration::from_millis(500),
+      Profile::Conservative => Duration::from_secs(10),
+    }
+  }
+
+  pub(crate) fn retry_policy(self) -> RetryPolicy {
+    match self {
+      Profile::Aggressive => RetryPolicy::builder()
+        .initial_interval(Duration::from_millis(250))
+        .max_interval(Duration::from_secs(2))
+        .max_elapsed(Some(Duration::from_secs(30)))
+        .build(),
+      Profile::Balanced => RetryPolicy::default(),
+      Profile::Conservative => RetryPolicy::builder()
+        .initial_interval(Duration::from_secs(2))
+        .multiplier(2.0)
+        .max_interval(Duration::from_secs(60))
+        .max_elapsed(Some(Duration::from_secs(15 * 60)))
+        .build(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::RobustDownloader;
+
+  #[test]
+  fn test_balanced_matches_builder_defaults() {
+    let preset = RobustDownloader::preset(Profile::Balanced);
+    let default = RobustDownloader::builder().build();
+
+    assert_eq!(preset.max_concurrent, default.max_concurrent);
+    assert_eq!(preset.connect_timeout, default.connect_timeout);
+    assert_eq!(preset.timeout, default.timeout);
+    assert_eq!(preset.read_chunk_timeout, default.read_chunk_timeout);
+    assert_eq!(preset.retry_policy, default.retry_policy);
+  }
+}
@@ -0,0 +1,174 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+use typed_builder::TypedBuilder;
+
+use crate::report::AttemptMetrics;
+
+/// Slow start for a batch's concurrency, for servers that fall over when many connections
+/// open at once, like a home NAS.
+///
+/// The batch starts with `initial` downloads at a time. Whenever `window` more attempts
+/// have finished, `step` slots are added if at most `max_error_rate` of those attempts
+/// failed, up to the downloader's `max_concurrent`; if more failed, the limit is halved,
+/// but not below `initial`. Slots taken away are given up as running downloads finish.
+///
+/// The ramp applies to the batch's own slots; batches run in a
+/// [`DownloadQueue`](crate::DownloadQueue) share the queue's slots instead.
+///
+/// ```rust
+/// use robust_downloader::{AutoRampPolicy, RobustDownloader};
+///
+/// let downloader = RobustDownloader::builder()
+///   .max_concurrent(16)
+///   .auto_ramp(AutoRampPolicy::builder().initial(2).build())
+///   .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, TypedBuilder)]
+pub struct AutoRampPolicy {
+  /// Downloads at a time when the batch starts. Defaults to 1.
+  #[builder(default = 1)]
+  pub initial: usize,
+  /// Slots added at once. Defaults to 1.
+  #[builder(default = 1)]
+  pub step: usize,
+  /// How many finished attempts the limit is adjusted after. Defaults to 4.
+  #[builder(default = 4)]
+  pub window: u32,
+  /// The share of failed attempts in a window above which the limit is lowered instead of
+  /// raised. Defaults to 0.1.
+  #[builder(default = 0.1)]
+  pub max_error_rate: f64,
+}
+
+/// The slots of one batch under an [`AutoRampPolicy`].
+#[derive(Debug)]
+pub(crate) struct Ramp {
+  policy: AutoRampPolicy,
+  max: usize,
+  semaphore: Arc<Semaphore>,
+  state: Mutex<RampState>,
+}
+
+#[derive(Debug, Default)]
+struct RampState {
+  limit: usize,
+  attempts: u32,
+  failures: u32,
+  /// Slots taken away while they were in use, given up as they are released.
+  owed: usize,
+}
+
+impl Ramp {
+  pub fn new(policy: AutoRampPolicy, max: usize) -> Self {
+    let max = max.max(1);
+    let limit = policy.initial.clamp(1, max);
+    Self {
+      policy,
+      max,
+      semaphore: Arc::new(Semaphore::new(limit)),
+      state: Mutex::new(RampState {
+        limit,
+        ..RampState::default()
+      }),
+    }
+  }
+
+  pub fn semaphore(&self) -> Arc<Semaphore> {
+    self.semaphore.clone()
+  }
+
+  /// Takes the `attempts` of a finished item into account, then releases its `permit`.
+  pub fn finish(&self, permit: Option<SemaphorePermit<'_>>, attempts: &[AttemptMetrics]) {
+    let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.attempts += attempts.len() as u32;
+    state.failures += attempts
+      .iter()
+      .filter(|attempt| attempt.error.is_some())
+      .count() as u32;
+
+    if state.attempts >= self.policy.window.max(1) {
+      let error_rate = f64::from(state.failures) / f64::from(state.attempts);
+      if error_rate <= self.policy.max_error_rate {
+        let added = self.policy.step.min(self.max - state.limit);
+        state.limit += added;
+        // Slots still owed are kept instead of being given up.
+        let kept = added.min(state.owed);
+        state.owed -= kept;
+        self.semaphore.add_permits(added - kept);
+      } else {
+        let floor = self.policy.initial.clamp(1, self.max);
+        let removed = state.limit - (state.limit / 2).max(floor).min(state.limit);
+        state.limit -= removed;
+        state.owed += removed;
+      }
+      state.attempts = 0;
+      state.failures = 0;
+    }
+
+    let forgotten = self.semaphore.forget_permits(state.owed);
+    state.owed -= forgotten;
+    if let Some(permit) = permit.filter(|_| state.owed > 0) {
+      permit.forget();
+      state.owed -= 1;
+    }
+  }
+
+  #[cfg(test)]
+  fn limit(&self) -> usize {
+    self.state.lock().unwrap().limit
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn attempts(failed: usize, succeeded: usize) -> Vec<AttemptMetrics> {
+    let failed = (0..failed).map(|_| AttemptMetrics {
+      error: Some("connection reset".to_string()),
+      ..AttemptMetrics::default()
+    });
+    failed
+      .chain((0..succeeded).map(|_| AttemptMetrics::default()))
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn test_ramp() {
+    let policy = AutoRampPolicy::builder()
+      .initial(2)
+      .step(2)
+      .window(2)
+      .build();
+    let ramp = Ramp::new(policy, 5);
+    let semaphore = ramp.semaphore();
+    assert_eq!(semaphore.available_permits(), 2);
+
+    // Growing up to the maximum while attempts succeed.
+    for limit in [4, 5, 5] {
+      let permit = semaphore.acquire().await.unwrap();
+      ramp.finish(Some(permit), &attempts(0, 2));
+      assert_eq!(
+        (ramp.limit(), semaphore.available_permits()),
+        (limit, limit)
+      );
+    }
+
+    // Halving when they fail, with the slots in use given up as they are released.
+    let running = [
+      semaphore.acquire().await.unwrap(),
+      semaphore.acquire().await.unwrap(),
+      semaphore.acquire().await.unwrap(),
+      semaphore.acquire().await.unwrap(),
+    ];
+    let [first, rest @ ..] = running;
+    ramp.finish(Some(first), &attempts(1, 1));
+    assert_eq!(ramp.limit(), 2);
+    assert_eq!(semaphore.available_permits(), 0);
+    for permit in rest {
+      ramp.finish(Some(permit), &[]);
+    }
+    assert_eq!(semaphore.available_permits(), 2);
+  }
+}
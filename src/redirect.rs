@@ -0,0 +1,240 @@
+use reqwest::{
+  Response, StatusCode, Url,
+  header::{AUTHORIZATION, COOKIE, HeaderMap, HeaderName, PROXY_AUTHORIZATION},
+};
+use typed_builder::TypedBuilder;
+
+/// Which of an item's headers are still sent once a redirect leaves the original origin
+/// (scheme, host and port).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CrossOriginHeaders {
+  /// Drop `Authorization`, `Cookie` and `Proxy-Authorization`.
+  #[default]
+  StripSensitive,
+  /// Drop every item header.
+  StripAll,
+  /// Drop exactly these headers.
+  Strip(Vec<HeaderName>),
+  /// Forward every header. Only use this when all hops are trusted.
+  KeepAll,
+}
+
+/// How redirects are followed.
+///
+/// Redirects are followed hop by hop by the downloader itself, so that credentials meant
+/// for a registry are not forwarded to the CDN it redirects to (S3, for one, rejects
+/// requests carrying both an `Authorization` header and a signed URL). Once a hop leaves
+/// the original origin, headers stay stripped for every following hop.
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
+pub struct RedirectPolicy {
+  /// Maximum number of hops before giving up. Defaults to 10.
+  #[builder(default = 10)]
+  pub max_redirects: usize,
+
+  /// Headers to strip on cross-origin hops. Defaults to [`CrossOriginHeaders::StripSensitive`].
+  #[builder(default)]
+  pub cross_origin: CrossOriginHeaders,
+
+  /// Maximum number of HTML `<meta http-equiv="refresh">` pages to follow.
+  ///
+  /// Some mirrors answer with a tiny HTML page pointing at the real file. When non-zero,
+  /// small `text/html` responses are inspected and their refresh target is followed like
+  /// a redirect (and counts towards [`max_redirects`](Self::max_redirects)).
+  /// Defaults to 0, i.e. disabled.
+  #[builder(default = 0)]
+  pub meta_refresh_hops: usize,
+}
+
+impl Default for RedirectPolicy {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl RedirectPolicy {
+  /// Returns the headers to send on a hop that left the original origin.
+  pub(crate) fn cross_origin_headers(&self, headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    match &self.cross_origin {
+      CrossOriginHeaders::StripSensitive => {
+        for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+          headers.remove(name);
+        }
+      }
+      CrossOriginHeaders::StripAll => headers.clear(),
+      CrossOriginHeaders::Strip(names) => {
+        for name in names {
+          headers.remove(name);
+        }
+      }
+      CrossOriginHeaders::KeepAll => {}
+    }
+    headers
+  }
+}
+
+/// Returns the next hop if `status` is a redirect with a usable `Location`.
+pub(crate) fn next_hop(current: &Url, status: StatusCode, headers: &HeaderMap) -> Option<Url> {
+  if !status.is_redirection() || status == StatusCode::NOT_MODIFIED {
+    return None;
+  }
+  let location = headers.get(reqwest::header::LOCATION)?.to_str().ok()?;
+  current.join(location).ok()
+}
+
+/// Responses larger than this are never inspected for a meta refresh.
+pub(crate) const META_REFRESH_MAX_BODY: u64 = 64 * 1024;
+
+/// Returns whether a response could be a meta-refresh interstitial worth buffering.
+pub(crate) fn may_be_meta_refresh(headers: &HeaderMap) -> bool {
+  let is_html = headers
+    .get(reqwest::header::CONTENT_TYPE)
+    .and_then(|v| v.to_str().ok())
+    .is_some_and(|v| {
+      v.trim_start()
+        .get(..9)
+        .is_some_and(|t| t.eq_ignore_ascii_case("text/html"))
+    });
+  let small = headers
+    .get(reqwest::header::CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.parse::<u64>().ok())
+    .is_none_or(|len| len <= META_REFRESH_MAX_BODY);
+  is_html && small
+}
+
+/// Result of buffering a possible meta-refresh page.
+pub(crate) enum Inspected {
+  /// The page points elsewhere.
+  Refresh(Url),
+  /// A regular response, rebuilt from the buffered body.
+  Body(Response),
+}
+
+/// Buffers a small HTML response and looks for a meta refresh in it.
+pub(crate) async fn inspect_meta_refresh(
+  current: &Url,
+  response: Response,
+) -> reqwest::Result<Inspected> {
+  let status = response.status();
+  let version = response.version();
+  let headers = response.headers().clone();
+  let body = response.bytes().await?;
+
+  let target = std::str::from_utf8(&body)
+    .ok()
+    .and_then(meta_refresh_target)
+    .and_then(|target| current.join(target).ok());
+  if let Some(target) = target {
+    return Ok(Inspected::Refresh(target));
+  }
+
+  let mut rebuilt = http::Response::new(body);
+  *rebuilt.status_mut() = status;
+  *rebuilt.version_mut() = version;
+  *rebuilt.headers_mut() = headers;
+  Ok(Inspected::Body(Response::from(rebuilt)))
+}
+
+/// Extracts the target of the first `<meta http-equiv="refresh" content="N; url=...">`.
+pub(crate) fn meta_refresh_target(html: &str) -> Option<&str> {
+  let lower = html.as_bytes().to_ascii_lowercase();
+  let mut offset = 0;
+
+  while let Some(start) = find(&lower[offset..], b"<meta").map(|i| i + offset) {
+    let end = find(&lower[start..], b">").map_or(lower.len(), |i| i + start);
+    let tag = &lower[start..end];
+    offset = end;
+
+    if find(tag, b"http-equiv").is_none() || find(tag, b"refresh").is_none() {
+      continue;
+    }
+
+    let Some(content) = find(tag, b"content=").map(|i| start + i + b"content=".len()) else {
+      continue;
+    };
+    if content >= end {
+      continue;
+    }
+    let quote = html.as_bytes()[content];
+    let (value_start, value_end) = if matches!(quote, b'"' | b'\'') {
+      let close = find(&lower[content + 1..end], &[quote]).map_or(end, |i| i + content + 1);
+      (content + 1, close)
+    } else {
+      let close = lower[content..end]
+        .iter()
+        .position(|b| b.is_ascii_whitespace())
+        .map_or(end, |i| i + content);
+      (content, close)
+    };
+
+    let Some(url_at) = find(&lower[value_start..value_end], b"url=") else {
+      continue;
+    };
+    let url_at = url_at + value_start + b"url=".len();
+    let target = html[url_at..value_end]
+      .trim()
+      .trim_matches(|c| c == '\'' || c == '"');
+    if !target.is_empty() {
+      return Some(target);
+    }
+  }
+
+  None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack
+    .windows(needle.len())
+    .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header::{ACCEPT, HeaderValue};
+
+  use super::*;
+
+  #[test]
+  fn test_strip_sensitive_keeps_other_headers() {
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+    headers.insert(ACCEPT, HeaderValue::from_static("application/octet-stream"));
+
+    let stripped = RedirectPolicy::default().cross_origin_headers(&headers);
+    assert!(!stripped.contains_key(AUTHORIZATION));
+    assert!(stripped.contains_key(ACCEPT));
+  }
+
+  #[test]
+  fn test_next_hop_resolves_relative_location() {
+    let current = Url::parse("https://registry.example.com/v2/blob").unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+      reqwest::header::LOCATION,
+      HeaderValue::from_static("/cdn/blob"),
+    );
+    assert_eq!(
+      next_hop(&current, StatusCode::FOUND, &headers)
+        .unwrap()
+        .as_str(),
+      "https://registry.example.com/cdn/blob"
+    );
+    assert!(next_hop(&current, StatusCode::OK, &headers).is_none());
+  }
+
+  #[test]
+  fn test_meta_refresh_target() {
+    let html = r#"<html><head><META HTTP-EQUIV="Refresh" CONTENT="0; URL=https://cdn.example.com/file.tar.gz"></head></html>"#;
+    assert_eq!(
+      meta_refresh_target(html),
+      Some("https://cdn.example.com/file.tar.gz")
+    );
+
+    let html = "<meta http-equiv='refresh' content='5;url=/real/file.zip'>";
+    assert_eq!(meta_refresh_target(html), Some("/real/file.zip"));
+
+    let html = r#"<meta charset="utf-8"><p>not an interstitial</p>"#;
+    assert_eq!(meta_refresh_target(html), None);
+  }
+}
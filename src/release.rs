@@ -0,0 +1,237 @@
+//! Resolving private GitHub and GitLab release assets into [`DownloadItem`]s.
+//!
+//! Release assets of private repositories cannot be fetched from their browser URL with a
+//! token. GitHub wants the asset's API URL together with `Accept: application/octet-stream`
+//! (it then redirects to a signed S3 URL, which rejects the `Authorization` header; the
+//! default [`RedirectPolicy`](crate::RedirectPolicy) already strips it on that hop), and
+//! GitLab wants a `PRIVATE-TOKEN` header on the asset link.
+//!
+//! ```rust,no_run
+//! use robust_downloader::{RobustDownloader, release::{ReleaseAsset, ReleaseHost}};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let asset: ReleaseAsset = "acme/tools@v1.2.0:tools-linux-x64.tar.gz".parse()?;
+//! let item = asset
+//!   .resolve(&ReleaseHost::github(), "ghp_secret", "tools.tar.gz")
+//!   .await?;
+//!
+//! RobustDownloader::builder().build().download(vec![item]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::str::FromStr;
+
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use serde::Deserialize;
+
+use crate::{err::ProgressDownloadError, item::DownloadItem};
+
+const PRIVATE_TOKEN: HeaderName = HeaderName::from_static("private-token");
+
+/// Where a release lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReleaseHost {
+  /// A GitHub (or GitHub Enterprise) REST API root, e.g. `https://api.github.com`.
+  GitHub { api: String },
+  /// A GitLab REST API root, e.g. `https://gitlab.com/api/v4`.
+  GitLab { api: String },
+}
+
+impl ReleaseHost {
+  /// `https://api.github.com`.
+  pub fn github() -> Self {
+    Self::GitHub {
+      api: "https://api.github.com".to_string(),
+    }
+  }
+
+  /// `https://gitlab.com/api/v4`.
+  pub fn gitlab() -> Self {
+    Self::GitLab {
+      api: "https://gitlab.com/api/v4".to_string(),
+    }
+  }
+}
+
+/// A release asset, written `owner/repo@tag:asset_name`.
+///
+/// For GitLab, `owner/repo` is the full project path and may contain subgroups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseAsset {
+  pub repo: String,
+  pub tag: String,
+  pub name: String,
+}
+
+impl FromStr for ReleaseAsset {
+  type Err = ProgressDownloadError;
+
+  fn from_str(spec: &str) -> Result<Self, Self::Err> {
+    let invalid = || ProgressDownloadError::InvalidReleaseSpec {
+      spec: spec.to_string(),
+    };
+    let (repo, rest) = spec.split_once('@').ok_or_else(invalid)?;
+    let (tag, name) = rest.split_once(':').ok_or_else(invalid)?;
+    if !repo.contains('/') || [repo, tag, name].iter().any(|part| part.is_empty()) {
+      return Err(invalid());
+    }
+
+    Ok(Self {
+      repo: repo.to_string(),
+      tag: tag.to_string(),
+      name: name.to_string(),
+    })
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubRelease {
+  assets: Vec<GitHubAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubAsset {
+  name: String,
+  url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabLink {
+  name: String,
+  url: String,
+  direct_asset_url: Option<String>,
+}
+
+impl ReleaseAsset {
+  /// Looks the asset up on `host` and returns an item that downloads it with `token`.
+  pub async fn resolve<P>(
+    &self,
+    host: &ReleaseHost,
+    token: &str,
+    target: P,
+  ) -> Result<DownloadItem<String, P>, ProgressDownloadError> {
+    let client = reqwest::Client::new();
+    let (url, headers) = match host {
+      ReleaseHost::GitHub { api } => self.resolve_github(&client, api, token).await?,
+      ReleaseHost::GitLab { api } => self.resolve_gitlab(&client, api, token).await?,
+    };
+
+    Ok(
+      DownloadItem::builder()
+        .url(url)
+        .target(target)
+        .headers(headers)
+        .build(),
+    )
+  }
+
+  async fn resolve_github(
+    &self,
+    client: &reqwest::Client,
+    api: &str,
+    token: &str,
+  ) -> Result<(String, HeaderMap), ProgressDownloadError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+      AUTHORIZATION,
+      self.header_value(&format!("Bearer {token}"))?,
+    );
+    // GitHub rejects API requests without a user agent.
+    headers.insert(USER_AGENT, HeaderValue::from_static("robust_downloader"));
+
+    let url = format!(
+      "{}/repos/{}/releases/tags/{}",
+      api.trim_end_matches('/'),
+      self.repo,
+      utf8_percent_encode(&self.tag, NON_ALPHANUMERIC)
+    );
+    let body = client
+      .get(url)
+      .headers(headers.clone())
+      .header(ACCEPT, "application/vnd.github+json")
+      .send()
+      .await?
+      .error_for_status()?
+      .bytes()
+      .await?;
+    let release: GitHubRelease = serde_json::from_slice(&body).map_err(|_| self.not_found())?;
+
+    let asset = release
+      .assets
+      .into_iter()
+      .find(|asset| asset.name == self.name)
+      .ok_or_else(|| self.not_found())?;
+
+    headers.insert(ACCEPT, HeaderValue::from_static("application/octet-stream"));
+    Ok((asset.url, headers))
+  }
+
+  async fn resolve_gitlab(
+    &self,
+    client: &reqwest::Client,
+    api: &str,
+    token: &str,
+  ) -> Result<(String, HeaderMap), ProgressDownloadError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(PRIVATE_TOKEN, self.header_value(token)?);
+
+    let url = format!(
+      "{}/projects/{}/releases/{}/assets/links",
+      api.trim_end_matches('/'),
+      utf8_percent_encode(&self.repo, NON_ALPHANUMERIC),
+      utf8_percent_encode(&self.tag, NON_ALPHANUMERIC)
+    );
+    let body = client
+      .get(url)
+      .headers(headers.clone())
+      .send()
+      .await?
+      .error_for_status()?
+      .bytes()
+      .await?;
+    let links: Vec<GitLabLink> = serde_json::from_slice(&body).map_err(|_| self.not_found())?;
+
+    let link = links
+      .into_iter()
+      .find(|link| link.name == self.name)
+      .ok_or_else(|| self.not_found())?;
+
+    Ok((link.direct_asset_url.unwrap_or(link.url), headers))
+  }
+
+  fn header_value(&self, value: &str) -> Result<HeaderValue, ProgressDownloadError> {
+    let mut value =
+      HeaderValue::from_str(value).map_err(|_| ProgressDownloadError::InvalidReleaseSpec {
+        spec: "token contains characters not allowed in a header".to_string(),
+      })?;
+    value.set_sensitive(true);
+    Ok(value)
+  }
+
+  fn not_found(&self) -> ProgressDownloadError {
+    ProgressDownloadError::ReleaseAssetNotFound {
+      repo: self.repo.clone(),
+      tag: self.tag.clone(),
+      name: self.name.clone(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_release_asset() {
+    let asset: ReleaseAsset = "group/sub/project@v1.0:app.tar.gz".parse().unwrap();
+    assert_eq!(asset.repo, "group/sub/project");
+    assert_eq!(asset.tag, "v1.0");
+    assert_eq!(asset.name, "app.tar.gz");
+
+    assert!("owner/repo:app.tar.gz".parse::<ReleaseAsset>().is_err());
+    assert!("repo@v1.0:app.tar.gz".parse::<ReleaseAsset>().is_err());
+    assert!("owner/repo@v1.0:".parse::<ReleaseAsset>().is_err());
+  }
+}
@@ -0,0 +1,44 @@
+use std::time::SystemTime;
+
+use reqwest::{
+  Url,
+  header::{ACCEPT_RANGES, CONTENT_LENGTH, ETAG, HeaderMap, LAST_MODIFIED},
+};
+
+/// What a server tells about a file without sending it, see
+/// [`RobustDownloader::head`](crate::RobustDownloader::head).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteMetadata {
+  /// `Content-Length`, if the server sent one.
+  pub size: Option<u64>,
+  /// `ETag`, quotes and weak validator prefix included.
+  pub etag: Option<String>,
+  /// `Last-Modified`.
+  pub last_modified: Option<SystemTime>,
+  /// Whether the server advertises `Accept-Ranges: bytes`. Servers that do not advertise
+  /// it may still honor ranges.
+  pub accepts_ranges: bool,
+  /// The URL that answered, after redirects.
+  pub final_url: Url,
+}
+
+impl RemoteMetadata {
+  pub(crate) fn from_response(response: &reqwest::Response) -> Self {
+    let headers = response.headers();
+    Self {
+      // Not `content_length()`: a `HEAD` response has no body to measure.
+      size: header(headers, CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+      etag: header(headers, ETAG).map(ToString::to_string),
+      last_modified: header(headers, LAST_MODIFIED).and_then(|v| httpdate::parse_http_date(v).ok()),
+      accepts_ranges: header(headers, ACCEPT_RANGES).is_some_and(|v| {
+        v.split(',')
+          .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"))
+      }),
+      final_url: response.url().clone(),
+    }
+  }
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<&str> {
+  headers.get(name).and_then(|v| v.to_str().ok())
+}
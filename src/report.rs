@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use crate::{err::ProgressDownloadError, observer::TaskState};
+
+/// Timings captured for a single attempt at downloading an item.
+///
+/// `reqwest` does not expose DNS, connect or TLS handshake timings, so those phases are
+/// folded into [`time_to_headers`](Self::time_to_headers). Comparing it with
+/// [`time_to_first_byte`](Self::time_to_first_byte) and the overall throughput is usually
+/// enough to tell a slow mirror from a slow disk.
+#[derive(Debug, Clone, Default)]
+pub struct AttemptMetrics {
+  /// 1-based number of this attempt at its [`url`](Self::url).
+  pub attempt: u32,
+  /// The URL this attempt fetched; differs from the item's primary URL once it fell back
+  /// to a mirror.
+  pub url: String,
+  /// Time from sending the request until the response headers arrived.
+  pub time_to_headers: Option<Duration>,
+  /// Time from sending the request until the first body chunk arrived (TTFB).
+  pub time_to_first_byte: Option<Duration>,
+  /// Where in the remote file the attempt started: the size of the partial file it
+  /// resumed, or 0.
+  pub resumed_from: u64,
+  /// Bytes received from the network during this attempt.
+  pub bytes: u64,
+  /// Highest number of chunks waiting between the network reader and the disk writer.
+  /// Staying at the configured capacity means the disk was the bottleneck.
+  pub peak_buffered_chunks: usize,
+  /// Size of a partial file that was discarded instead of resumed: the remote file changed
+  /// between attempts or runs, or the server answered the range request with the whole
+  /// file or with a range not starting where the partial ends.
+  pub discarded_partial: Option<u64>,
+  /// What the downloaded bytes were checked against; `None` when they were not verified.
+  pub verified_by: Option<VerificationSource>,
+  /// The furthest state the attempt reached: [`TaskState::Done`] for the attempt that
+  /// placed the file, the state it failed in for the others.
+  pub state: TaskState,
+  /// Total duration of the attempt.
+  pub elapsed: Duration,
+  /// The error that ended the attempt, if it failed.
+  pub error: Option<String>,
+}
+
+/// Where the checksum a download was verified against came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationSource {
+  /// The item's own `integrity`.
+  Item,
+  /// The response's `Content-MD5` header.
+  ContentMd5,
+  /// The response's `x-amz-checksum-sha256` header.
+  AmzChecksum,
+  /// The response's `Digest` header.
+  Digest,
+  /// The SHA-256 pinned by an earlier download of the URL, see
+  /// [`PinStore`](crate::PinStore).
+  Pin,
+}
+
+/// What happened to an item that completed without error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadOutcome {
+  /// The file was fetched and placed at the target.
+  Downloaded,
+  /// The target was already up to date with the remote file, or linked to an identical
+  /// stored copy; nothing was fetched.
+  NotModified,
+  /// The target already matched the item's integrity; nothing was fetched.
+  AlreadyValid,
+  /// The target already existed and the item's overwrite policy is
+  /// [`Skip`](crate::OverwritePolicy::Skip); nothing was fetched.
+  Skipped,
+  /// The item was cancelled by tag before it completed.
+  Cancelled,
+  /// The item did not fit into the available disk space and was left out of a batch run
+  /// with `best_effort_space`; nothing was fetched.
+  NoSpace,
+}
+
+/// Outcome of a successfully processed item.
+#[derive(Debug, Clone)]
+pub struct DownloadReport {
+  /// The item's position in the batch it was passed in.
+  pub index: usize,
+  /// The URL the item was downloaded from: its primary URL or the mirror that succeeded.
+  pub url: String,
+  /// Where the file was placed; for content-addressed items and items targeting a
+  /// directory this is the resolved path.
+  pub target: PathBuf,
+  /// Whether the file was actually downloaded.
+  pub outcome: DownloadOutcome,
+  /// The item's tags.
+  pub tags: Vec<String>,
+  /// Every attempt made for this item, in order, including failed ones.
+  pub attempts: Vec<AttemptMetrics>,
+  /// Total time spent on the item, including retries and backoff sleeps.
+  pub elapsed: Duration,
+}
+
+impl DownloadReport {
+  /// Bytes received from the network over all attempts.
+  pub fn bytes(&self) -> u64 {
+    self.attempts.iter().map(|attempt| attempt.bytes).sum()
+  }
+
+  /// What the placed file was verified against, taken from the last attempt.
+  pub fn verified_by(&self) -> Option<VerificationSource> {
+    self.attempts.last().and_then(|attempt| attempt.verified_by)
+  }
+}
+
+/// Result of one item of a [`download_all`](crate::RobustDownloader::download_all) call,
+/// whether it succeeded or not.
+#[derive(Debug)]
+pub struct DownloadResult {
+  /// The item's position in the batch it was passed in.
+  pub index: usize,
+  /// The item's primary URL.
+  pub url: String,
+  /// The item's target as given.
+  pub target: PathBuf,
+  /// The item's report, or the error it failed with after its retries.
+  pub result: Result<DownloadReport, ProgressDownloadError>,
+  /// Bytes received from the network over all attempts, failed ones included.
+  pub bytes: u64,
+  /// Total time spent on the item, including retries and backoff sleeps.
+  pub elapsed: Duration,
+}
+
+impl DownloadResult {
+  pub fn is_ok(&self) -> bool {
+    self.result.is_ok()
+  }
+}
+
+/// An item that failed for good, with the attempts it made on the way.
+#[derive(Debug)]
+pub(crate) struct ItemFailure {
+  pub error: ProgressDownloadError,
+  pub attempts: Vec<AttemptMetrics>,
+}
+
+impl From<ProgressDownloadError> for ItemFailure {
+  fn from(error: ProgressDownloadError) -> Self {
+    Self {
+      error,
+      attempts: Vec::new(),
+    }
+  }
+}
+
+/// Aggregated results of all items sharing a tag.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagSummary {
+  pub items: usize,
+  pub downloaded: usize,
+  pub not_modified: usize,
+  pub already_valid: usize,
+  pub skipped: usize,
+  pub cancelled: usize,
+  pub no_space: usize,
+  /// Bytes received from the network over all attempts.
+  pub bytes: u64,
+}
+
+/// Groups reports by tag. An item with several tags counts towards each of them;
+/// untagged items are left out.
+pub fn summarize_by_tag(reports: &[DownloadReport]) -> BTreeMap<String, TagSummary> {
+  let mut summaries = BTreeMap::<String, TagSummary>::new();
+  for report in reports {
+    for tag in &report.tags {
+      let summary = summaries.entry(tag.clone()).or_default();
+      summary.items += 1;
+      summary.bytes += report.bytes();
+      match report.outcome {
+        DownloadOutcome::Downloaded => summary.downloaded += 1,
+        DownloadOutcome::NotModified => summary.not_modified += 1,
+        DownloadOutcome::AlreadyValid => summary.already_valid += 1,
+        DownloadOutcome::Skipped => summary.skipped += 1,
+        DownloadOutcome::Cancelled => summary.cancelled += 1,
+        DownloadOutcome::NoSpace => summary.no_space += 1,
+      }
+    }
+  }
+  summaries
+}
@@ -0,0 +1,222 @@
+use std::{sync::Arc, time::Duration};
+
+use backoff::{backoff::Backoff, exponential::ExponentialBackoff};
+use rand::Rng;
+use typed_builder::TypedBuilder;
+
+use crate::clock::{BackoffClock, Clock};
+
+/// How failed attempts are spaced out, and when they stop.
+///
+/// Waits start at `initial_interval` and grow by `multiplier` up to `max_interval`, each
+/// randomized by [`Jitter`] so that many clients failing together do not retry together.
+/// Retrying stops at whichever limit is hit first, `max_retries` or `max_elapsed`; both
+/// count per URL, so an item with mirrors gets the full schedule on each of them.
+/// Permanent errors (e.g. 404 or an integrity mismatch) are never retried.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use robust_downloader::{RetryPolicy, RobustDownloader};
+///
+/// // A mirror with a strict rate limit: few, widely spaced retries.
+/// let policy = RetryPolicy::builder()
+///   .initial_interval(Duration::from_secs(10))
+///   .multiplier(2.0)
+///   .max_interval(Duration::from_secs(300))
+///   .max_retries(5)
+///   .max_elapsed(None)
+///   .build();
+/// let downloader = RobustDownloader::builder().retry_policy(policy).build();
+/// ```
+#[derive(Debug, Clone, PartialEq, TypedBuilder)]
+pub struct RetryPolicy {
+  /// Wait before the first retry. Defaults to 500ms.
+  #[builder(default = Duration::from_millis(500))]
+  pub initial_interval: Duration,
+  /// Growth of the wait from one retry to the next. Defaults to 1.5.
+  #[builder(default = 1.5)]
+  pub multiplier: f64,
+  /// Longest wait between two attempts. Defaults to 5 seconds.
+  #[builder(default = Duration::from_secs(5))]
+  pub max_interval: Duration,
+  /// How many times a URL is retried after its first attempt. Defaults to no limit.
+  #[builder(default, setter(strip_option))]
+  pub max_retries: Option<u32>,
+  /// How long a URL is retried, counted from its first attempt; `None` retries for as
+  /// long as `max_retries` allows. Defaults to 120 seconds.
+  #[builder(default = Some(Duration::from_secs(120)))]
+  pub max_elapsed: Option<Duration>,
+  /// How every wait is randomized. Defaults to `Jitter::Proportional(0.15)`.
+  #[builder(default)]
+  pub jitter: Jitter,
+}
+
+/// How a retry wait is randomized around the nominal, exponentially growing one.
+///
+/// The more clients retry against the same server, the more spread out their retries need
+/// to be: after an outage, proportional jitter still lets them come back in waves, while
+/// full and decorrelated jitter scatter them over the whole wait.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jitter {
+  /// Up to this fraction of the nominal wait either way: 0.15 waits anywhere between 85%
+  /// and 115% of it.
+  Proportional(f64),
+  /// Anywhere between zero and the nominal wait.
+  Full,
+  /// Half the nominal wait, plus anywhere up to the other half.
+  Equal,
+  /// Anywhere between `initial_interval` and three times the previous wait, capped at
+  /// `max_interval`. Grows about as fast as the nominal wait, but each client on its own
+  /// random path.
+  Decorrelated,
+}
+
+impl Default for Jitter {
+  fn default() -> Self {
+    Jitter::Proportional(0.15)
+  }
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl RetryPolicy {
+  /// A schedule counting its elapsed time on `clock`.
+  pub(crate) fn backoff(&self, clock: &Arc<dyn Clock>) -> RetryBackoff {
+    RetryBackoff {
+      // Randomized here rather than by `backoff`, which only knows proportional jitter.
+      nominal: ExponentialBackoff {
+        initial_interval: self.initial_interval,
+        current_interval: self.initial_interval,
+        randomization_factor: 0.0,
+        multiplier: self.multiplier.max(1.0),
+        max_interval: self.max_interval,
+        start_time: clock.now(),
+        max_elapsed_time: self.max_elapsed,
+        clock: BackoffClock(clock.clone()),
+      },
+      jitter: self.jitter,
+      initial_interval: self.initial_interval,
+      max_interval: self.max_interval,
+      previous: None,
+    }
+  }
+
+  /// How many attempts a URL gets at most, or `None` when only time limits them.
+  pub(crate) fn max_attempts(&self) -> Option<u32> {
+    self.max_retries.map(|max| max.saturating_add(1))
+  }
+
+  /// Whether a URL that failed `failures` times in a row is not retried anymore.
+  pub(crate) fn exhausted(&self, failures: u32) -> bool {
+    self.max_retries.is_some_and(|max| failures > max)
+  }
+}
+
+/// The waits of one retry schedule. The nominal backoff also keeps track of the elapsed
+/// time, and ends the schedule once `max_elapsed` has passed.
+#[derive(Debug)]
+pub(crate) struct RetryBackoff {
+  nominal: ExponentialBackoff<BackoffClock>,
+  jitter: Jitter,
+  initial_interval: Duration,
+  max_interval: Duration,
+  previous: Option<Duration>,
+}
+
+impl Backoff for RetryBackoff {
+  fn reset(&mut self) {
+    self.nominal.reset();
+    self.previous = None;
+  }
+
+  fn next_backoff(&mut self) -> Option<Duration> {
+    let nominal = self.nominal.next_backoff()?;
+    let wait = match self.jitter {
+      Jitter::Proportional(fraction) => {
+        let fraction = fraction.clamp(0.0, 1.0);
+        nominal.mul_f64(random(1.0 - fraction, 1.0 + fraction))
+      }
+      Jitter::Full => nominal.mul_f64(random(0.0, 1.0)),
+      Jitter::Equal => nominal.mul_f64(random(0.5, 1.0)),
+      Jitter::Decorrelated => {
+        let previous = self.previous.unwrap_or(self.initial_interval);
+        let wait = random(
+          self.initial_interval.as_secs_f64(),
+          (previous * 3).as_secs_f64(),
+        );
+        Duration::from_secs_f64(wait).min(self.max_interval)
+      }
+    };
+    self.previous = Some(wait);
+    Some(wait)
+  }
+}
+
+/// A random number in `low..high`, or `low` if the range is empty.
+fn random(low: f64, high: f64) -> f64 {
+  if high > low {
+    rand::thread_rng().gen_range(low..high)
+  } else {
+    low
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_max_retries_counts_retries_not_attempts() {
+    let policy = RetryPolicy::builder().max_retries(2).build();
+
+    assert!(!policy.exhausted(1));
+    assert!(!policy.exhausted(2));
+    assert!(policy.exhausted(3));
+    assert!(!RetryPolicy::default().exhausted(u32::MAX));
+  }
+
+  #[test]
+  fn test_jitter_bounds() {
+    let policy = |jitter| {
+      RetryPolicy::builder()
+        .initial_interval(Duration::from_secs(1))
+        .multiplier(2.0)
+        .max_interval(Duration::from_secs(8))
+        .jitter(jitter)
+        .build()
+    };
+
+    let clock: Arc<dyn Clock> = Arc::new(crate::clock::SystemClock);
+    let mut full = policy(Jitter::Full).backoff(&clock);
+    let mut equal = policy(Jitter::Equal).backoff(&clock);
+    let mut decorrelated = policy(Jitter::Decorrelated).backoff(&clock);
+    let mut nominal = Duration::from_secs(1);
+    for _ in 0..10 {
+      assert!(full.next_backoff().unwrap() <= nominal);
+      let wait = equal.next_backoff().unwrap();
+      assert!(wait >= nominal / 2 && wait <= nominal);
+      let wait = decorrelated.next_backoff().unwrap();
+      assert!(wait >= Duration::from_secs(1) && wait <= Duration::from_secs(8));
+      nominal = (nominal * 2).min(Duration::from_secs(8));
+    }
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_max_elapsed_follows_the_clock() {
+    let policy = RetryPolicy::builder()
+      .max_elapsed(Some(Duration::from_secs(120)))
+      .build();
+    let clock: Arc<dyn Clock> = Arc::new(crate::clock::TokioClock);
+    let mut backoff = policy.backoff(&clock);
+
+    assert!(backoff.next_backoff().is_some());
+    tokio::time::advance(Duration::from_secs(119)).await;
+    assert!(backoff.next_backoff().is_some());
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert_eq!(backoff.next_backoff(), None);
+  }
+}
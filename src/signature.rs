@@ -0,0 +1,139 @@
+use std::{io::BufReader, path::PathBuf};
+
+use pgp::{
+  Deserializable, SignedPublicKey, StandaloneSignature,
+  types::{KeyId, PublicKeyTrait},
+};
+use typed_builder::TypedBuilder;
+
+use crate::err::ProgressDownloadError;
+
+/// A detached OpenPGP signature an item is verified against after its hash, like
+/// `gpg --verify file.asc file` but without shelling out to `gpg`. The file is only placed
+/// when the signature was made over it by one of the keyring's keys or subkeys.
+///
+/// Often the signed file is a checksum list: download the release's signed `SHASUMS256.txt`
+/// with a signature first, then check the artifacts against it with an
+/// [`IntegrityFile::Path`](crate::IntegrityFile::Path).
+///
+/// ```rust
+/// use robust_downloader::{DownloadItem, PgpSignature, SignatureSource};
+///
+/// let item = DownloadItem::builder()
+///   .url("https://nodejs.org/dist/v23.9.0/SHASUMS256.txt")
+///   .target("SHASUMS256.txt")
+///   .signature(
+///     PgpSignature::builder()
+///       .signature(SignatureSource::Url(
+///         "https://nodejs.org/dist/v23.9.0/SHASUMS256.txt.sig".to_string(),
+///       ))
+///       .keyring("node-release-keys.asc")
+///       .build(),
+///   )
+///   .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
+pub struct PgpSignature {
+  /// Where the detached signature is, armored (`.asc`) or binary (`.sig`).
+  pub signature: SignatureSource,
+  /// A file holding the trusted public keys, armored or binary, e.g. exported with
+  /// `gpg --export --armor`.
+  #[builder(setter(into))]
+  pub keyring: PathBuf,
+}
+
+/// Where a [`PgpSignature`] is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureSource {
+  /// Fetched with the downloader's client before the item is downloaded.
+  Url(String),
+  /// A file on the local disk.
+  Path(PathBuf),
+  /// The signature itself.
+  Bytes(Vec<u8>),
+}
+
+impl PgpSignature {
+  /// Checks the signature over the file at `file`, the download of `url`.
+  pub(crate) async fn verify(&self, url: &str, file: PathBuf) -> Result<(), ProgressDownloadError> {
+    let failed = |message: String| ProgressDownloadError::PgpSignature {
+      url: url.to_string(),
+      message,
+    };
+    let signature = match &self.signature {
+      SignatureSource::Bytes(bytes) => bytes.clone(),
+      SignatureSource::Path(path) => tokio::fs::read(path).await?,
+      // Fetched before the download starts, see `RobustDownloader::fetched_signature`.
+      SignatureSource::Url(url) => return Err(failed(format!("{url} was not fetched"))),
+    };
+    let keyring = tokio::fs::read(&self.keyring).await?;
+
+    let (signature, _) = StandaloneSignature::from_reader_single(&signature[..])
+      .map_err(|e| failed(format!("unreadable signature: {e}")))?;
+    let (keys, _) = SignedPublicKey::from_reader_many(&keyring[..])
+      .map_err(|e| failed(format!("unreadable keyring: {e}")))?;
+    let keys = keys
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| failed(format!("unreadable keyring: {e}")))?;
+
+    // Verifying reads the whole file, so it is kept off the runtime's threads.
+    let valid = tokio::task::spawn_blocking(move || {
+      let signature = &signature.signature;
+      let issuers = signature.issuer();
+      let may_have_signed = |key_id: KeyId| issuers.is_empty() || issuers.contains(&&key_id);
+      let content = || std::fs::File::open(&file).map(BufReader::new);
+
+      for key in &keys {
+        if may_have_signed(key.key_id()) && signature.verify(key, content()?).is_ok() {
+          return Ok::<_, std::io::Error>(true);
+        }
+        for subkey in &key.public_subkeys {
+          if may_have_signed(subkey.key_id()) && signature.verify(subkey, content()?).is_ok() {
+            return Ok(true);
+          }
+        }
+      }
+      Ok(false)
+    })
+    .await
+    .map_err(|e| failed(e.to_string()))??;
+
+    if !valid {
+      return Err(failed(format!(
+        "not signed by a key in {}",
+        self.keyring.display()
+      )));
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_unreadable_signature_fails() {
+    let dir = std::env::temp_dir().join(format!("rd-pgp-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(dir.join("file.bin"), b"abc")
+      .await
+      .unwrap();
+    tokio::fs::write(dir.join("keys.asc"), b"not a keyring")
+      .await
+      .unwrap();
+
+    let signature = PgpSignature::builder()
+      .signature(SignatureSource::Bytes(b"not a signature".to_vec()))
+      .keyring(dir.join("keys.asc"))
+      .build();
+    let result = signature
+      .verify("https://example.com/file.bin", dir.join("file.bin"))
+      .await;
+    assert!(matches!(
+      result,
+      Err(ProgressDownloadError::PgpSignature { .. })
+    ));
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
@@ -0,0 +1,203 @@
+use std::{
+  collections::{BTreeMap, HashSet},
+  io,
+  path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::err::ProgressDownloadError;
+
+/// The bytes one item of a batch writes, by filesystem id (see [`Volumes::locate`]).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SpaceNeed {
+  pub url: String,
+  /// The item's full size, 0 if unknown.
+  pub size: u64,
+  pub bytes: Vec<(String, u64)>,
+}
+
+/// The filesystems a batch writes to, with the space available on each.
+#[derive(Debug, Default)]
+pub(crate) struct Volumes {
+  volumes: BTreeMap<String, Volume>,
+}
+
+#[derive(Debug)]
+struct Volume {
+  /// A directory on the filesystem, for error messages.
+  dir: PathBuf,
+  available: u64,
+}
+
+impl Volumes {
+  /// Returns the id of the filesystem `dir` will be on; it does not have to exist yet.
+  pub async fn locate(&mut self, dir: &Path) -> io::Result<String> {
+    let mut located = None;
+    for ancestor in dir.ancestors() {
+      // Relative paths end in "", the current directory.
+      let ancestor = if ancestor.as_os_str().is_empty() {
+        Path::new(".")
+      } else {
+        ancestor
+      };
+      if let Ok(metadata) = tokio::fs::metadata(ancestor).await {
+        located = Some((ancestor.to_path_buf(), metadata));
+        break;
+      }
+    }
+    let (existing, metadata) = located.ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no existing directory above {}", dir.display()),
+      )
+    })?;
+
+    let id = filesystem_id(&existing, &metadata);
+    if !self.volumes.contains_key(&id) {
+      let available = fs4::available_space(&existing)?;
+      self.volumes.insert(
+        id.clone(),
+        Volume {
+          dir: existing,
+          available,
+        },
+      );
+    }
+    Ok(id)
+  }
+
+  /// Fits the needs of a batch's items into the available space.
+  ///
+  /// Without `best_effort`, fails with [`ProgressDownloadError::InsufficientSpace`] as soon
+  /// as one filesystem is short for the whole batch. With it, items are admitted in order
+  /// while they fit and the positions of the others are returned.
+  pub fn plan(
+    &self,
+    needs: &[SpaceNeed],
+    best_effort: bool,
+  ) -> Result<HashSet<usize>, ProgressDownloadError> {
+    if !best_effort {
+      for (id, volume) in &self.volumes {
+        let mut items = needs
+          .iter()
+          .flat_map(|need| {
+            need
+              .bytes
+              .iter()
+              .filter(|(on, _)| on == id)
+              .map(|(_, bytes)| (need.url.clone(), *bytes))
+          })
+          .collect::<Vec<_>>();
+        let needed = items.iter().map(|(_, bytes)| bytes).sum::<u64>();
+        if needed > volume.available {
+          items.sort_by_key(|i| std::cmp::Reverse(i.1));
+          return Err(ProgressDownloadError::InsufficientSpace {
+            dir: volume.dir.clone(),
+            needed,
+            available: volume.available,
+            items,
+          });
+        }
+      }
+      return Ok(HashSet::new());
+    }
+
+    let mut available = self
+      .volumes
+      .iter()
+      .map(|(id, volume)| (id.as_str(), volume.available))
+      .collect::<BTreeMap<_, _>>();
+    let mut left_out = HashSet::new();
+    for (index, need) in needs.iter().enumerate() {
+      let fits = need
+        .bytes
+        .iter()
+        .all(|(id, bytes)| available.get(id.as_str()).is_some_and(|left| left >= bytes));
+      if fits {
+        for (id, bytes) in &need.bytes {
+          if let Some(left) = available.get_mut(id.as_str()) {
+            *left -= bytes;
+          }
+        }
+      } else {
+        warn!("not enough space left for {}, leaving it out", need.url);
+        left_out.insert(index);
+      }
+    }
+    Ok(left_out)
+  }
+}
+
+#[cfg(unix)]
+fn filesystem_id(_dir: &Path, metadata: &std::fs::Metadata) -> String {
+  use std::os::unix::fs::MetadataExt;
+  metadata.dev().to_string()
+}
+
+#[cfg(not(unix))]
+fn filesystem_id(dir: &Path, _metadata: &std::fs::Metadata) -> String {
+  // The drive or share; volumes mounted into folders are not told apart.
+  std::path::absolute(dir)
+    .ok()
+    .and_then(|dir| {
+      dir
+        .components()
+        .next()
+        .map(|prefix| prefix.as_os_str().to_string_lossy().to_string())
+    })
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn volumes(available: &[(&str, u64)]) -> Volumes {
+    Volumes {
+      volumes: available
+        .iter()
+        .map(|&(id, available)| {
+          let dir = PathBuf::from(format!("/{id}"));
+          (id.to_string(), Volume { dir, available })
+        })
+        .collect(),
+    }
+  }
+
+  fn need(url: &str, bytes: &[(&str, u64)]) -> SpaceNeed {
+    SpaceNeed {
+      url: url.to_string(),
+      bytes: bytes
+        .iter()
+        .map(|&(id, bytes)| (id.to_string(), bytes))
+        .collect(),
+      ..SpaceNeed::default()
+    }
+  }
+
+  #[test]
+  fn test_plan() {
+    let volumes = volumes(&[("tmp", 100), ("data", 1000)]);
+    let needs = [
+      need("a", &[("tmp", 60), ("data", 60)]),
+      need("b", &[("tmp", 60), ("data", 60)]),
+      need("c", &[("data", 500)]),
+    ];
+
+    let Err(ProgressDownloadError::InsufficientSpace {
+      needed,
+      available,
+      items,
+      ..
+    }) = volumes.plan(&needs, false)
+    else {
+      panic!("the temp dir is short");
+    };
+    assert_eq!((needed, available), (120, 100));
+    assert_eq!(items, [("a".to_string(), 60), ("b".to_string(), 60)]);
+
+    assert_eq!(volumes.plan(&needs, true).unwrap(), HashSet::from([1]));
+    assert!(volumes.plan(&needs[1..], false).unwrap().is_empty());
+  }
+}
@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+use rand::Rng;
+use typed_builder::TypedBuilder;
+
+/// How [`RobustDownloader::spot_check`](crate::RobustDownloader::spot_check) samples a
+/// local copy of a remote file: its first and last `sample_size` bytes, and `samples` more
+/// ranges at random offsets.
+///
+/// ```rust
+/// use robust_downloader::SpotCheck;
+///
+/// let check = SpotCheck::builder().samples(32).sample_size(1024 * 1024).build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
+pub struct SpotCheck {
+  /// Ranges compared at random offsets, besides the first and the last one. Defaults to 8.
+  #[builder(default = 8)]
+  pub samples: u32,
+  /// Bytes in each range. Defaults to 64 KiB.
+  #[builder(default = 64 * 1024)]
+  pub sample_size: u64,
+}
+
+impl Default for SpotCheck {
+  fn default() -> Self {
+    Self::builder().build()
+  }
+}
+
+impl SpotCheck {
+  /// The ranges to compare of a file of `size` bytes, sorted and without duplicates.
+  pub(crate) fn ranges(&self, size: u64) -> Vec<Range<u64>> {
+    if size == 0 {
+      return Vec::new();
+    }
+    let len = self.sample_size.clamp(1, size);
+    let last = size - len;
+
+    let mut rng = rand::thread_rng();
+    let mut starts = [0, last]
+      .into_iter()
+      .chain((0..self.samples).map(|_| rng.gen_range(0..=last)))
+      .collect::<Vec<_>>();
+    starts.sort_unstable();
+    starts.dedup();
+    starts.into_iter().map(|start| start..start + len).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ranges() {
+    let check = SpotCheck::builder().samples(16).sample_size(10).build();
+    let ranges = check.ranges(1000);
+    assert_eq!(ranges.first(), Some(&(0..10)));
+    assert_eq!(ranges.last(), Some(&(990..1000)));
+    assert!(ranges.len() <= 18);
+    assert!(ranges.iter().all(|range| range.end - range.start == 10));
+
+    // A file smaller than a sample is compared whole, once.
+    assert_eq!(check.ranges(4), vec![0..4]);
+    assert!(check.ranges(0).is_empty());
+  }
+}
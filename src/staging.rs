@@ -0,0 +1,92 @@
+use std::{
+  env,
+  ffi::OsStr,
+  path::{Path, PathBuf},
+};
+
+/// Where an item is downloaded to before it is verified and placed at its target.
+///
+/// Partial files stay there between runs so downloads can resume, and placing a verified
+/// file is a plain rename only when the staging file is on the target's filesystem;
+/// otherwise it is copied. In a shared directory the staging file's name includes a hash
+/// of the item's URL and target, so items with the same file name never share one.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Staging {
+  /// The system temp directory. Often a different filesystem than the target, and wiped
+  /// on reboot on many systems, which loses partial downloads.
+  #[default]
+  TempDir,
+  /// This directory, created if needed.
+  Dir(PathBuf),
+  /// `<target>.part` next to the target, so placing it is always an atomic rename.
+  NextToTarget,
+}
+
+impl Staging {
+  /// The staging file for `file_name`, to be placed in `target_dir`. `key` identifies the
+  /// item; the same key always maps to the same file, so a later run resumes it.
+  pub(crate) fn temp_file(&self, target_dir: &Path, file_name: &OsStr, key: &str) -> PathBuf {
+    let unique = || {
+      let mut name = file_name.to_os_string();
+      name.push(format!(".{:016x}", fingerprint(key)));
+      name
+    };
+    match self {
+      Staging::TempDir => env::temp_dir().join(unique()),
+      Staging::Dir(dir) => dir.join(unique()),
+      // The target's own path already tells items apart.
+      Staging::NextToTarget => {
+        let mut name = file_name.to_os_string();
+        name.push(".part");
+        target_dir.join(name)
+      }
+    }
+  }
+}
+
+/// Where the validator of a partial download is kept, see `resume_validator`.
+pub(crate) fn validator_file(temp_file: &Path) -> PathBuf {
+  let mut name = temp_file.as_os_str().to_os_string();
+  name.push(".validator");
+  PathBuf::from(name)
+}
+
+/// FNV-1a: unlike the std hashers it is stable across builds, so partial files of an
+/// earlier run are found again.
+fn fingerprint(key: &str) -> u64 {
+  key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_part_file_next_to_target() {
+    let name = OsStr::new("tool.tar.gz");
+
+    assert_eq!(
+      Staging::NextToTarget.temp_file(Path::new("out/bin"), name, "key"),
+      Path::new("out/bin/tool.tar.gz.part")
+    );
+  }
+
+  #[test]
+  fn test_same_file_name_gets_distinct_temp_files() {
+    let staging = Staging::Dir(PathBuf::from("/var/cache/dl"));
+    let name = OsStr::new("file.tar.gz");
+    let a = staging.temp_file(Path::new("a"), name, "https://example.com/a/file.tar.gz\na");
+    let b = staging.temp_file(Path::new("b"), name, "https://example.com/b/file.tar.gz\nb");
+
+    assert_ne!(a, b);
+    assert!(a.starts_with("/var/cache/dl"));
+    assert!(a.to_string_lossy().contains("file.tar.gz."));
+    // Stable, so a later run resumes the same file.
+    assert_eq!(
+      a,
+      staging.temp_file(Path::new("a"), name, "https://example.com/a/file.tar.gz\na")
+    );
+  }
+}
@@ -0,0 +1,59 @@
+use std::{fmt::Debug, path::Path, time::Duration};
+
+use futures::future::BoxFuture;
+
+/// The parts of an item a [`StartPolicy`] decides on.
+#[derive(Debug, Clone, Copy)]
+pub struct StartCandidate<'a> {
+  pub url: &'a str,
+  pub target: &'a Path,
+  pub tags: &'a [String],
+  /// The item's [`expected_size`](crate::DownloadItem::expected_size), or else its
+  /// [`size_hint`](crate::DownloadItem::size_hint).
+  pub size_hint: Option<u64>,
+}
+
+/// What a [`StartPolicy`] decided for an item that just got a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartDecision {
+  /// Download the item now.
+  Start,
+  /// Give the slot to another item and ask again after this long.
+  Defer(Duration),
+}
+
+/// Decides whether an item may start once a concurrency slot is free for it, e.g. to keep
+/// large, low-priority items back until off-peak hours, Wi-Fi or AC power.
+///
+/// A deferred item hands its slot back while it waits, so items the policy lets through
+/// are not held up. A deferred item that is cancelled (by tag or token) meanwhile is not
+/// asked about again once its wait is over; it is reported as cancelled.
+///
+/// ```rust
+/// use std::{sync::Arc, time::Duration};
+/// use futures::future::BoxFuture;
+/// use robust_downloader::{RobustDownloader, StartCandidate, StartDecision, StartPolicy};
+///
+/// #[derive(Debug)]
+/// struct BulkAtNight;
+///
+/// impl StartPolicy for BulkAtNight {
+///   fn should_start<'a>(&'a self, item: StartCandidate<'a>) -> BoxFuture<'a, StartDecision> {
+///     Box::pin(async move {
+///       if item.tags.iter().any(|tag| tag == "bulk") && !off_peak() {
+///         StartDecision::Defer(Duration::from_secs(600))
+///       } else {
+///         StartDecision::Start
+///       }
+///     })
+///   }
+/// }
+/// # fn off_peak() -> bool { true }
+///
+/// let downloader = RobustDownloader::builder()
+///   .start_policy(Arc::new(BulkAtNight))
+///   .build();
+/// ```
+pub trait StartPolicy: Debug + Send + Sync {
+  fn should_start<'a>(&'a self, item: StartCandidate<'a>) -> BoxFuture<'a, StartDecision>;
+}
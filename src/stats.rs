@@ -0,0 +1,182 @@
+use std::{
+  collections::VecDeque,
+  sync::{
+    Mutex,
+    atomic::{AtomicUsize, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+/// Window over which the current throughput is averaged.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Samples closer together than this are merged into one bucket.
+const THROUGHPUT_BUCKET: Duration = Duration::from_millis(100);
+
+/// Window over which failed attempts are counted.
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// A point-in-time view of what a [`RobustDownloader`](crate::RobustDownloader) is doing.
+///
+/// Obtained through [`RobustDownloader::stats`](crate::RobustDownloader::stats) while
+/// downloads are running, e.g. to defer non-critical batches when the link is busy.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DownloadStats {
+  /// Number of downloads currently holding a concurrency slot.
+  pub active: usize,
+  /// Number of downloads waiting for a concurrency slot.
+  pub queued: usize,
+  /// Bytes received per second, averaged over the last 5 seconds.
+  pub bytes_per_sec: f64,
+  /// Number of failed attempts (retried or not) during the last minute.
+  pub failures_last_minute: usize,
+  /// Chunks received from the network but not yet handed to the writers.
+  pub buffered_chunks: usize,
+}
+
+/// Shared, lock-light collector behind [`DownloadStats`].
+#[derive(Debug, Default)]
+pub(crate) struct StatsCollector {
+  active: AtomicUsize,
+  queued: AtomicUsize,
+  buffered_chunks: AtomicUsize,
+  throughput: Mutex<VecDeque<(Instant, u64)>>,
+  failures: Mutex<VecDeque<Instant>>,
+}
+
+impl StatsCollector {
+  /// Marks a download as waiting for a slot until the returned guard is activated or dropped.
+  pub fn enqueue(&self) -> QueuedGuard<'_> {
+    self.queued.fetch_add(1, Ordering::Relaxed);
+    QueuedGuard { collector: self }
+  }
+
+  pub fn record_bytes(&self, bytes: u64, now: Instant) {
+    let mut samples = self.throughput.lock().unwrap_or_else(|e| e.into_inner());
+    match samples.back_mut() {
+      Some((at, total)) if now.duration_since(*at) < THROUGHPUT_BUCKET => *total += bytes,
+      _ => samples.push_back((now, bytes)),
+    }
+    prune(&mut samples, now, THROUGHPUT_WINDOW, |(at, _)| *at);
+  }
+
+  pub fn buffer_push(&self) {
+    self.buffered_chunks.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn buffer_pop(&self) {
+    self.buffer_pop_n(1);
+  }
+
+  pub fn buffer_pop_n(&self, chunks: usize) {
+    self.buffered_chunks.fetch_sub(chunks, Ordering::Relaxed);
+  }
+
+  pub fn record_failure(&self, now: Instant) {
+    let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+    failures.push_back(now);
+    prune(&mut failures, now, FAILURE_WINDOW, |at| *at);
+  }
+
+  /// The counters as of `now`.
+  pub fn snapshot(&self, now: Instant) -> DownloadStats {
+    let bytes_per_sec = {
+      let mut samples = self.throughput.lock().unwrap_or_else(|e| e.into_inner());
+      prune(&mut samples, now, THROUGHPUT_WINDOW, |(at, _)| *at);
+      let total: u64 = samples.iter().map(|(_, bytes)| bytes).sum();
+      total as f64 / THROUGHPUT_WINDOW.as_secs_f64()
+    };
+
+    let failures_last_minute = {
+      let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+      prune(&mut failures, now, FAILURE_WINDOW, |at| *at);
+      failures.len()
+    };
+
+    DownloadStats {
+      active: self.active.load(Ordering::Relaxed),
+      queued: self.queued.load(Ordering::Relaxed),
+      bytes_per_sec,
+      failures_last_minute,
+      buffered_chunks: self.buffered_chunks.load(Ordering::Relaxed),
+    }
+  }
+}
+
+fn prune<T>(samples: &mut VecDeque<T>, now: Instant, window: Duration, at: impl Fn(&T) -> Instant) {
+  while samples
+    .front()
+    .is_some_and(|sample| now.duration_since(at(sample)) > window)
+  {
+    samples.pop_front();
+  }
+}
+
+/// Counts a download as queued for as long as it lives.
+#[derive(Debug)]
+pub(crate) struct QueuedGuard<'a> {
+  collector: &'a StatsCollector,
+}
+
+impl<'a> QueuedGuard<'a> {
+  /// Moves the download from the queue to the active set.
+  pub fn activate(self) -> ActiveGuard<'a> {
+    let collector = self.collector;
+    // `Drop` of `self` takes care of the queued counter.
+    drop(self);
+    collector.active.fetch_add(1, Ordering::Relaxed);
+    ActiveGuard { collector }
+  }
+}
+
+impl Drop for QueuedGuard<'_> {
+  fn drop(&mut self) {
+    self.collector.queued.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+/// Counts a download as active for as long as it lives.
+#[derive(Debug)]
+pub(crate) struct ActiveGuard<'a> {
+  collector: &'a StatsCollector,
+}
+
+impl Drop for ActiveGuard<'_> {
+  fn drop(&mut self) {
+    self.collector.active.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_guards_track_queue_and_active() {
+    let collector = StatsCollector::default();
+    let now = Instant::now();
+
+    let queued = collector.enqueue();
+    assert_eq!(collector.snapshot(now).queued, 1);
+
+    let active = queued.activate();
+    let stats = collector.snapshot(now);
+    assert_eq!((stats.queued, stats.active), (0, 1));
+
+    drop(active);
+    assert_eq!(collector.snapshot(now).active, 0);
+  }
+
+  #[test]
+  fn test_throughput_and_failures() {
+    let collector = StatsCollector::default();
+    let now = Instant::now();
+    collector.record_bytes(5 * 1024, now);
+    collector.record_bytes(5 * 1024, now);
+    collector.record_failure(now);
+
+    let stats = collector.snapshot(now);
+    assert_eq!(stats.bytes_per_sec, 2048.0);
+    assert_eq!(stats.failures_last_minute, 1);
+  }
+}
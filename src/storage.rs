@@ -0,0 +1,126 @@
+use std::{
+  collections::HashMap,
+  fmt::Debug,
+  io::{self, ErrorKind},
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+use futures::future::BoxFuture;
+
+/// Where verified downloads end up.
+///
+/// The download engine always streams into a local staging file so that retries, resume
+/// and integrity checks work the same for every backend. Once an item is complete and
+/// verified, the staging file is handed to [`Storage::place`], which owns it from then on:
+/// implementations must move or delete it.
+///
+/// The default is [`LocalStorage`]. Implement this trait to store into an object store,
+/// an archive being built, or anything else.
+pub trait Storage: Debug + Send + Sync {
+  /// Stores the content of `staged` under `target`, consuming the staging file.
+  fn place<'a>(&'a self, staged: &'a Path, target: &'a Path) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// Stores targets on the local filesystem, creating parent directories as needed.
+///
+/// A target is always replaced in one rename, also when the staging file is on another
+/// filesystem, so it is either the old file or the complete new one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalStorage;
+
+impl Storage for LocalStorage {
+  fn place<'a>(&'a self, staged: &'a Path, target: &'a Path) -> BoxFuture<'a, io::Result<()>> {
+    Box::pin(async move {
+      // 确保目标文件的父目录存在
+      if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+      }
+
+      if let Err(e) = tokio::fs::rename(staged, target).await {
+        if e.kind() == ErrorKind::CrossesDevices {
+          // 跨设备重命名失败，尝试复制
+          // The copy is made next to the target and renamed, so the target never shows up
+          // half-written.
+          let mut aside = target.as_os_str().to_owned();
+          aside.push(".placing");
+          let aside = PathBuf::from(aside);
+          let copied = async {
+            tokio::fs::copy(staged, &aside).await?;
+            tokio::fs::rename(&aside, target).await
+          }
+          .await;
+          if let Err(e) = copied {
+            let _ = tokio::fs::remove_file(&aside).await;
+            return Err(e);
+          }
+          tokio::fs::remove_file(staged).await?;
+        } else {
+          return Err(e);
+        }
+      }
+
+      Ok(())
+    })
+  }
+}
+
+/// Keeps targets in memory, keyed by target path. Mostly useful for tests and for
+/// pipelines that post-process downloads without touching the disk twice.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+  files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+  /// Returns a copy of the content stored under `target`.
+  pub fn get(&self, target: impl AsRef<Path>) -> Option<Vec<u8>> {
+    self.lock().get(target.as_ref()).cloned()
+  }
+
+  /// Removes and returns the content stored under `target`.
+  pub fn take(&self, target: impl AsRef<Path>) -> Option<Vec<u8>> {
+    self.lock().remove(target.as_ref())
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Vec<u8>>> {
+    self.files.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl Storage for MemoryStorage {
+  fn place<'a>(&'a self, staged: &'a Path, target: &'a Path) -> BoxFuture<'a, io::Result<()>> {
+    Box::pin(async move {
+      let content = tokio::fs::read(staged).await?;
+      tokio::fs::remove_file(staged).await?;
+      self.lock().insert(target.to_path_buf(), content);
+      Ok(())
+    })
+  }
+}
+
+/// Keeps the staged file where it is and remembers it, so the caller can consume the
+/// verified content itself. Used to pipe verified downloads to stdout.
+#[derive(Debug, Default)]
+pub(crate) struct HoldingStorage {
+  held: Mutex<Option<PathBuf>>,
+}
+
+impl HoldingStorage {
+  /// Returns the staged file of the placed item, if one was placed.
+  pub fn take(&self) -> Option<PathBuf> {
+    self.held.lock().unwrap_or_else(|e| e.into_inner()).take()
+  }
+}
+
+impl Storage for HoldingStorage {
+  fn place<'a>(&'a self, staged: &'a Path, _target: &'a Path) -> BoxFuture<'a, io::Result<()>> {
+    Box::pin(async move {
+      // Move it aside so a later download of the same file name cannot resume into it.
+      let held = staged.with_extension("verified");
+      tokio::fs::rename(staged, &held).await?;
+      *self.held.lock().unwrap_or_else(|e| e.into_inner()) = Some(held);
+      Ok(())
+    })
+  }
+}
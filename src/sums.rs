@@ -0,0 +1,300 @@
+use std::path::{Path, PathBuf};
+
+use cow_utils::CowUtils;
+use hashery::Hashery;
+
+use crate::{err::ProgressDownloadError, integrity::Integrity};
+
+/// File name of the list written by [`write_sha256sums`].
+pub const SHA256SUMS: &str = "SHA256SUMS";
+/// Where the list is written before being renamed into place.
+const STAGED: &str = ".SHA256SUMS.tmp";
+
+/// Writes a `SHA256SUMS` file into `dir` listing every file below it, e.g. after mirroring
+/// a release directory, so consumers of the mirror can check what they fetch from it with
+/// `sha256sum -c` or with an item's [`Integrity::SHA256`](crate::Integrity::SHA256).
+///
+/// Lines are `<sha256>  <path>`, sorted by path, with `/`-separated paths relative to
+/// `dir`. Symlinks to files are listed under their own name; symlinked directories are not
+/// followed. A list left by an earlier call is replaced, not listed. Returns the path of
+/// the list.
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let sums = robust_downloader::write_sha256sums("mirror/node/v23.9.0").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn write_sha256sums(dir: impl AsRef<Path>) -> Result<PathBuf, ProgressDownloadError> {
+  let dir = dir.as_ref();
+  let hashery = Hashery::builder()
+    .algorithm(hashery::Algorithm::SHA256)
+    .build();
+
+  let mut files = list_files(dir).await?;
+  files.sort();
+
+  let mut list = String::new();
+  for relative in files {
+    let digest = hashery.digest(&dir.join(&relative)).await?;
+    list.push_str(&format!("{digest}  {relative}\n"));
+  }
+
+  // Written aside and renamed, so a consumer never sees a half-written list.
+  let path = dir.join(SHA256SUMS);
+  let staged = dir.join(STAGED);
+  tokio::fs::write(&staged, list).await?;
+  tokio::fs::rename(&staged, &path).await?;
+  Ok(path)
+}
+
+/// A checksum list an item is verified against instead of an
+/// [`integrity`](crate::DownloadItem::integrity) of its own: the `SHASUMS256.txt` or
+/// `SHA512SUMS` published next to a release, in the format written by `sha256sum`,
+/// `sha512sum` and `md5sum` (`<hex>  <name>`, or `<ALGORITHM> (<name>) = <hex>` with
+/// `--tag`).
+///
+/// The list is read before the item is downloaded, and its entry for the target's file
+/// name is used, or failing that its entry for the file name in the item's URL. Entries
+/// with directories match by their last component when no entry matches exactly. The
+/// algorithm follows from the length of the checksum; MD5 entries need the `md5` feature.
+///
+/// ```rust
+/// use robust_downloader::{DownloadItem, IntegrityFile};
+///
+/// let item = DownloadItem::builder()
+///   .url("https://nodejs.org/dist/v23.9.0/node-v23.9.0-linux-x64.tar.gz")
+///   .target("node-v23.9.0-linux-x64.tar.gz")
+///   .integrity_file(IntegrityFile::Url(
+///     "https://nodejs.org/dist/v23.9.0/SHASUMS256.txt".to_string(),
+///   ))
+///   .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityFile {
+  /// A list on the local disk.
+  Path(PathBuf),
+  /// A list fetched with the downloader's client.
+  Url(String),
+}
+
+impl std::fmt::Display for IntegrityFile {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Path(path) => write!(f, "{}", path.display()),
+      Self::Url(url) => f.write_str(url),
+    }
+  }
+}
+
+/// The checksum listed for the first of `names` in `list`, matching entries exactly before
+/// matching them by their last component. Lines that are not entries are skipped.
+pub(crate) fn find(list: &str, names: &[String]) -> Option<Integrity> {
+  let entries = list.lines().filter_map(entry).collect::<Vec<_>>();
+  let exact = |name: &String| entries.iter().find(|(entry, _)| entry == name);
+  let by_file_name = |name: &String| {
+    entries
+      .iter()
+      .find(|(entry, _)| entry.rsplit('/').next() == Some(name.as_str()))
+  };
+
+  let (_, digest) = names
+    .iter()
+    .find_map(exact)
+    .or_else(|| names.iter().find_map(by_file_name))?;
+  integrity(digest)
+}
+
+/// The checksum of a file holding only one, like the `tool.tar.gz.sha256` some releases
+/// publish next to each artifact: a bare checksum or a single entry, whatever its name.
+pub(crate) fn single(list: &str) -> Option<Integrity> {
+  let mut lines = list
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#'));
+  let line = lines.next().filter(|_| lines.next().is_none())?;
+  match entry(line) {
+    Some((_, digest)) => integrity(digest),
+    None if line.chars().all(|c| c.is_ascii_hexdigit()) => integrity(line),
+    None => None,
+  }
+}
+
+/// The algorithm of a hex checksum follows from its length.
+fn integrity(digest: &str) -> Option<Integrity> {
+  let digest = digest.cow_to_ascii_lowercase().into_owned();
+  match digest.len() {
+    #[cfg(feature = "md5")]
+    32 => Some(Integrity::MD5(digest)),
+    #[cfg(feature = "sha1")]
+    40 => Some(Integrity::SHA1(digest)),
+    64 => Some(Integrity::SHA256(digest)),
+    128 => Some(Integrity::SHA512(digest)),
+    _ => None,
+  }
+}
+
+/// Parses one line of a checksum list into the file name and its hex checksum.
+fn entry(line: &str) -> Option<(String, &str)> {
+  // `sha256sum` escapes names holding a backslash or a newline, and marks their lines.
+  let (escaped, line) = match line.strip_prefix('\\') {
+    Some(line) => (true, line),
+    None => (false, line),
+  };
+
+  let (name, digest) = match line.split_once(" (") {
+    // `SHA256 (name) = <hex>`
+    Some((algorithm, rest)) if algorithm.chars().all(|c| c.is_ascii_alphanumeric()) => {
+      rest.rsplit_once(") = ")?
+    }
+    // `<hex>  <name>`, or `<hex> *<name>` for files read in binary mode
+    _ => {
+      let (digest, rest) = line.split_once(' ')?;
+      let name = rest.strip_prefix([' ', '*']).unwrap_or(rest);
+      (name, digest)
+    }
+  };
+  if digest.is_empty() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+    return None;
+  }
+
+  let name = name.strip_prefix("./").unwrap_or(name);
+  let name = if escaped {
+    unescape(name)
+  } else {
+    name.to_string()
+  };
+  Some((name, digest))
+}
+
+/// Undoes the `\\` and `\n` escapes of `sha256sum`, in one pass so an escaped backslash
+/// followed by `n` stays a backslash and an `n`.
+fn unescape(name: &str) -> String {
+  let mut unescaped = String::with_capacity(name.len());
+  let mut chars = name.chars().peekable();
+  while let Some(c) = chars.next() {
+    let escaped = match (c, chars.peek()) {
+      ('\\', Some('\\')) => Some('\\'),
+      ('\\', Some('n')) => Some('\n'),
+      _ => None,
+    };
+    match escaped {
+      Some(escaped) => {
+        chars.next();
+        unescaped.push(escaped);
+      }
+      None => unescaped.push(c),
+    }
+  }
+  unescaped
+}
+
+/// Paths of the files below `dir`, relative to it and `/`-separated.
+async fn list_files(dir: &Path) -> Result<Vec<String>, ProgressDownloadError> {
+  let mut files = Vec::new();
+  let mut pending = vec![(dir.to_path_buf(), String::new())];
+
+  while let Some((current, prefix)) = pending.pop() {
+    let mut entries = tokio::fs::read_dir(&current).await?;
+    while let Some(entry) = entries.next_entry().await? {
+      let path = entry.path();
+      let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+        return Err(ProgressDownloadError::Path {
+          path: path.to_string_lossy().to_string(),
+        });
+      };
+      let relative = format!("{prefix}{name}");
+
+      if entry.file_type().await?.is_dir() {
+        pending.push((path, format!("{relative}/")));
+      } else if !matches!(relative.as_str(), SHA256SUMS | STAGED)
+        && tokio::fs::metadata(&path)
+          .await
+          .is_ok_and(|metadata| metadata.is_file())
+      {
+        files.push(relative);
+      }
+    }
+  }
+
+  Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_lists_files_relative_to_dir() {
+    let dir = std::env::temp_dir().join(format!("rd-sha256sums-{}", std::process::id()));
+    tokio::fs::create_dir_all(dir.join("docs")).await.unwrap();
+    tokio::fs::write(dir.join("tool.tar.gz"), b"abc")
+      .await
+      .unwrap();
+    tokio::fs::write(dir.join("docs/empty"), b"").await.unwrap();
+
+    // The second run must not list the first run's output.
+    for _ in 0..2 {
+      let sums = write_sha256sums(&dir).await.unwrap();
+      assert_eq!(
+        tokio::fs::read_to_string(&sums).await.unwrap(),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  docs/empty\n\
+         ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  tool.tar.gz\n"
+      );
+    }
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[test]
+  fn test_find() {
+    let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let list = format!(
+      "# node v23.9.0\n\
+       {}  node-v23.9.0.tar.gz\n\
+       {sha256} *win-x64/node.exe\n\
+       SHA512 (node.pkg) = {}\n\
+       \\{}  odd\\\\name\r\n",
+      "0".repeat(64),
+      "A".repeat(128),
+      "1".repeat(64),
+    );
+    let lookup = |names: &[&str]| {
+      let names = names.iter().map(ToString::to_string).collect::<Vec<_>>();
+      find(&list, &names).map(|integrity| integrity.value().to_string())
+    };
+
+    assert_eq!(lookup(&["node-v23.9.0.tar.gz"]), Some("0".repeat(64)));
+    assert_eq!(lookup(&["node.exe"]), Some(sha256.to_string()));
+    assert_eq!(lookup(&["node.pkg"]), Some("a".repeat(128)));
+    assert_eq!(lookup(&["odd\\name"]), Some("1".repeat(64)));
+    // The target's name is looked up first, the URL's only when it is not listed.
+    assert_eq!(
+      lookup(&["renamed.tar.gz", "node-v23.9.0.tar.gz"]),
+      Some("0".repeat(64))
+    );
+    assert_eq!(lookup(&["node"]), None);
+  }
+
+  #[test]
+  fn test_unescape() {
+    assert_eq!(unescape("odd\\\\name"), "odd\\name");
+    assert_eq!(unescape("two\\nlines"), "two\nlines");
+    // An escaped backslash before an `n` is not a newline, and NULs are kept as they are.
+    assert_eq!(unescape("a\\\\nb\0c"), "a\\nb\0c");
+  }
+
+  #[test]
+  fn test_single() {
+    let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let value = |list: &str| single(list).map(|integrity| integrity.value().to_string());
+
+    assert_eq!(value(&format!("{sha256}\n")), Some(sha256.to_string()));
+    assert_eq!(
+      value(&format!("{sha256}  build/out.tar.gz")),
+      Some(sha256.to_string())
+    );
+    assert_eq!(value(&format!("{sha256}  a\n{sha256}  b\n")), None);
+    assert_eq!(value("not a checksum"), None);
+  }
+}
@@ -1,5 +1,15 @@
-use std::{io::ErrorKind, path::Path, time::Duration};
+use std::{
+  io::SeekFrom,
+  ops::Range,
+  path::{Path, PathBuf},
+  sync::{
+    Arc, Mutex, OnceLock,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+  },
+  time::{Duration, Instant, SystemTime},
+};
 
+use cow_utils::CowUtils;
 use futures::StreamExt;
 #[cfg(any(
   feature = "md5",
@@ -10,26 +20,83 @@ use futures::StreamExt;
   feature = "blake3"
 ))]
 use hashery::Hashery;
-use indicatif::ProgressBar;
-use log::debug;
-use reqwest::IntoUrl;
-use tokio::io::AsyncWriteExt;
+use log::{debug, info, warn};
+use reqwest::{
+  IntoUrl, Method, StatusCode, Url,
+  header::{
+    CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_RANGE, ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED,
+    RANGE, RETRY_AFTER,
+  },
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use typed_builder::TypedBuilder;
 
-use crate::{err::ProgressDownloadError, item::DownloadItem, tracker::DownloadTracker};
+#[cfg(any(feature = "md5", feature = "sha2"))]
+use crate::integrity;
+use crate::{
+  auth::Auth,
+  cancel::CancellationToken,
+  clock::{Clock, SystemClock},
+  err::ProgressDownloadError,
+  filename::FilenamePolicy,
+  item::{DownloadItem, FanOut},
+  memory::MemoryBudget,
+  observer::{ItemObserver, TaskState},
+  overwrite::OverwritePolicy,
+  pacing::HostPacer,
+  pins::PinStore,
+  redirect::{self, Inspected, RedirectPolicy},
+  remote::RemoteMetadata,
+  report::AttemptMetrics,
+  sign::RequestSigner,
+  staging,
+  stats::StatsCollector,
+  storage::Storage,
+  throttle::BandwidthLimiter,
+  tracker::DownloadTracker,
+  transform::TransformChain,
+  verifier,
+};
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+use crate::{
+  cache::DigestCache,
+  hasher::{Hashers, StreamHasher},
+};
+#[cfg(feature = "sha2")]
+use crate::{integrity::Integrity, naming};
+
+/// Capacity of the write buffer of a download when no memory limit applies.
+const WRITE_BUFFER_CAPACITY: usize = 1024 * 1024;
 
 #[derive(Debug, TypedBuilder)]
-pub struct DownloadTaskRunner<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> {
+pub struct DownloadTaskRunner<'a, U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> {
   #[builder]
   client: reqwest::Client,
   #[builder]
-  progress_bar: ProgressBar,
+  observer: Arc<ItemObserver>,
 
   #[builder]
   tmp_file: P,
 
+  /// Borrowed, so a batch never holds more than one copy of an item's URLs and paths.
   #[builder]
-  item: DownloadItem<U, TP>,
+  item: &'a DownloadItem<U, TP>,
+  /// The URL being downloaded: the item's own or one of its mirrors.
+  #[builder]
+  url: &'a U,
+  /// Where the file goes: the item's target, unless its overwrite policy picked a new name.
+  #[builder(default = item.target.as_ref().to_path_buf())]
+  target: PathBuf,
+  /// The item's own limit, shared by all its attempts at this URL.
+  #[builder(default = item.max_bandwidth.map(BandwidthLimiter::new), setter(skip))]
+  item_bandwidth: Option<BandwidthLimiter>,
   #[builder]
   timeout: Duration,
 
@@ -37,31 +104,763 @@ pub struct DownloadTaskRunner<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path
   read_chunk_timeout: Duration,
   #[builder]
   flush_threshold: usize,
+  #[builder(default = 16)]
+  buffer_chunks: usize,
+  #[builder]
+  stats: Arc<StatsCollector>,
+  #[builder]
+  storage: Arc<dyn Storage>,
+  #[builder(default)]
+  memory: Option<Arc<MemoryBudget>>,
+  #[builder(default)]
+  bandwidth: Option<Arc<BandwidthLimiter>>,
+  #[builder(default)]
+  cancelled: Arc<AtomicBool>,
+  #[builder(default)]
+  token: Option<CancellationToken>,
+  #[builder(default)]
+  redirect_policy: RedirectPolicy,
+  #[builder(default)]
+  pacer: Arc<HostPacer>,
+  #[builder(default)]
+  auth: Option<Auth>,
+  #[builder(default)]
+  request_signer: Option<Arc<dyn RequestSigner>>,
+  #[builder(default = true)]
+  restart_on_remote_change: bool,
+  #[cfg_attr(not(any(feature = "md5", feature = "sha2")), allow(dead_code))]
+  #[builder(default = true)]
+  verify_server_checksums: bool,
+  /// How many attempts the retry policy allows, for progress reports.
+  #[builder(default)]
+  max_attempts: Option<u32>,
+  /// Applied to the name of an item targeting a directory once the response names it.
+  #[builder(default)]
+  overwrite: OverwritePolicy,
+  #[builder(default)]
+  filename_policy: FilenamePolicy,
+  #[cfg_attr(not(feature = "sha2"), allow(dead_code))]
+  #[builder(default)]
+  pin_store: Option<Arc<PinStore>>,
+  #[builder(default)]
+  max_size: Option<u64>,
+  #[builder(default)]
+  completion_marker: Option<String>,
+  #[builder(default = Arc::new(SystemClock))]
+  clock: Arc<dyn Clock>,
+
+  #[builder(default, setter(skip))]
+  attempts: Mutex<Vec<AttemptMetrics>>,
 }
 
-impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U, P, TP> {
-  async fn send(&self, downloaded_size: u64) -> Result<reqwest::Response, ProgressDownloadError> {
-    let request = self
-      .client
-      .get(self.item.url.as_str())
-      .header("Range", format!("bytes={}-", downloaded_size))
-      .timeout(self.timeout);
+impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<'_, U, P, TP> {
+  async fn send(
+    &self,
+    downloaded_size: u64,
+    if_range: Option<&str>,
+  ) -> Result<reqwest::Response, ProgressDownloadError> {
+    self
+      .execute(Method::GET, Some(downloaded_size), if_range)
+      .await
+  }
+
+  /// Sends a request for the item, following redirects hop by hop per the redirect policy.
+  /// With `if_range`, the server sends the whole file instead of the range if it changed.
+  async fn execute(
+    &self,
+    method: Method,
+    range_start: Option<u64>,
+    if_range: Option<&str>,
+  ) -> Result<reqwest::Response, ProgressDownloadError> {
+    let mut url = self.url.clone().into_url()?;
+    let origin = url.origin();
+    let mut crossed_origin = false;
+    let mut hops = 0;
+    let mut meta_refreshes = 0;
+
+    loop {
+      let mut headers = if crossed_origin {
+        self
+          .redirect_policy
+          .cross_origin_headers(&self.item.headers)
+      } else {
+        self.item.headers.clone()
+      };
+      // Ranges are ours to pick: a range of the item's own would break resuming.
+      headers.remove(RANGE);
+
+      let mut request = self
+        .client
+        .request(method.clone(), url.clone())
+        .headers(headers)
+        .timeout(self.timeout);
+      if let Some(start) = range_start {
+        request = request.header(RANGE, format!("bytes={}-", start));
+      }
+      if let Some(validator) = if_range {
+        request = request.header(IF_RANGE, validator);
+      }
+      // Like signatures, credentials stay with the original origin.
+      let auth = self.item.auth.as_ref().or(self.auth.as_ref());
+      if let Some(auth) = auth.filter(|_| !crossed_origin) {
+        request = auth.apply(request);
+      }
+
+      let mut request = request.build()?;
+      // Only the original origin gets signed; see `RequestSigner`.
+      if let Some(signer) = self.request_signer.as_ref().filter(|_| !crossed_origin) {
+        signer.sign(&mut request, self.clock.system_time())?;
+      }
+
+      let host = url.host_str().unwrap_or_default().to_string();
+      self.pacer.wait(&host, self.clock.now()).await;
+      let response = self.client.execute(request).await?;
+      self
+        .pacer
+        .record(&host, response.status(), self.clock.now());
+
+      let next = match redirect::next_hop(&url, response.status(), response.headers()) {
+        Some(next) => next,
+        None
+          if meta_refreshes < self.redirect_policy.meta_refresh_hops
+            && redirect::may_be_meta_refresh(response.headers()) =>
+        {
+          match redirect::inspect_meta_refresh(&url, response).await? {
+            Inspected::Refresh(next) => {
+              meta_refreshes += 1;
+              next
+            }
+            Inspected::Body(response) => return Ok(response),
+          }
+        }
+        None => return Ok(response),
+      };
+
+      hops += 1;
+      if hops > self.redirect_policy.max_redirects {
+        return Err(ProgressDownloadError::TooManyRedirects {
+          url: self.url.as_str().to_string(),
+        });
+      }
+
+      crossed_origin |= next.origin() != origin;
+      debug!("following redirect {} -> {}", url, next);
+      url = next;
+    }
+  }
+
+  /// Asks for the item's metadata with a `HEAD` request.
+  pub async fn head(&self) -> Result<RemoteMetadata, ProgressDownloadError> {
+    let response = self
+      .execute(Method::HEAD, None, None)
+      .await?
+      .error_for_status()?;
+    Ok(RemoteMetadata::from_response(&response))
+  }
 
-    let response = request.send().await?;
+  /// Fetches the whole body as text, for small files such as checksum lists.
+  #[cfg(feature = "sha2")]
+  pub async fn text(&self) -> Result<String, ProgressDownloadError> {
+    let response = self
+      .execute(Method::GET, None, None)
+      .await?
+      .error_for_status()?;
+    Ok(response.text().await?)
+  }
+
+  /// Fetches the whole body, for small files such as signatures.
+  #[cfg(feature = "pgp")]
+  pub async fn bytes(&self) -> Result<Vec<u8>, ProgressDownloadError> {
+    let response = self
+      .execute(Method::GET, None, None)
+      .await?
+      .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+  }
+
+  /// Streams the body through a hasher made by `new_hasher` without storing any of it, and
+  /// returns the digest.
+  ///
+  /// A failed attempt leaves its hasher in `resume`, and the next attempt continues from
+  /// where it stopped if the server answers the range request for the rest.
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  pub async fn hash(
+    &self,
+    resume: &Mutex<Option<(StreamHasher, u64)>>,
+    new_hasher: fn() -> StreamHasher,
+  ) -> Result<String, ProgressDownloadError> {
+    if self.is_cancelled() {
+      return Err(ProgressDownloadError::Cancelled);
+    }
+
+    let resumed = resume.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let start = resumed.as_ref().map_or(0, |(_, hashed)| *hashed);
+    let response = self
+      .execute(Method::GET, (start > 0).then_some(start), None)
+      .await?
+      .error_for_status()?;
+    let (mut hasher, mut hashed) = resumed
+      .filter(|_| {
+        response.status() == StatusCode::PARTIAL_CONTENT && range_start(&response) == Some(start)
+      })
+      .unwrap_or_else(|| (new_hasher(), 0));
+    let expected = response.content_length().map(|length| hashed + length);
+
+    let mut tracker = DownloadTracker::builder()
+      .observer(&self.observer)
+      .downloaded_size(hashed)
+      .remaining_size(response.content_length().unwrap_or(0))
+      .max_attempts(self.max_attempts)
+      .stats(&self.stats)
+      .clock(self.clock.as_ref())
+      .build();
+    tracker.init_progress();
+
+    let stream = response.bytes_stream();
+    tokio::pin!(stream);
+    let streamed = async {
+      while let Some(chunk) = tokio::time::timeout(self.read_chunk_timeout, stream.next())
+        .await?
+        .transpose()?
+      {
+        if self.is_cancelled() {
+          return Err(ProgressDownloadError::Cancelled);
+        }
+        if let Some(bandwidth) = &self.bandwidth {
+          bandwidth.consume(chunk.len(), self.clock.now()).await;
+        }
+        hasher.update(&chunk);
+        hashed += chunk.len() as u64;
+        tracker.update_progress(chunk.len());
+      }
+      match expected {
+        Some(expected) if hashed < expected => Err(ProgressDownloadError::Truncated {
+          url: self.url.as_str().to_string(),
+          expected,
+          received: hashed,
+        }),
+        _ => Ok(()),
+      }
+    }
+    .await;
+
+    if let Err(e) = streamed {
+      *resume.lock().unwrap_or_else(|e| e.into_inner()) = Some((hasher, hashed));
+      return Err(e);
+    }
+    Ok(hasher.finish())
+  }
+
+  /// Checks with a `HEAD` request whether the existing target matches the remote file,
+  /// the way `wget -N` does: same size and not older than the remote `Last-Modified`.
+  ///
+  /// Returns `false` without touching the network when there is no target yet.
+  pub async fn is_up_to_date(&self) -> Result<bool, ProgressDownloadError> {
+    let Ok(local) = tokio::fs::metadata(&self.target).await else {
+      return Ok(false);
+    };
+    if !local.is_file() {
+      return Ok(false);
+    }
+
+    let remote = self.head().await?;
+    if remote.size.is_some_and(|size| size != local.len()) {
+      return Ok(false);
+    }
+
+    let up_to_date = match (remote.last_modified, local.modified()) {
+      (Some(remote), Ok(local)) => remote <= local,
+      // Without a date to compare, do not assume the file is current.
+      _ => false,
+    };
+
+    Ok(up_to_date)
+  }
+
+  /// Checks whether the existing target already hashes to the item's integrity, in which
+  /// case there is nothing to fetch.
+  ///
+  /// Returns `false` without reading anything when there is no target yet, and for items
+  /// that are content-addressed or have extra targets, which still need placing.
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  pub async fn is_already_valid(
+    &self,
+    cache: Option<&DigestCache>,
+  ) -> Result<bool, ProgressDownloadError> {
+    let Some(integrity) = &self.item.integrity else {
+      return Ok(false);
+    };
+    if self.item.content_address.is_some() || !self.item.extra_targets.is_empty() {
+      return Ok(false);
+    }
+    let target = self.target.as_path();
+    if !tokio::fs::metadata(target)
+      .await
+      .is_ok_and(|metadata| metadata.is_file())
+    {
+      return Ok(false);
+    }
+
+    let actual = match cache {
+      Some(cache) => cache.digest(target, integrity).await?,
+      None => {
+        Hashery::builder()
+          .algorithm(integrity.algorithm())
+          .build()
+          .digest(target)
+          .await?
+      }
+    };
 
-    Ok(response)
+    Ok(actual.eq_ignore_ascii_case(integrity.value()))
   }
 
-  pub async fn download(&self) -> Result<(), ProgressDownloadError> {
+  /// Adopts `existing`, a partial download of the item left by another tool, as the temp
+  /// file by moving it there. Only done if it holds more than the temp file and its last
+  /// `check` bytes match the remote file at the same offset. Returns whether it was adopted.
+  pub async fn adopt_partial(
+    &self,
+    existing: &Path,
+    check: u64,
+  ) -> Result<bool, ProgressDownloadError> {
     let temp_file = self.tmp_file.as_ref();
-    let downloaded_size = temp_file.metadata().map(|item| item.len()).unwrap_or(0);
+    let Ok(metadata) = tokio::fs::metadata(existing).await else {
+      return Ok(false);
+    };
+    let current = tokio::fs::metadata(temp_file)
+      .await
+      .map_or(0, |metadata| metadata.len());
+    // Transformed downloads always start over.
+    if !metadata.is_file() || metadata.len() <= current || !self.item.transforms.is_empty() {
+      return Ok(false);
+    }
+    if !self.continues(existing, check).await? {
+      warn!(
+        "{} does not match {}, not resuming from it",
+        existing.display(),
+        self.url.as_str()
+      );
+      return Ok(false);
+    }
+
+    if let Some(parent) = temp_file.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    if tokio::fs::rename(existing, temp_file).await.is_err() {
+      // E.g. across filesystems.
+      tokio::fs::copy(existing, temp_file).await?;
+      tokio::fs::remove_file(existing).await?;
+    }
+    // Nothing tells which version of the remote file the adopted bytes belong to.
+    let _ = tokio::fs::remove_file(staging::validator_file(temp_file)).await;
+    info!(
+      "resuming {} from the {} bytes of {}",
+      self.url.as_str(),
+      metadata.len(),
+      existing.display()
+    );
+    Ok(true)
+  }
+
+  /// Whether the last `tail` bytes of `partial` match the remote file at the same offset.
+  async fn continues(&self, partial: &Path, tail: u64) -> Result<bool, ProgressDownloadError> {
+    let size = tokio::fs::metadata(partial).await?.len();
+    let start = size.saturating_sub(tail);
+    if start == size {
+      return Ok(true);
+    }
+    Ok(
+      self
+        .matches_remote(partial, start..size)
+        .await?
+        .unwrap_or(false),
+    )
+  }
+
+  /// Compares each of the `ranges` of `local` with the same range of the remote file,
+  /// stopping at the first that differs. Fails if the server ignores ranges, since nothing
+  /// can be compared then.
+  pub async fn spot_check(
+    &self,
+    local: &Path,
+    ranges: &[Range<u64>],
+  ) -> Result<bool, ProgressDownloadError> {
+    for range in ranges {
+      if self.is_cancelled() {
+        return Err(ProgressDownloadError::Cancelled);
+      }
+      match self.matches_remote(local, range.clone()).await? {
+        Some(true) => {}
+        Some(false) => {
+          debug!(
+            "{} differs from {} in {range:?}",
+            local.display(),
+            self.url.as_str()
+          );
+          return Ok(false);
+        }
+        None => {
+          return Err(ProgressDownloadError::RangesNotSupported {
+            url: self.url.as_str().to_string(),
+          });
+        }
+      }
+    }
+    Ok(true)
+  }
+
+  /// Whether `range` of `local` matches the remote file, or `None` if the server does not
+  /// answer with that range. A range beyond the end of the remote file does not match.
+  async fn matches_remote(
+    &self,
+    local: &Path,
+    range: Range<u64>,
+  ) -> Result<Option<bool>, ProgressDownloadError> {
+    let response = self.execute(Method::GET, Some(range.start), None).await?;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+      return Ok(Some(false));
+    }
+    let response = response.error_for_status()?;
+    if response.status() != StatusCode::PARTIAL_CONTENT
+      || range_start(&response) != Some(range.start)
+    {
+      return Ok(None);
+    }
+
+    let mut expected = vec![0; (range.end - range.start) as usize];
+    let mut file = tokio::fs::File::open(local).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+    file.read_exact(&mut expected).await?;
+
+    let mut remote = Vec::with_capacity(expected.len());
+    let stream = response.bytes_stream();
+    tokio::pin!(stream);
+    while remote.len() < expected.len() {
+      let Some(chunk) = tokio::time::timeout(self.read_chunk_timeout, stream.next())
+        .await?
+        .transpose()?
+      else {
+        break;
+      };
+      remote.extend_from_slice(&chunk);
+    }
+    // The rest of the body is not needed: dropping the response closes the connection.
+    Ok(Some(remote.get(..expected.len()) == Some(&expected[..])))
+  }
+
+  fn attempt_count(&self) -> u32 {
+    self
+      .attempts
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .len() as u32
+  }
+
+  /// Takes the metrics of every attempt made so far.
+  pub fn take_attempts(&self) -> Vec<AttemptMetrics> {
+    std::mem::take(&mut *self.attempts.lock().unwrap_or_else(|e| e.into_inner()))
+  }
+
+  /// Whether the item was cancelled by tag or through the call's token.
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+      || self
+        .token
+        .as_ref()
+        .is_some_and(CancellationToken::is_cancelled)
+  }
+
+  pub fn url(&self) -> &str {
+    self.url.as_str()
+  }
 
-    let response = self.send(downloaded_size).await?;
-    let supports_resume = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
-    let remaining_size = response.content_length().unwrap_or(0);
+  /// Runs one attempt and returns where the file was placed.
+  pub async fn download(&self) -> Result<PathBuf, ProgressDownloadError> {
+    let started = self.clock.now();
+    let mut metrics = AttemptMetrics {
+      attempt: self.attempt_count() + 1,
+      url: self.url.as_str().to_string(),
+      ..Default::default()
+    };
+
+    let result = self.attempt(started, &mut metrics).await.map_err(|error| {
+      let offset = metrics.resumed_from + metrics.bytes;
+      error.in_attempt(self.url.as_str(), metrics.state, offset)
+    });
+
+    metrics.elapsed = self.clock.elapsed(started);
+    metrics.error = result.as_ref().err().map(ToString::to_string);
+    debug!("attempt metrics for {}: {:?}", self.url.as_str(), metrics);
+
+    self
+      .attempts
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .push(metrics);
+
+    result
+  }
+
+  async fn attempt(
+    &self,
+    started: Instant,
+    metrics: &mut AttemptMetrics,
+  ) -> Result<PathBuf, ProgressDownloadError> {
+    // Cancellation may arrive while waiting between retries.
+    if self.is_cancelled() {
+      return Err(ProgressDownloadError::Cancelled);
+    }
+    self.enter(metrics, TaskState::Connecting);
+
+    let temp_file = self.tmp_file.as_ref();
+    // Transformed bytes on disk cannot be resumed from an offset in the remote file.
+    let mut downloaded_size = if self.item.transforms.is_empty() {
+      temp_file.metadata().map(|item| item.len()).unwrap_or(0)
+    } else {
+      0
+    };
+    metrics.resumed_from = downloaded_size;
+
+    let reservation = match &self.memory {
+      Some(memory) => Some(memory.reserve(WRITE_BUFFER_CAPACITY).await?),
+      None => None,
+    };
+    let buffer_capacity = reservation
+      .as_ref()
+      .map_or(WRITE_BUFFER_CAPACITY, |reservation| reservation.size);
+    let flush_threshold = self.flush_threshold.min(buffer_capacity);
+
+    // Partials without a validator, e.g. from older versions, are resumed blindly.
+    let validator_file = staging::validator_file(temp_file);
+    let validator = if downloaded_size > 0 {
+      tokio::fs::read_to_string(&validator_file).await.ok()
+    } else {
+      None
+    };
+
+    let mut response = self.send(downloaded_size, validator.as_deref()).await?;
+    if downloaded_size > 0 && remote_shrank(&response, downloaded_size) {
+      let remote_size = remote_size(&response);
+      if !self.restart_on_remote_change {
+        return Err(ProgressDownloadError::RemoteFileChanged {
+          url: self.url.as_str().to_string(),
+          local_size: downloaded_size,
+          remote_size,
+        });
+      }
+
+      warn!(
+        "{} is smaller than the {} bytes already downloaded (remote size {:?}); restarting",
+        self.url.as_str(),
+        downloaded_size,
+        remote_size
+      );
+      metrics.discarded_partial = Some(downloaded_size);
+      tokio::fs::remove_file(temp_file).await?;
+      downloaded_size = 0;
+      response = self.send(downloaded_size, None).await?;
+    } else if downloaded_size > 0
+      && response.status() == StatusCode::PARTIAL_CONTENT
+      && range_start(&response) != Some(downloaded_size)
+    {
+      // Appending a range that does not start where the partial ends would corrupt the file
+      // in a way only the final verification notices, if there is one.
+      warn!(
+        "{} sent a range starting at {:?} instead of byte {}; restarting",
+        self.url.as_str(),
+        range_start(&response),
+        downloaded_size
+      );
+      metrics.discarded_partial = Some(downloaded_size);
+      tokio::fs::remove_file(temp_file).await?;
+      downloaded_size = 0;
+      response = self.send(downloaded_size, None).await?;
+    }
+    let status = response.status();
+    let wait = matches!(
+      status,
+      StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    )
+    .then(|| retry_after(response.headers(), self.clock.system_time()))
+    .flatten();
+    if let Some(wait) = wait {
+      return Err(ProgressDownloadError::RetryAfter {
+        url: self.url.as_str().to_string(),
+        status: status.as_u16(),
+        wait,
+      });
+    }
+    // A range starting at the remote file's end: the partial already holds all of it, and
+    // the 416's body is an error page, not bytes of the file.
+    let complete = downloaded_size > 0
+      && status == StatusCode::RANGE_NOT_SATISFIABLE
+      && remote_size(&response) == Some(downloaded_size);
+    // Error statuses (429s included) fail the attempt instead of landing in the file.
+    let response = if complete {
+      debug!("{} is already complete, verifying it", self.url.as_str());
+      response
+    } else {
+      response.error_for_status()?
+    };
+    metrics.time_to_headers = Some(self.clock.elapsed(started));
+    let supports_resume = complete || response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    // A full response to a range request: the server ignores ranges, or the file changed
+    // and `If-Range` asked for all of it. Either way it starts over from the first byte.
+    if downloaded_size > 0 && !supports_resume {
+      debug!(
+        "{} answered {} to a range request, discarding {} bytes",
+        self.url.as_str(),
+        response.status(),
+        downloaded_size
+      );
+      metrics.discarded_partial = Some(downloaded_size);
+      downloaded_size = 0;
+    }
+    metrics.resumed_from = downloaded_size;
+    if let Some((expected, announced)) = self
+      .item
+      .expected_size
+      .zip(remote_size(&response))
+      .filter(|(expected, announced)| expected != announced)
+    {
+      return Err(ProgressDownloadError::SizeMismatch {
+        url: self.url.as_str().to_string(),
+        expected,
+        actual: announced,
+      });
+    }
+    // The headers of a complete partial's 416 describe no content.
+    for assertion in self.item.assertions.iter().filter(|_| !complete) {
+      if let Err(violation) = assertion.check(response.headers()) {
+        return Err(ProgressDownloadError::ResponseAssertion {
+          url: self.url.as_str().to_string(),
+          violation,
+        });
+      }
+    }
+    if let Some(max_size) = self.max_size.filter(|max_size| {
+      self.item.transforms.is_empty() && remote_size(&response).is_some_and(|size| size > *max_size)
+    }) {
+      return Err(ProgressDownloadError::TooLarge {
+        url: self.url.as_str().to_string(),
+        max_size,
+      });
+    }
+    // What is left to download: the whole file after a restart, the rest after a resume.
+    let content_length = response.content_length().filter(|_| !complete);
+    let remaining_size = content_length.unwrap_or(0);
+    let etag = response
+      .headers()
+      .get(reqwest::header::ETAG)
+      .and_then(|v| v.to_str().ok())
+      .map(ToString::to_string);
+    let disposition = response
+      .headers()
+      .get(CONTENT_DISPOSITION)
+      .and_then(|v| v.to_str().ok())
+      .map(ToString::to_string);
+    // Only the response names the file of an item targeting a directory, so this is the
+    // first point its overwrite policy can be applied, before any byte is written.
+    let named_target = match &self.item.content_address {
+      None if self.item.target_is_dir => Some(self.named_target(disposition.as_deref()).await?),
+      _ => None,
+    };
+
+    // The built client never decodes bodies, so a `.tar.gz` served with `Content-Encoding:
+    // gzip` is stored byte for byte as sent. Flag it anyway: it is the usual suspect for a
+    // hash mismatch.
+    if let Some(encoding) = response
+      .headers()
+      .get(CONTENT_ENCODING)
+      .filter(|encoding| *encoding != "identity")
+    {
+      warn!(
+        "{} was sent with Content-Encoding {:?}; storing the bytes as sent, undecoded",
+        self.url.as_str(),
+        encoding
+      );
+    }
 
     let should_resume = supports_resume && downloaded_size > 0;
 
+    // Checksum headers describe the whole file, so only a full response is checked
+    // against them.
+    #[cfg(any(feature = "md5", feature = "sha2"))]
+    let announced = (self.verify_server_checksums
+      && self.item.transforms.is_empty()
+      && response.status() == StatusCode::OK)
+      .then(|| integrity::from_server_headers(response.headers()))
+      .flatten();
+
+    #[cfg(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    ))]
+    let expected = self
+      .item
+      .integrity
+      .clone()
+      .map(|integrity| (integrity, crate::report::VerificationSource::Item));
+    #[cfg(any(feature = "md5", feature = "sha2"))]
+    let expected = expected.or(announced);
+
+    // The body is hashed as it is written. A file that holds bytes from an earlier attempt
+    // is hashed from disk once it is complete instead.
+    #[cfg(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    ))]
+    let mut hashers = Hashers::default();
+    #[cfg(any(
+      feature = "md5",
+      feature = "sha1",
+      feature = "sha2",
+      feature = "sha3",
+      feature = "blake2",
+      feature = "blake3"
+    ))]
+    if !should_resume {
+      hashers.integrity = expected
+        .as_ref()
+        .map(|(integrity, _)| StreamHasher::new(integrity));
+      #[cfg(feature = "sha2")]
+      if self.pin_store.is_some() && self.item.integrity.is_none() {
+        hashers.pin = Some(StreamHasher::sha256());
+      }
+    }
+
+    let mut custom_digest = self
+      .item
+      .verifier
+      .as_ref()
+      .filter(|_| !should_resume)
+      .map(|verifier| verifier.digest());
+
+    if let Some(parent) = temp_file.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
     let file = tokio::fs::OpenOptions::new()
       .write(true)
       .create(true)
@@ -70,32 +869,180 @@ impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U,
       .open(temp_file)
       .await?;
 
+    // Recorded before any of the body is written, so every partial file can be resumed
+    // against the version of the remote file it holds the beginning of.
+    if !should_resume {
+      if validator.is_some() {
+        info!(
+          "{} changed since the partial download, restarting",
+          self.url.as_str()
+        );
+      }
+      match resume_validator(response.headers()) {
+        Some(validator) => tokio::fs::write(&validator_file, validator).await?,
+        // A stale validator only costs a restart, never a corrupted file.
+        None => {
+          let _ = tokio::fs::remove_file(&validator_file).await;
+        }
+      }
+    }
+
     let mut delegate = DownloadTracker::builder()
-      .progress_bar(&self.progress_bar)
+      .observer(&self.observer)
       .downloaded_size(downloaded_size)
       .remaining_size(remaining_size)
-      .url(self.item.url.clone())
+      .attempt(metrics.attempt)
+      .max_attempts(self.max_attempts)
+      .stats(&self.stats)
+      .clock(self.clock.as_ref())
       .build();
 
+    self.enter(metrics, TaskState::Streaming);
     delegate.init_progress();
 
-    let mut writer = tokio::io::BufWriter::with_capacity(1024 * 1024, file);
+    let mut writer = tokio::io::BufWriter::with_capacity(buffer_capacity, file);
 
-    let stream = response.bytes_stream();
+    // The reader hands chunks to the writer through a bounded channel: when the disk is
+    // slower than the network the reader stops polling, so TCP backpressure applies
+    // instead of chunks piling up in memory.
+    let (sender, receiver) = tokio::sync::mpsc::channel(self.buffer_chunks.max(1));
+    let occupancy = AtomicUsize::new(0);
+    let peak_occupancy = AtomicUsize::new(0);
+    let first_byte = OnceLock::new();
+    let mut transforms = TransformChain::new(&self.item.transforms);
+    // Bytes written to the file by this attempt, checked against the maximum size before
+    // they are written.
+    let mut written = 0u64;
+    let check_size = |written: u64| match self.max_size {
+      Some(max_size) if downloaded_size + written > max_size => {
+        Err(ProgressDownloadError::TooLarge {
+          url: self.url.as_str().to_string(),
+          max_size,
+        })
+      }
+      _ => Ok(()),
+    };
 
-    tokio::pin!(stream);
+    let read = async {
+      let sender = sender;
+      if complete {
+        return Ok(());
+      }
+      let stream = response.bytes_stream();
+      tokio::pin!(stream);
 
-    while let Some(chunk) = tokio::time::timeout(self.read_chunk_timeout, stream.next())
-      .await?
-      .transpose()?
-    {
-      delegate.update_progress(chunk.len());
+      while let Some(chunk) = tokio::time::timeout(self.read_chunk_timeout, stream.next())
+        .await?
+        .transpose()?
+      {
+        first_byte.get_or_init(|| self.clock.elapsed(started));
+        // Waiting here is not reading, so it does not count against the chunk timeout.
+        if let Some(bandwidth) = &self.item_bandwidth {
+          bandwidth.consume(chunk.len(), self.clock.now()).await;
+        }
+        if let Some(bandwidth) = &self.bandwidth {
+          bandwidth.consume(chunk.len(), self.clock.now()).await;
+        }
+        let occupied = occupancy.fetch_add(1, Ordering::Relaxed) + 1;
+        peak_occupancy.fetch_max(occupied, Ordering::Relaxed);
+        self.stats.buffer_push();
+
+        if sender.send(chunk).await.is_err() {
+          // The writer stopped; its error is the one reported.
+          occupancy.fetch_sub(1, Ordering::Relaxed);
+          self.stats.buffer_pop();
+          break;
+        }
+      }
+
+      Ok::<_, ProgressDownloadError>(())
+    };
+
+    let write = async {
+      // Owning the receiver means a failing writer also stops the reader.
+      let mut receiver = receiver;
+      while let Some(chunk) = receiver.recv().await {
+        occupancy.fetch_sub(1, Ordering::Relaxed);
+        self.stats.buffer_pop();
+
+        if self.is_cancelled() {
+          // Keep what we have so the item can be resumed later.
+          writer.flush().await?;
+          return Err(ProgressDownloadError::Cancelled);
+        }
 
-      writer.write_all(&chunk).await?;
+        metrics.bytes += chunk.len() as u64;
+        delegate.update_progress(chunk.len());
 
-      // 减少刷新频率，提高性能
-      if writer.buffer().len() >= self.flush_threshold {
-        writer.flush().await?;
+        let transformed;
+        let bytes = if transforms.is_empty() {
+          &chunk[..]
+        } else {
+          transformed = transforms.apply(&chunk)?;
+          &transformed[..]
+        };
+        written += bytes.len() as u64;
+        check_size(written)?;
+        writer.write_all(bytes).await?;
+        #[cfg(any(
+          feature = "md5",
+          feature = "sha1",
+          feature = "sha2",
+          feature = "sha3",
+          feature = "blake2",
+          feature = "blake3"
+        ))]
+        hashers.update(bytes);
+        if let Some(digest) = &mut custom_digest {
+          digest.update(bytes);
+        }
+
+        // 减少刷新频率，提高性能
+        if writer.buffer().len() >= flush_threshold {
+          writer.flush().await?;
+        }
+      }
+
+      Ok::<_, ProgressDownloadError>(())
+    };
+
+    let (read, write) = tokio::join!(read, write);
+
+    // Chunks dropped along with a failed writer no longer count as buffered.
+    self.stats.buffer_pop_n(occupancy.into_inner());
+
+    metrics.time_to_first_byte = first_byte.get().copied();
+    metrics.peak_buffered_chunks = peak_occupancy.into_inner();
+    match write {
+      Err(error @ ProgressDownloadError::TooLarge { .. }) => {
+        // Nothing of it is worth resuming.
+        drop(writer);
+        tokio::fs::remove_file(temp_file).await?;
+        return Err(error);
+      }
+      write => write?,
+    }
+    read?;
+
+    if !transforms.is_empty() {
+      let rest = transforms.finish()?;
+      if let Err(error) = check_size(written + rest.len() as u64) {
+        drop(writer);
+        tokio::fs::remove_file(temp_file).await?;
+        return Err(error);
+      }
+      writer.write_all(&rest).await?;
+      #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha2",
+        feature = "sha3",
+        feature = "blake2",
+        feature = "blake3"
+      ))]
+      hashers.update(&rest);
+      if let Some(digest) = &mut custom_digest {
+        digest.update(&rest);
       }
     }
 
@@ -103,8 +1050,35 @@ impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U,
     writer.flush().await?;
 
     writer.into_inner().sync_all().await?;
+    self.enter(metrics, TaskState::Verifying);
+
+    // A body that ends early without an error, e.g. on a connection closed by a proxy in
+    // a response without a length, must not be placed as if it were complete.
+    let received = downloaded_size + metrics.bytes;
+    let expected_size = self
+      .item
+      .expected_size
+      .or(content_length.map(|length| downloaded_size + length));
+    if let Some(expected) = expected_size {
+      if received < expected {
+        // What did arrive is kept, so the retry resumes from it.
+        return Err(ProgressDownloadError::Truncated {
+          url: self.url.as_str().to_string(),
+          expected,
+          received,
+        });
+      }
+      if received > expected {
+        tokio::fs::remove_file(temp_file).await?;
+        return Err(ProgressDownloadError::SizeMismatch {
+          url: self.url.as_str().to_string(),
+          expected,
+          actual: received,
+        });
+      }
+    }
 
-    let target = self.item.target.as_ref();
+    let target = self.target.as_path();
 
     #[cfg(any(
       feature = "md5",
@@ -114,17 +1088,30 @@ impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U,
       feature = "blake2",
       feature = "blake3"
     ))]
-    if let Some(integrity) = &self.item.integrity {
-      let actual = Hashery::builder()
-        .algorithm(integrity.algorithm())
-        .build()
-        .digest(temp_file)
-        .await?;
+    if let Some((integrity, source)) = expected {
+      let actual = match hashers.integrity.take() {
+        Some(hasher) => hasher.finish(),
+        None => {
+          Hashery::builder()
+            .algorithm(integrity.algorithm())
+            .build()
+            .digest(temp_file)
+            .await?
+        }
+      };
 
       let expect = integrity.value().to_string();
 
       if actual != expect {
         tokio::fs::remove_file(temp_file).await?;
+        if source != crate::report::VerificationSource::Item {
+          return Err(ProgressDownloadError::ChecksumHeaderMismatch {
+            url: self.url.as_str().to_string(),
+            header: source,
+            expect,
+            actual,
+          });
+        }
         return Err(ProgressDownloadError::IntegrityHash {
           expect,
           actual,
@@ -132,25 +1119,357 @@ impl<U: IntoUrl + Clone, P: AsRef<Path>, TP: AsRef<Path>> DownloadTaskRunner<U,
           target_file: target.to_path_buf(),
         });
       }
+      debug!("{} verified against {:?}", self.url.as_str(), source);
+      metrics.verified_by = Some(source);
     }
 
-    // 确保目标文件的父目录存在
-    if let Some(parent) = target.parent() {
-      tokio::fs::create_dir_all(parent).await?;
+    if let Some(verifier) = &self.item.verifier {
+      let actual = match custom_digest.take() {
+        Some(digest) => digest.finish(),
+        None => verifier::digest_file(temp_file, verifier.digest()).await?,
+      };
+      if !actual.eq_ignore_ascii_case(verifier.expected()) {
+        tokio::fs::remove_file(temp_file).await?;
+        return Err(ProgressDownloadError::IntegrityHash {
+          expect: verifier.expected().cow_to_ascii_lowercase().into_owned(),
+          actual,
+          actual_file: temp_file.to_path_buf(),
+          target_file: target.to_path_buf(),
+        });
+      }
+      debug!(
+        "{} verified with {}",
+        self.url.as_str(),
+        verifier.algorithm_name()
+      );
+      metrics.verified_by = Some(crate::report::VerificationSource::Item);
     }
 
-    if let Err(e) = tokio::fs::rename(&self.tmp_file, &target).await {
-      if e.kind() == ErrorKind::CrossesDevices {
-        // 跨设备重命名失败，尝试复制
-        tokio::fs::copy(&self.tmp_file, target).await?;
-        tokio::fs::remove_file(&self.tmp_file).await?;
-      } else {
-        return Err(e.into());
+    #[cfg(feature = "pgp")]
+    if let Some(signature) = &self.item.signature {
+      if let Err(error) = signature
+        .verify(self.url.as_str(), temp_file.to_path_buf())
+        .await
+      {
+        tokio::fs::remove_file(temp_file).await?;
+        return Err(error);
       }
+      debug!("{} matches its OpenPGP signature", self.url.as_str());
     }
 
-    debug!("😆 Download Success: {}", target.display());
+    // Trust on first use, for items that have no integrity of their own.
+    #[cfg(feature = "sha2")]
+    if let Some(pins) = self
+      .pin_store
+      .as_ref()
+      .filter(|_| self.item.integrity.is_none())
+    {
+      let url = self.item.url.as_str();
+      let actual = match hashers.pin.take() {
+        Some(hasher) => hasher.finish(),
+        None => {
+          Hashery::builder()
+            .algorithm(hashery::Algorithm::SHA256)
+            .build()
+            .digest(temp_file)
+            .await?
+        }
+      };
+
+      match pins.get(url) {
+        Some(pinned) if pinned != actual => {
+          tokio::fs::remove_file(temp_file).await?;
+          return Err(ProgressDownloadError::PinMismatch {
+            url: url.to_string(),
+            pinned,
+            actual,
+          });
+        }
+        Some(_) => {
+          debug!("{} matches its pin", url);
+          metrics
+            .verified_by
+            .get_or_insert(crate::report::VerificationSource::Pin);
+        }
+        None => {
+          info!("pinning {} to sha256 {}", url, actual);
+          pins.pin(url, &actual);
+        }
+      }
+    }
+
+    // Nothing has been written at the target up to here.
+    self.enter(metrics, TaskState::Placing);
+    let target = match &self.item.content_address {
+      Some(address) => {
+        address
+          .resolve(
+            target,
+            temp_file,
+            self.verified_sha256(),
+            etag.as_deref(),
+            self.url.as_str(),
+          )
+          .await?
+      }
+      None => named_target.unwrap_or_else(|| target.to_path_buf()),
+    };
+
+    // Extra targets are staged next to the verified file first, so every storage backend
+    // places them like the main target.
+    for (index, extra) in self.item.extra_targets.iter().enumerate() {
+      let mut staged = temp_file.as_os_str().to_owned();
+      staged.push(format!(".fanout{index}"));
+      let staged = PathBuf::from(staged);
+
+      let linked = self.item.fan_out == FanOut::HardLink
+        && tokio::fs::hard_link(temp_file, &staged).await.is_ok();
+      if !linked {
+        tokio::fs::copy(temp_file, &staged).await?;
+      }
+      self.storage.place(&staged, extra).await?;
+    }
+
+    #[cfg(feature = "sha2")]
+    let linked = match &self.item.link_store {
+      Some(store) => {
+        naming::link_into_store(store, temp_file, self.verified_sha256(), &target).await?;
+        true
+      }
+      None => false,
+    };
+    #[cfg(not(feature = "sha2"))]
+    let linked = false;
 
+    if !linked {
+      self.storage.place(temp_file, &target).await?;
+    }
+    if let Some(suffix) = &self.completion_marker {
+      self.mark_complete(&target, suffix).await?;
+    }
+    let _ = tokio::fs::remove_file(&validator_file).await;
+    self.enter(metrics, TaskState::Done);
+
+    info!("download complete: {}", target.display());
+
+    Ok(target)
+  }
+
+  /// Where the file of an item targeting a directory goes: the name the response gives
+  /// it, unless the overwrite policy renames, skips or refuses it.
+  async fn named_target(
+    &self,
+    disposition: Option<&str>,
+  ) -> Result<PathBuf, ProgressDownloadError> {
+    let named = self.target.join(self.file_name(disposition)?);
+    self
+      .overwrite
+      .resolve(&named)
+      .await?
+      .ok_or_else(|| ProgressDownloadError::TargetSkipped {
+        path: named.to_string_lossy().to_string(),
+      })
+  }
+
+  /// Places the completion marker of the just placed `target`, staged next to the temp file.
+  async fn mark_complete(&self, target: &Path, suffix: &str) -> Result<(), ProgressDownloadError> {
+    let mut marker = target.as_os_str().to_owned();
+    marker.push(suffix);
+    let mut staged = self.tmp_file.as_ref().as_os_str().to_owned();
+    staged.push(".marker");
+
+    let name = target
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+    let content = self
+      .verified_sha256()
+      .map(|sha256| format!("{sha256}  {name}\n"))
+      .unwrap_or_default();
+    tokio::fs::write(&staged, content).await?;
+    self
+      .storage
+      .place(Path::new(&staged), Path::new(&marker))
+      .await?;
     Ok(())
   }
+
+  fn enter(&self, metrics: &mut AttemptMetrics, state: TaskState) {
+    metrics.state = state;
+    self.observer.state(state);
+  }
+
+  /// Names the file of an item targeting a directory.
+  fn file_name(&self, disposition: Option<&str>) -> Result<String, ProgressDownloadError> {
+    let policy = self.filename_policy;
+    disposition
+      .and_then(|disposition| policy.file_name_from_disposition(disposition))
+      .or_else(|| {
+        let url = Url::parse(self.url.as_str()).ok()?;
+        policy.file_name_from_url(&url)
+      })
+      .ok_or_else(|| ProgressDownloadError::Path {
+        path: self.target.to_string_lossy().to_string(),
+      })
+  }
+
+  /// The SHA-256 the item was just verified against, if any.
+  fn verified_sha256(&self) -> Option<&str> {
+    #[cfg(feature = "sha2")]
+    if let Some(Integrity::SHA256(value)) = &self.item.integrity {
+      return Some(value);
+    }
+    None
+  }
+}
+
+/// Whether the response to a resumed request shows that the remote file is now smaller than
+/// the `local_size` bytes already downloaded.
+fn remote_shrank(response: &reqwest::Response, local_size: u64) -> bool {
+  match remote_size(response) {
+    Some(size) => size < local_size,
+    // The partial ends beyond the remote file's end, by how much is not known.
+    None => response.status() == StatusCode::RANGE_NOT_SATISFIABLE,
+  }
+}
+
+/// The full size of the remote file, from `Content-Range` (`bytes 0-99/1234` or
+/// `bytes */1234`) or, for a full response, from `Content-Length`.
+fn remote_size(response: &reqwest::Response) -> Option<u64> {
+  match response.status() {
+    StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => response
+      .headers()
+      .get(CONTENT_RANGE)?
+      .to_str()
+      .ok()?
+      .rsplit_once('/')?
+      .1
+      .trim()
+      .parse()
+      .ok(),
+    StatusCode::OK => response.content_length(),
+    _ => None,
+  }
+}
+
+/// The first byte of a partial response, from its `Content-Range` (`bytes 100-199/1234`).
+fn range_start(response: &reqwest::Response) -> Option<u64> {
+  let range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+  let (start, _) = range.trim().strip_prefix("bytes ")?.split_once('-')?;
+  start.trim().parse().ok()
+}
+
+/// What a partial file is resumed against with `If-Range`: a strong `ETag`, or else
+/// `Last-Modified`. Weak ETags are not allowed in `If-Range`.
+fn resume_validator(headers: &HeaderMap) -> Option<String> {
+  let etag = headers
+    .get(ETAG)
+    .and_then(|v| v.to_str().ok())
+    .filter(|etag| !etag.starts_with("W/"));
+  etag
+    .or_else(|| headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok()))
+    .map(ToString::to_string)
+}
+
+/// How long a `Retry-After` header (delay in seconds or an HTTP date) asks to wait.
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+  let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+  match value.parse::<u64>() {
+    Ok(seconds) => Some(Duration::from_secs(seconds)),
+    Err(_) => {
+      let at = httpdate::parse_http_date(value).ok()?;
+      // A date in the past means right away.
+      Some(at.duration_since(now).unwrap_or_default())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn response(status: StatusCode, content_range: Option<&str>, body: usize) -> reqwest::Response {
+    let mut response = http::Response::new(vec![0u8; body]);
+    *response.status_mut() = status;
+    if let Some(content_range) = content_range {
+      response
+        .headers_mut()
+        .insert(CONTENT_RANGE, content_range.parse().unwrap());
+    }
+    reqwest::Response::from(response)
+  }
+
+  #[test]
+  fn test_retry_after() {
+    let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+    let headers = |value: &'static str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(RETRY_AFTER, value.parse().unwrap());
+      headers
+    };
+
+    assert_eq!(
+      retry_after(&headers("120"), now),
+      Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+      retry_after(&headers("Wed, 21 Oct 2015 07:29:30 GMT"), now),
+      Some(Duration::from_secs(90))
+    );
+    assert_eq!(
+      retry_after(&headers("Wed, 21 Oct 2015 07:00:00 GMT"), now),
+      Some(Duration::ZERO)
+    );
+    assert_eq!(retry_after(&headers("soon"), now), None);
+    assert_eq!(retry_after(&HeaderMap::new(), now), None);
+  }
+
+  #[test]
+  fn test_resume_validator_prefers_strong_etag() {
+    let modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+    let mut headers = HeaderMap::new();
+    headers.insert(LAST_MODIFIED, modified.parse().unwrap());
+    headers.insert(ETAG, "W/\"weak\"".parse().unwrap());
+    assert_eq!(resume_validator(&headers).as_deref(), Some(modified));
+
+    headers.insert(ETAG, "\"strong\"".parse().unwrap());
+    assert_eq!(resume_validator(&headers).as_deref(), Some("\"strong\""));
+    assert_eq!(resume_validator(&HeaderMap::new()), None);
+  }
+
+  #[test]
+  fn test_range_start() {
+    let partial = |content_range| response(StatusCode::PARTIAL_CONTENT, content_range, 0);
+    assert_eq!(
+      range_start(&partial(Some("bytes 1000-1999/2000"))),
+      Some(1000)
+    );
+    assert_eq!(range_start(&partial(Some("bytes 0-99/*"))), Some(0));
+    assert_eq!(range_start(&partial(Some("bytes */2000"))), None);
+    assert_eq!(range_start(&partial(None)), None);
+  }
+
+  #[test]
+  fn test_remote_shrank() {
+    let not_satisfiable = response(StatusCode::RANGE_NOT_SATISFIABLE, Some("bytes */500"), 0);
+    assert_eq!(remote_size(&not_satisfiable), Some(500));
+    assert!(remote_shrank(&not_satisfiable, 1000));
+    // The partial holds the whole file.
+    assert!(!remote_shrank(&not_satisfiable, 500));
+    let unknown = response(StatusCode::RANGE_NOT_SATISFIABLE, None, 0);
+    assert!(remote_shrank(&unknown, 1000));
+
+    // The server ignored the range and sent the whole, smaller file.
+    let full = response(StatusCode::OK, None, 500);
+    assert!(remote_shrank(&full, 1000));
+    assert!(!remote_shrank(&full, 200));
+
+    let partial = response(
+      StatusCode::PARTIAL_CONTENT,
+      Some("bytes 1000-1999/2000"),
+      1000,
+    );
+    assert_eq!(remote_size(&partial), Some(2000));
+    assert!(!remote_shrank(&partial, 1000));
+  }
 }
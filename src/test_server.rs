@@ -0,0 +1,139 @@
+//! A loopback HTTP/1.1 server for tests that need to control every byte a server sends:
+//! short bodies, misplaced ranges, missing lengths.
+
+use std::{sync::Arc, time::Duration};
+
+use cow_utils::CowUtils;
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpListener,
+};
+
+/// A request as the server read it.
+#[derive(Debug)]
+pub(crate) struct Request {
+  /// As sent, e.g. `GET`.
+  pub method: String,
+  pub path: String,
+  /// Where the requested range starts, if the request has a `Range` header.
+  pub range_start: Option<usize>,
+}
+
+/// What the server answers. The connection is closed after it.
+#[derive(Debug)]
+pub(crate) struct Response {
+  head: Vec<u8>,
+  body: Vec<u8>,
+  /// Sends the body in two halves with this pause between them.
+  pause: Option<Duration>,
+}
+
+impl Response {
+  /// `200 OK` with `body` and its length.
+  pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+    let body = body.into();
+    let head = format!(
+      "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+      body.len()
+    );
+    Self::new(head.into_bytes(), body)
+  }
+
+  /// `206 Partial Content` with the bytes of `file` from `start` on.
+  pub fn range(file: &[u8], start: usize) -> Self {
+    let head = format!(
+      "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes {start}-{}/{}\r\n\
+       content-length: {}\r\nconnection: close\r\n\r\n",
+      file.len() - 1,
+      file.len(),
+      file.len() - start
+    );
+    Self::new(head.into_bytes(), file[start..].to_vec())
+  }
+
+  /// `file`, from the start of the requested range if there is one.
+  pub fn file(file: &[u8], request: &Request) -> Self {
+    match request.range_start {
+      Some(start) => Self::range(file, start),
+      None => Self::ok(file),
+    }
+  }
+
+  /// Exactly these bytes, head included, for responses the other constructors do not make.
+  pub fn raw(bytes: impl Into<Vec<u8>>) -> Self {
+    Self::new(bytes.into(), Vec::new())
+  }
+
+  /// Sends the body in two halves, `pause` apart.
+  pub fn paused(self, pause: Duration) -> Self {
+    Self {
+      pause: Some(pause),
+      ..self
+    }
+  }
+
+  fn new(head: Vec<u8>, body: Vec<u8>) -> Self {
+    Self {
+      head,
+      body,
+      pause: None,
+    }
+  }
+}
+
+/// Answers every request with `respond` until the test ends, serving connections
+/// concurrently. Returns the server's base URL, e.g. `http://127.0.0.1:40123`. Responses
+/// to `HEAD` requests are sent without their body.
+pub(crate) async fn serve<F>(respond: F) -> String
+where
+  F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let base = format!("http://{}", listener.local_addr().unwrap());
+  let respond = Arc::new(respond);
+  tokio::spawn(async move {
+    loop {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let respond = respond.clone();
+      tokio::spawn(async move {
+        let mut buf = [0; 4096];
+        let n = socket.read(&mut buf).await.unwrap_or(0);
+        let request = parse(&String::from_utf8_lossy(&buf[..n]));
+        let response = respond(&request);
+
+        let _ = socket.write_all(&response.head).await;
+        if request.method == "HEAD" {
+          return;
+        }
+        match response.pause {
+          Some(pause) => {
+            let (first, second) = response.body.split_at(response.body.len() / 2);
+            let _ = socket.write_all(first).await;
+            tokio::time::sleep(pause).await;
+            let _ = socket.write_all(second).await;
+          }
+          None => {
+            let _ = socket.write_all(&response.body).await;
+          }
+        }
+      });
+    }
+  });
+  base
+}
+
+fn parse(head: &str) -> Request {
+  let mut words = head.split_whitespace();
+  let method = words.next().unwrap_or_default().to_string();
+  let path = words.next().unwrap_or_default().to_string();
+  let range_start = head
+    .cow_to_ascii_lowercase()
+    .split_once("\r\nrange: bytes=")
+    .and_then(|(_, range)| range.split_once('-'))
+    .and_then(|(start, _)| start.parse().ok());
+  Request {
+    method,
+    path,
+    range_start,
+  }
+}
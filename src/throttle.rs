@@ -0,0 +1,82 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+/// A token bucket capping the bytes per second read by all downloads sharing it.
+///
+/// The bucket holds up to one second worth of bytes, so a download starting after a quiet
+/// period may briefly burst. Readers take what they received and, when that leaves the
+/// bucket in debt, sleep until it is paid off; later readers queue behind that debt, which
+/// keeps the combined rate at the limit however many downloads run.
+#[derive(Debug)]
+pub(crate) struct BandwidthLimiter {
+  bytes_per_sec: f64,
+  bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+  /// Negative while readers are waiting for bytes they already took.
+  tokens: f64,
+  /// Unset until the first bytes are taken, so the bucket starts full on any clock.
+  refilled: Option<Instant>,
+}
+
+impl BandwidthLimiter {
+  pub fn new(bytes_per_sec: u64) -> Self {
+    let bytes_per_sec = bytes_per_sec.max(1) as f64;
+    Self {
+      bytes_per_sec,
+      bucket: Mutex::new(Bucket {
+        tokens: bytes_per_sec,
+        refilled: None,
+      }),
+    }
+  }
+
+  /// Waits until `bytes`, received at `now`, fit within the limit.
+  pub async fn consume(&self, bytes: usize, now: Instant) {
+    let wait = self.take(bytes, now);
+    if !wait.is_zero() {
+      tokio::time::sleep(wait).await;
+    }
+  }
+
+  /// Takes `bytes` from the bucket and returns how long the caller has to wait for them.
+  fn take(&self, bytes: usize, now: Instant) -> Duration {
+    let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+    let refilled = bucket.refilled.unwrap_or(now);
+    let refill = now.saturating_duration_since(refilled).as_secs_f64() * self.bytes_per_sec;
+    bucket.tokens = (bucket.tokens + refill).min(self.bytes_per_sec) - bytes as f64;
+    bucket.refilled = Some(refilled.max(now));
+
+    if bucket.tokens >= 0.0 {
+      Duration::ZERO
+    } else {
+      Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_debt_is_shared_between_readers() {
+    let limiter = BandwidthLimiter::new(1000);
+    let now = Instant::now();
+
+    // A full second worth of bytes is available up front.
+    assert_eq!(limiter.take(1000, now), Duration::ZERO);
+    assert_eq!(limiter.take(500, now), Duration::from_millis(500));
+    // A second reader waits for the first one's debt too.
+    assert_eq!(limiter.take(500, now), Duration::from_secs(1));
+    // Time pays the debt off.
+    assert_eq!(
+      limiter.take(0, now + Duration::from_secs(2)),
+      Duration::ZERO
+    );
+  }
+}
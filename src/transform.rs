@@ -0,0 +1,304 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::err::ProgressDownloadError;
+
+/// Rewrites the body of a download on its way from the network to the disk, e.g. to
+/// normalize line endings or decrypt it.
+///
+/// An item's transforms run in order, each one feeding the next, and see every body chunk
+/// of an attempt from the first byte: items with transforms never resume a partial
+/// download, since the stored bytes no longer line up with the remote ones. The item's
+/// integrity is checked against the stored, transformed file, and server checksum headers
+/// (which describe the wire bytes) are ignored.
+///
+/// Transforms are stateful, so the configured transform is used as a template and every
+/// attempt works on a [`fresh`](Self::fresh) copy of it.
+pub trait ChunkTransform: Debug + Send + Sync {
+  /// Transforms `chunk`, appending the output to `out`. A chunk may produce more, fewer or
+  /// no bytes at all, e.g. when input is held back until more arrives.
+  fn transform(&mut self, chunk: &[u8], out: &mut Vec<u8>) -> Result<(), ProgressDownloadError>;
+
+  /// Called once after the last chunk, to append whatever was held back.
+  fn finish(&mut self, out: &mut Vec<u8>) -> Result<(), ProgressDownloadError> {
+    let _ = out;
+    Ok(())
+  }
+
+  /// A copy of this transform in its initial state.
+  fn fresh(&self) -> Box<dyn ChunkTransform>;
+}
+
+/// Turns `\r\n` line endings into `\n`, leaving lone `\r` alone.
+#[derive(Debug, Clone, Default)]
+pub struct CrlfToLf {
+  // A chunk ending in `\r` is undecided until the next chunk arrives.
+  pending_cr: bool,
+}
+
+impl ChunkTransform for CrlfToLf {
+  fn transform(&mut self, chunk: &[u8], out: &mut Vec<u8>) -> Result<(), ProgressDownloadError> {
+    out.reserve(chunk.len() + 1);
+    for &byte in chunk {
+      if self.pending_cr && byte != b'\n' {
+        out.push(b'\r');
+      }
+      self.pending_cr = byte == b'\r';
+      if !self.pending_cr {
+        out.push(byte);
+      }
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self, out: &mut Vec<u8>) -> Result<(), ProgressDownloadError> {
+    if std::mem::take(&mut self.pending_cr) {
+      out.push(b'\r');
+    }
+    Ok(())
+  }
+
+  fn fresh(&self) -> Box<dyn ChunkTransform> {
+    Box::new(Self::default())
+  }
+}
+
+#[cfg(feature = "decrypt")]
+pub use decrypt::Aes256GcmStream;
+
+#[cfg(feature = "decrypt")]
+mod decrypt {
+  use aes_gcm::{
+    Aes256Gcm, Key,
+    aead::{generic_array::GenericArray, stream::DecryptorBE32},
+  };
+  use typed_builder::TypedBuilder;
+
+  use super::ChunkTransform;
+  use crate::err::ProgressDownloadError;
+
+  /// Length of the nonce prefix the body starts with.
+  const NONCE_PREFIX_LEN: usize = 7;
+  /// Length of the authentication tag sealing every segment.
+  const TAG_LEN: usize = 16;
+
+  /// Decrypts a body encrypted with AES-256-GCM in the STREAM construction, e.g. an
+  /// artifact kept encrypted at rest on a CDN. The item's integrity then applies to the
+  /// plaintext.
+  ///
+  /// The body is a 7-byte nonce prefix followed by the plaintext in segments of
+  /// `segment_size` bytes (the last one may be shorter), each sealed with its own 16-byte
+  /// tag: the layout written by `aead::stream::EncryptorBE32<Aes256Gcm>`. Every segment is
+  /// authenticated before it reaches the disk, and a truncated body fails on the last
+  /// segment; either way the item fails with [`ProgressDownloadError::Transform`].
+  ///
+  /// ```rust
+  /// use std::sync::Arc;
+  /// use robust_downloader::{Aes256GcmStream, DownloadItem};
+  ///
+  /// let key = [0u8; 32]; // from your secret store
+  /// let item = DownloadItem::builder()
+  ///   .url("https://cdn.example.com/tool.tar.enc")
+  ///   .target("tool.tar")
+  ///   .transforms(vec![Arc::new(Aes256GcmStream::builder().key(key).build())])
+  ///   .build();
+  /// ```
+  #[derive(TypedBuilder)]
+  pub struct Aes256GcmStream {
+    key: [u8; 32],
+    /// Plaintext bytes per segment. Defaults to 64 KiB.
+    #[builder(default = 64 * 1024)]
+    segment_size: usize,
+    #[builder(default, setter(skip))]
+    decryptor: Option<DecryptorBE32<Aes256Gcm>>,
+    #[builder(default, setter(skip))]
+    buffer: Vec<u8>,
+  }
+
+  impl std::fmt::Debug for Aes256GcmStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      f.debug_struct("Aes256GcmStream")
+        .field("segment_size", &self.segment_size)
+        .finish_non_exhaustive()
+    }
+  }
+
+  impl ChunkTransform for Aes256GcmStream {
+    fn transform(&mut self, chunk: &[u8], out: &mut Vec<u8>) -> Result<(), ProgressDownloadError> {
+      self.buffer.extend_from_slice(chunk);
+
+      if self.decryptor.is_none() {
+        if self.buffer.len() < NONCE_PREFIX_LEN {
+          return Ok(());
+        }
+        let prefix = self.buffer.drain(..NONCE_PREFIX_LEN).collect::<Vec<_>>();
+        self.decryptor = Some(DecryptorBE32::new(
+          Key::<Aes256Gcm>::from_slice(&self.key),
+          GenericArray::from_slice(&prefix),
+        ));
+      }
+      let decryptor = self.decryptor.as_mut().expect("created above");
+
+      // The last segment is sealed differently, so a full segment stays buffered until
+      // more bytes show it is not the last one.
+      let sealed = self.segment_size + TAG_LEN;
+      while self.buffer.len() > sealed {
+        let plaintext = decryptor
+          .decrypt_next(&self.buffer[..sealed])
+          .map_err(|_| authentication_failed())?;
+        self.buffer.drain(..sealed);
+        out.extend_from_slice(&plaintext);
+      }
+      Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> Result<(), ProgressDownloadError> {
+      let decryptor = self.decryptor.take().ok_or_else(authentication_failed)?;
+      let plaintext = decryptor
+        .decrypt_last(self.buffer.as_slice())
+        .map_err(|_| authentication_failed())?;
+      self.buffer.clear();
+      out.extend_from_slice(&plaintext);
+      Ok(())
+    }
+
+    fn fresh(&self) -> Box<dyn ChunkTransform> {
+      Box::new(
+        Self::builder()
+          .key(self.key)
+          .segment_size(self.segment_size)
+          .build(),
+      )
+    }
+  }
+
+  fn authentication_failed() -> ProgressDownloadError {
+    ProgressDownloadError::Transform {
+      message: "encrypted body is truncated or was tampered with".to_string(),
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use std::sync::Arc;
+
+    use aes_gcm::aead::stream::EncryptorBE32;
+
+    use super::*;
+    use crate::transform::TransformChain;
+
+    fn encrypt(key: &[u8; 32], plaintext: &[u8], segment_size: usize) -> Vec<u8> {
+      let prefix = [9u8; NONCE_PREFIX_LEN];
+      let mut encryptor = EncryptorBE32::<Aes256Gcm>::new(
+        Key::<Aes256Gcm>::from_slice(key),
+        GenericArray::from_slice(&prefix),
+      );
+      let segments = plaintext.chunks(segment_size).collect::<Vec<_>>();
+      let (last, rest) = segments.split_last().unwrap();
+
+      let mut body = prefix.to_vec();
+      for segment in rest {
+        body.extend(encryptor.encrypt_next(*segment).unwrap());
+      }
+      body.extend(encryptor.encrypt_last(*last).unwrap());
+      body
+    }
+
+    fn decrypt(key: [u8; 32], body: &[u8]) -> Result<Vec<u8>, ProgressDownloadError> {
+      let templates: Vec<Arc<dyn ChunkTransform>> = vec![Arc::new(
+        Aes256GcmStream::builder()
+          .key(key)
+          .segment_size(256)
+          .build(),
+      )];
+      let mut chain = TransformChain::new(&templates);
+
+      let mut plaintext = Vec::new();
+      // Network chunks never line up with segments.
+      for chunk in body.chunks(100) {
+        plaintext.extend(chain.apply(chunk)?);
+      }
+      plaintext.extend(chain.finish()?);
+      Ok(plaintext)
+    }
+
+    #[test]
+    fn test_decrypts_across_chunk_boundaries() {
+      let key = [7u8; 32];
+      let plaintext = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+      let body = encrypt(&key, &plaintext, 256);
+
+      assert_eq!(decrypt(key, &body).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn test_rejects_truncated_body() {
+      let key = [7u8; 32];
+      let body = encrypt(&key, &[1; 1000], 256);
+
+      assert!(matches!(
+        decrypt(key, &body[..body.len() - 272]),
+        Err(ProgressDownloadError::Transform { .. })
+      ));
+    }
+  }
+}
+
+/// The transforms of one attempt, chained in order.
+pub(crate) struct TransformChain {
+  stages: Vec<Box<dyn ChunkTransform>>,
+}
+
+impl TransformChain {
+  pub fn new(templates: &[Arc<dyn ChunkTransform>]) -> Self {
+    Self {
+      stages: templates.iter().map(|template| template.fresh()).collect(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.stages.is_empty()
+  }
+
+  pub fn apply(&mut self, chunk: &[u8]) -> Result<Vec<u8>, ProgressDownloadError> {
+    let mut data = chunk.to_vec();
+    for stage in &mut self.stages {
+      let mut out = Vec::with_capacity(data.len());
+      stage.transform(&data, &mut out)?;
+      data = out;
+    }
+    Ok(data)
+  }
+
+  /// Flushes every stage; what a stage holds back still passes through the later ones.
+  pub fn finish(&mut self) -> Result<Vec<u8>, ProgressDownloadError> {
+    let mut data = Vec::new();
+    for stage in &mut self.stages {
+      let mut out = Vec::with_capacity(data.len());
+      if !data.is_empty() {
+        stage.transform(&data, &mut out)?;
+      }
+      stage.finish(&mut out)?;
+      data = out;
+    }
+    Ok(data)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_crlf_split_across_chunks() {
+    let templates: Vec<Arc<dyn ChunkTransform>> = vec![Arc::new(CrlfToLf::default())];
+    let mut chain = TransformChain::new(&templates);
+
+    let mut output = Vec::new();
+    for chunk in [&b"a\r"[..], b"\nb\r", b"c\r"] {
+      output.extend(chain.apply(chunk).unwrap());
+    }
+    output.extend(chain.finish().unwrap());
+
+    assert_eq!(output, b"a\nb\rc\r");
+  }
+}
@@ -0,0 +1,495 @@
+use std::{
+  fs::File,
+  io::{BufRead, BufReader, Read},
+  path::{Component, Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+use hashery::Hashery;
+use typed_builder::TypedBuilder;
+
+use crate::err::ProgressDownloadError;
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+use crate::integrity::Integrity;
+
+/// Name of the link [`Unpack::link_current`] maintains inside `dest`.
+pub const CURRENT: &str = "current";
+
+/// Installs an item's archive into a versioned directory once it is downloaded and
+/// verified, the way toolchain managers install a release.
+///
+/// The archive is extracted into `dest/.staging-<id>`, which is then renamed to
+/// `dest/<version>` in one step, so the version directory either does not exist or is
+/// complete. With `link_current`, `dest/current` is then switched to the new version by
+/// renaming a fresh link over it, so it always points at a complete version. An existing
+/// version directory is taken as installed and left alone, which makes downloading the
+/// same version again cheap.
+///
+/// Tar archives are supported, gzip-compressed or not. The archive stays at the item's
+/// target, which must be on the local disk as it is with the default storage. The item's
+/// `integrity` is the digest of the archive as downloaded unless `integrity_of` says it is
+/// the digest of the decompressed tar, see [`IntegrityOf`].
+///
+/// ```rust,no_run
+/// use robust_downloader::{DownloadItem, RobustDownloader, Unpack};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let item = DownloadItem::builder()
+///   .url("https://nodejs.org/dist/v23.9.0/node-v23.9.0-linux-x64.tar.gz")
+///   .target("cache/node-v23.9.0-linux-x64.tar.gz")
+///   .unpack(
+///     Unpack::builder()
+///       .dest("toolchains/node")
+///       .version("23.9.0")
+///       .strip_components(1)
+///       .link_current(true)
+///       .build(),
+///   )
+///   .build();
+/// RobustDownloader::builder().build().download_one(item).await?;
+/// // toolchains/node/current/bin/node
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Unpack {
+  /// The directory holding the installed versions.
+  #[builder(setter(into))]
+  pub dest: PathBuf,
+  /// The name of this version's directory inside `dest`.
+  #[builder(setter(into))]
+  pub version: String,
+  /// Leading path components dropped from every entry, like `tar --strip-components`:
+  /// release archives usually wrap everything in one `name-version/` directory.
+  /// Defaults to 0.
+  #[builder(default)]
+  pub strip_components: usize,
+  /// Point `dest/current` at this version once it is installed. Defaults to false.
+  #[builder(default)]
+  pub link_current: bool,
+  /// Which bytes the item's `integrity` is the digest of. Defaults to
+  /// [`IntegrityOf::Archive`].
+  #[builder(default)]
+  pub integrity_of: IntegrityOf,
+
+  /// The item's integrity when it is the digest of the decompressed tar, moved here so
+  /// the download is not checked against it.
+  #[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha2",
+    feature = "sha3",
+    feature = "blake2",
+    feature = "blake3"
+  ))]
+  #[builder(default, setter(skip))]
+  pub(crate) decompressed_integrity: Option<Integrity>,
+}
+
+/// Which bytes the `integrity` of an item with [`Unpack`] is the digest of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegrityOf {
+  /// The archive as downloaded, e.g. the `.tar.gz`. It is checked while it downloads,
+  /// like any item's.
+  #[default]
+  Archive,
+  /// The tar inside a gzip-compressed archive, for releases that publish the digest of the
+  /// `.tar`. The download itself is not checked; the tar is checked before anything is
+  /// extracted, and an archive that does not match is not installed.
+  Decompressed,
+}
+
+impl Unpack {
+  /// Where this version is installed.
+  pub fn version_dir(&self) -> PathBuf {
+    self.dest.join(&self.version)
+  }
+
+  /// Installs the archive at `archive` unless the version is installed already, and
+  /// returns the version directory.
+  pub(crate) async fn install(&self, archive: &Path) -> Result<PathBuf, ProgressDownloadError> {
+    let version_dir = self.version_dir();
+    if !usable_name(&self.version) {
+      return Err(ProgressDownloadError::Path {
+        path: version_dir.to_string_lossy().to_string(),
+      });
+    }
+
+    if tokio::fs::symlink_metadata(&version_dir).await.is_err() {
+      tokio::fs::create_dir_all(&self.dest).await?;
+      let staging = self.dest.join(format!(".staging-{}", unique_id()));
+      #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha2",
+        feature = "sha3",
+        feature = "blake2",
+        feature = "blake3"
+      ))]
+      if let Some(expected) = &self.decompressed_integrity {
+        let mut tar = staging.as_os_str().to_owned();
+        tar.push(".tar");
+        check_decompressed(archive, Path::new(&tar), expected, &version_dir).await?;
+      }
+
+      let extracted = {
+        let archive = archive.to_path_buf();
+        let staging = staging.clone();
+        let strip_components = self.strip_components;
+        tokio::task::spawn_blocking(move || extract(&archive, &staging, strip_components))
+          .await
+          .unwrap_or_else(|e| {
+            Err(ProgressDownloadError::Internal {
+              message: e.to_string(),
+            })
+          })
+      };
+      let installed = match extracted {
+        Ok(()) => tokio::fs::rename(&staging, &version_dir)
+          .await
+          .map_err(ProgressDownloadError::from),
+        Err(e) => Err(e),
+      };
+      if let Err(e) = installed {
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        // Another process may have installed the same version in the meantime.
+        if tokio::fs::symlink_metadata(&version_dir).await.is_err() {
+          return Err(e);
+        }
+      }
+    }
+
+    if self.link_current {
+      switch_current(&self.dest, &self.version).await?;
+    }
+    Ok(version_dir)
+  }
+}
+
+/// Moves the integrity of an item that is the digest of its decompressed archive to its
+/// [`Unpack`], see [`IntegrityOf::Decompressed`].
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+pub(crate) fn defer_decompressed_integrity<U, P>(item: &mut crate::DownloadItem<U, P>) {
+  if let Some(unpack) = item
+    .unpack
+    .as_mut()
+    .filter(|unpack| unpack.integrity_of == IntegrityOf::Decompressed)
+  {
+    unpack.decompressed_integrity = item.integrity.take();
+  }
+}
+
+/// Checks the tar inside `archive` against `expected`. The tar is written to `tar` to be
+/// hashed and removed again.
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+async fn check_decompressed(
+  archive: &Path,
+  tar: &Path,
+  expected: &Integrity,
+  version_dir: &Path,
+) -> Result<(), ProgressDownloadError> {
+  let decompressed = {
+    let (archive, tar) = (archive.to_path_buf(), tar.to_path_buf());
+    tokio::task::spawn_blocking(move || decompress(&archive, &tar))
+      .await
+      .unwrap_or_else(|e| {
+        Err(ProgressDownloadError::Internal {
+          message: e.to_string(),
+        })
+      })
+  };
+  let actual = match decompressed {
+    Ok(()) => Hashery::builder()
+      .algorithm(expected.algorithm())
+      .build()
+      .digest(tar)
+      .await
+      .map_err(ProgressDownloadError::from),
+    Err(e) => Err(e),
+  };
+  let _ = tokio::fs::remove_file(tar).await;
+
+  let actual = actual?;
+  if !actual.eq_ignore_ascii_case(expected.value()) {
+    return Err(ProgressDownloadError::IntegrityHash {
+      expect: expected.value().to_string(),
+      actual,
+      actual_file: archive.to_path_buf(),
+      target_file: version_dir.to_path_buf(),
+    });
+  }
+  Ok(())
+}
+
+/// A version name must stay a single directory inside `dest`.
+fn usable_name(name: &str) -> bool {
+  let mut components = Path::new(name).components();
+  matches!(components.next(), Some(Component::Normal(_)))
+    && components.next().is_none()
+    && name != CURRENT
+    && !name.starts_with('.')
+}
+
+fn unique_id() -> String {
+  format!("{}-{:016x}", std::process::id(), rand::random::<u64>())
+}
+
+/// Points `dest/current` at `version`, through a relative link so that `dest` can be moved
+/// as a whole. The link is made aside and renamed over the old one.
+async fn switch_current(dest: &Path, version: &str) -> Result<(), ProgressDownloadError> {
+  let current = dest.join(CURRENT);
+  if tokio::fs::read_link(&current)
+    .await
+    .is_ok_and(|target| target == Path::new(version))
+  {
+    return Ok(());
+  }
+
+  let staged = dest.join(format!(".current-{}", unique_id()));
+  #[cfg(unix)]
+  tokio::fs::symlink(version, &staged).await?;
+  #[cfg(windows)]
+  tokio::fs::symlink_dir(version, &staged).await?;
+  if let Err(e) = tokio::fs::rename(&staged, &current).await {
+    let _ = tokio::fs::remove_file(&staged).await;
+    return Err(e.into());
+  }
+  Ok(())
+}
+
+/// Reads the tar in the archive at `archive`, gzip-compressed or not.
+fn open(archive: &Path) -> Result<Box<dyn Read>, ProgressDownloadError> {
+  let mut reader = BufReader::new(File::open(archive)?);
+  let gzipped = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+  Ok(if gzipped {
+    Box::new(GzDecoder::new(reader))
+  } else {
+    Box::new(reader)
+  })
+}
+
+/// Writes the tar in the archive at `archive` to `tar`.
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+fn decompress(archive: &Path, tar: &Path) -> Result<(), ProgressDownloadError> {
+  std::io::copy(&mut open(archive)?, &mut File::create(tar)?)?;
+  Ok(())
+}
+
+/// Extracts the tar archive at `archive`, gzip-compressed or not, into `into`.
+fn extract(
+  archive: &Path,
+  into: &Path,
+  strip_components: usize,
+) -> Result<(), ProgressDownloadError> {
+  let unsafe_entry = |entry: &Path| ProgressDownloadError::Unpack {
+    archive: archive.to_string_lossy().to_string(),
+    message: format!(
+      "entry {} would be written outside the directory",
+      entry.display()
+    ),
+  };
+
+  let reader = open(archive)?;
+  std::fs::create_dir_all(into)?;
+  let root = into.canonicalize()?;
+  // Where an entry goes, or `None` for the stripped leading directories themselves.
+  let destination = |entry: &Path| {
+    let components = entry
+      .components()
+      .filter(|component| *component != Component::CurDir)
+      .collect::<Vec<_>>();
+    if !components
+      .iter()
+      .all(|component| matches!(component, Component::Normal(_)))
+    {
+      return Err(unsafe_entry(entry));
+    }
+    Ok(
+      (components.len() > strip_components)
+        .then(|| root.join(components[strip_components..].iter().collect::<PathBuf>())),
+    )
+  };
+
+  let mut tar = tar::Archive::new(reader);
+  for entry in tar.entries()? {
+    let mut entry = entry?;
+    let path = entry.path()?.into_owned();
+    let Some(target) = destination(path.as_path())? else {
+      continue;
+    };
+
+    if let Some(parent) = target.parent() {
+      std::fs::create_dir_all(parent)?;
+      // A symlink unpacked earlier must not lead the entry out of the directory.
+      if !parent.canonicalize()?.starts_with(&root) {
+        return Err(unsafe_entry(path.as_path()));
+      }
+    }
+
+    // Hard links name their source by its path in the archive, which is stripped too.
+    if entry.header().entry_type().is_hard_link() {
+      let Some(source) = entry.link_name()? else {
+        return Err(unsafe_entry(path.as_path()));
+      };
+      let Some(source) = destination(source.as_ref())? else {
+        return Err(unsafe_entry(path.as_path()));
+      };
+      std::fs::hard_link(source, &target)?;
+      continue;
+    }
+    entry.unpack(&target)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn archive(path: &Path) {
+    let file = File::create(path).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder
+      .append_data(&mut header, "tool-1.0/bin/tool", &b"tool"[..])
+      .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_install_and_switch() {
+    let dir = std::env::temp_dir().join(format!("rd-unpack-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let tarball = dir.join("tool-1.0.tar.gz");
+    archive(&tarball);
+
+    let unpack = |version: &str| {
+      Unpack::builder()
+        .dest(dir.join("versions"))
+        .version(version)
+        .strip_components(1)
+        .link_current(true)
+        .build()
+    };
+    for version in ["1.0", "1.0", "1.0-copy"] {
+      let installed = unpack(version).install(&tarball).await.unwrap();
+      assert_eq!(installed, dir.join("versions").join(version));
+    }
+
+    let current = dir.join("versions").join(CURRENT);
+    assert_eq!(
+      tokio::fs::read(current.join("bin/tool")).await.unwrap(),
+      b"tool"
+    );
+    assert_eq!(
+      tokio::fs::read_link(&current).await.unwrap(),
+      Path::new("1.0-copy")
+    );
+    // Nothing is left behind next to the versions.
+    let mut entries = tokio::fs::read_dir(dir.join("versions")).await.unwrap();
+    let mut names = Vec::new();
+    while let Some(entry) = entries.next_entry().await.unwrap() {
+      names.push(entry.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+    assert_eq!(names, ["1.0", "1.0-copy", CURRENT]);
+
+    assert!(unpack("../escape").install(&tarball).await.is_err());
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+
+  #[cfg(feature = "sha2")]
+  #[tokio::test]
+  async fn test_decompressed_integrity_is_checked_before_extracting() {
+    let dir = std::env::temp_dir().join(format!("rd-unpack-decompressed-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let tarball = dir.join("tool-1.0.tar.gz");
+    archive(&tarball);
+    decompress(&tarball, &dir.join("tool-1.0.tar")).unwrap();
+    let sha256 = Hashery::builder()
+      .algorithm(hashery::Algorithm::SHA256)
+      .build()
+      .digest(dir.join("tool-1.0.tar"))
+      .await
+      .unwrap();
+
+    let unpack = |version: &str, sha256: &str| {
+      let mut item = crate::DownloadItem::builder()
+        .url("https://example.com/tool-1.0.tar.gz")
+        .target(tarball.clone())
+        .integrity(Integrity::SHA256(sha256.to_string()))
+        .unpack(
+          Unpack::builder()
+            .dest(dir.join("versions"))
+            .version(version)
+            .integrity_of(IntegrityOf::Decompressed)
+            .build(),
+        )
+        .build();
+      defer_decompressed_integrity(&mut item);
+      // The download is not checked against the digest of the tar.
+      assert!(item.integrity.is_none());
+      item.unpack.unwrap()
+    };
+
+    assert!(matches!(
+      unpack("1.0-bad", &"0".repeat(64)).install(&tarball).await,
+      Err(ProgressDownloadError::IntegrityHash { .. })
+    ));
+    let installed = unpack("1.0", &sha256).install(&tarball).await.unwrap();
+    assert_eq!(
+      tokio::fs::read(installed.join("tool-1.0/bin/tool"))
+        .await
+        .unwrap(),
+      b"tool"
+    );
+    // Neither the rejected version nor the tar it was checked from is left behind.
+    let mut entries = tokio::fs::read_dir(dir.join("versions")).await.unwrap();
+    let mut names = Vec::new();
+    while let Some(entry) = entries.next_entry().await.unwrap() {
+      names.push(entry.file_name().to_string_lossy().to_string());
+    }
+    assert_eq!(names, ["1.0"]);
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  }
+}
@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use tokio::io::AsyncReadExt;
+
+use crate::err::ProgressDownloadError;
+
+/// Incremental hashing of a download's bytes, made by an [`IntegrityVerifier`] for every
+/// attempt.
+pub trait Digest: Send {
+  /// Adds the next bytes of the file.
+  fn update(&mut self, bytes: &[u8]);
+
+  /// The digest of every byte added, as lowercase hex.
+  fn finish(self: Box<Self>) -> String;
+}
+
+/// A checksum an item is verified against with a hashing implementation of the caller's
+/// choice, e.g. `ring`, OpenSSL or a hardware-accelerated one, instead of the algorithms
+/// behind the hash features. Works without any of those features enabled.
+///
+/// The body is hashed as it is written, like for an item's `integrity`; a file that holds
+/// bytes from an earlier attempt is read back once it is complete. A mismatch fails the
+/// attempt with an integrity error, and the file is not placed.
+///
+/// ```rust
+/// use robust_downloader::{Digest, DownloadItem, IntegrityVerifier};
+/// use std::sync::Arc;
+///
+/// /// Adler-32, standing in for a hasher from another crate.
+/// #[derive(Debug)]
+/// struct Adler32(String);
+///
+/// struct Running(u32, u32);
+///
+/// impl Digest for Running {
+///   fn update(&mut self, bytes: &[u8]) {
+///     for byte in bytes {
+///       self.0 = (self.0 + u32::from(*byte)) % 65521;
+///       self.1 = (self.1 + self.0) % 65521;
+///     }
+///   }
+///
+///   fn finish(self: Box<Self>) -> String {
+///     format!("{:08x}", (self.1 << 16) | self.0)
+///   }
+/// }
+///
+/// impl IntegrityVerifier for Adler32 {
+///   fn algorithm_name(&self) -> &str {
+///     "adler32"
+///   }
+///
+///   fn expected(&self) -> &str {
+///     &self.0
+///   }
+///
+///   fn digest(&self) -> Box<dyn Digest> {
+///     Box::new(Running(1, 0))
+///   }
+/// }
+///
+/// let item = DownloadItem::builder()
+///   .url("https://example.com/file.bin")
+///   .target("file.bin")
+///   .verifier(Arc::new(Adler32("024d0127".to_string())))
+///   .build();
+/// ```
+pub trait IntegrityVerifier: Send + Sync + std::fmt::Debug {
+  /// A stable, lowercase name for the algorithm, e.g. `sha256`.
+  fn algorithm_name(&self) -> &str;
+
+  /// The expected digest as hex; compared case-insensitively.
+  fn expected(&self) -> &str;
+
+  /// A fresh digest for one attempt's bytes.
+  fn digest(&self) -> Box<dyn Digest>;
+}
+
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+impl Digest for crate::hasher::StreamHasher {
+  fn update(&mut self, bytes: &[u8]) {
+    crate::hasher::StreamHasher::update(self, bytes);
+  }
+
+  fn finish(self: Box<Self>) -> String {
+    crate::hasher::StreamHasher::finish(*self)
+  }
+}
+
+/// The built-in algorithms are verifiers like any other.
+#[cfg(any(
+  feature = "md5",
+  feature = "sha1",
+  feature = "sha2",
+  feature = "sha3",
+  feature = "blake2",
+  feature = "blake3"
+))]
+impl IntegrityVerifier for crate::integrity::Integrity {
+  fn algorithm_name(&self) -> &str {
+    crate::integrity::Integrity::algorithm_name(self)
+  }
+
+  fn expected(&self) -> &str {
+    self.value()
+  }
+
+  fn digest(&self) -> Box<dyn Digest> {
+    Box::new(crate::hasher::StreamHasher::new(self))
+  }
+}
+
+/// Digests the file at `path` with `digest`, for files not hashed while they were written.
+pub(crate) async fn digest_file(
+  path: &Path,
+  mut digest: Box<dyn Digest>,
+) -> Result<String, ProgressDownloadError> {
+  let mut file = tokio::fs::File::open(path).await?;
+  let mut buf = vec![0; 64 * 1024];
+  loop {
+    let n = file.read(&mut buf).await?;
+    if n == 0 {
+      return Ok(digest.finish());
+    }
+    digest.update(&buf[..n]);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Sums the bytes, which is enough to tell files apart here.
+  #[derive(Debug)]
+  struct Sum(&'static str);
+
+  struct Running(u64);
+
+  impl Digest for Running {
+    fn update(&mut self, bytes: &[u8]) {
+      self.0 += bytes.iter().map(|byte| u64::from(*byte)).sum::<u64>();
+    }
+
+    fn finish(self: Box<Self>) -> String {
+      format!("{:x}", self.0)
+    }
+  }
+
+  impl IntegrityVerifier for Sum {
+    fn algorithm_name(&self) -> &str {
+      "sum"
+    }
+
+    fn expected(&self) -> &str {
+      self.0
+    }
+
+    fn digest(&self) -> Box<dyn Digest> {
+      Box::new(Running(0))
+    }
+  }
+
+  #[tokio::test]
+  async fn test_digest_file() {
+    let path = std::env::temp_dir().join(format!("rd-verifier-{}", std::process::id()));
+    tokio::fs::write(&path, b"abc").await.unwrap();
+
+    let verifier = Sum("126");
+    let actual = digest_file(&path, verifier.digest()).await.unwrap();
+    assert_eq!(actual, verifier.expected());
+    tokio::fs::remove_file(&path).await.unwrap();
+  }
+}